@@ -95,7 +95,7 @@ async function main() {
     eventQueueCapacity: 512,
     tickSize: new BN(10_000),
     minBaseQty: new BN(100),
-    feesBps: 0,
+    takerFeeBps: 0,
     pre: 1_200_000,
   });
 
@@ -131,7 +131,6 @@ async function main() {
     priceTicks: new BN(10_000),
     baseQty: new BN(100),
     side: 1,
-    lockLamports: new BN(1000),
     maxSlippageTicks: new BN(0),
     pre: 1_000_000,
     userSigner: maker,
@@ -144,7 +143,6 @@ async function main() {
     priceTicks: new BN(10_000),
     baseQty: new BN(100),
     side: 0,
-    lockLamports: new BN(1000),
     maxSlippageTicks: new BN(0),
     pre: 1_000_000,
     userSigner: taker,
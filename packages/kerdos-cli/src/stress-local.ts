@@ -97,7 +97,6 @@ async function main() {
   const groupSize = Number(process.env.STRESS_GROUP_SIZE ?? 10);
   const maxEvents = Number(process.env.STRESS_MAX_EVENTS ?? 1);
   const settleEvery = Number(process.env.STRESS_SETTLE_EVERY ?? 1);
-  const lockLamports = new BN(Number(process.env.STRESS_LOCK_LAMPORTS ?? 100_000));
 
   const mintAuthority = (provider.wallet as any).payer as Keypair;
   const baseMint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
@@ -109,16 +108,16 @@ async function main() {
     bidsCapacity: bidsCap,
     asksCapacity: asksCap,
     eventQueueCapacity: evqCap,
-    tickSize: new BN(10_000),
+    tickSize: new BN(100),
     minBaseQty: new BN(100),
-    feesBps: 0,
+    takerFeeBps: 0,
     pre: 1_200_000,
   });
   await client.initVaults({ baseMint, quoteMint });
 
   // Ensure blobs reach the requested capacities with incremental grow instructions (each limited to ~10 KiB).
-  const desiredBookLen = (cap: number) => 13 + 24 + 80 * cap; // Blob + SlabHeader + nodes
-  const desiredEventLen = (cap: number) => 13 + 48 * cap; // Blob + FillEvent * cap
+  const desiredBookLen = (cap: number) => 13 + 24 + 152 * cap; // Blob + SlabHeader + nodes
+  const desiredEventLen = (cap: number) => 13 + 16 + 209 * cap; // Blob + ring header + slots * cap
   const pdas = client.derivePdas(baseMint, quoteMint, (provider.wallet as any).publicKey);
   const readBlobInfo = async (pubkey: PublicKey) => {
     const acc = await provider.connection.getAccountInfo(pubkey);
@@ -195,23 +194,20 @@ async function main() {
       const side = randInt(0, 1) as 0 | 1;
       const qty = new BN(randInt(1, 5) * 100);
       const price = side === 0
-        ? new BN(11_000 + randInt(0, 500)) // bids above mid
-        : new BN(9_000 - randInt(0, 500)); // asks below mid
+        ? new BN(11_000 + randInt(0, 5) * 100) // bids above mid
+        : new BN(9_000 - randInt(0, 5) * 100); // asks below mid
 
       try {
         const ooAcc = await client.fetchOpenOrders(w.pdas.oo);
-        if (ooAcc.active) {
+        if (ooAcc.slots.every((s: any) => s.active)) {
+          const oldest = ooAcc.slots.reduce((a: any, b: any) => (a.orderId.lt(b.orderId) ? a : b));
           await client.cancelOrder({
             baseMint,
             quoteMint,
             user: w.keypair.publicKey,
+            orderId: oldest.orderId,
             userSigner: w.keypair,
           });
-          const after = await client.fetchOpenOrders(w.pdas.oo);
-          if (after.active) {
-            process.stdout.write(`OO still active after cancel, skipping\n`);
-            continue;
-          }
         }
       } catch (_) {
         // OO doesn't exist yet; placeOrder will create it.
@@ -224,7 +220,6 @@ async function main() {
         priceTicks: price,
         baseQty: qty,
         side,
-        lockLamports,
         maxSlippageTicks: new BN(slippageTicks),
         pre: 1_200_000,
         userSigner: w.keypair,
@@ -1,4 +1,4 @@
-import anchorPkg, { AnchorProvider, BN, Program } from "@coral-xyz/anchor";
+import anchorPkg, { AnchorProvider, BN, BorshCoder, EventParser, Program } from "@coral-xyz/anchor";
 import type { Idl } from "@coral-xyz/anchor";
 import { PublicKey, Signer, ComputeBudgetProgram, SystemProgram } from "@solana/web3.js";
 import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
@@ -22,6 +22,54 @@ const SEEDS = {
   USER: Buffer.from("kerdos_user"),
 };
 
+export type OrderType = "limit" | "postOnly" | "postOnlySlide" | "immediateOrCancel" | "fillOrKill" | "market";
+export type SelfTradeBehavior = "cancelMaker" | "cancelTaker" | "decrementBoth" | "abortTransaction";
+export type EngineKind = "priceTime" | "proRata" | "batchAuction";
+
+export type OrderArgs = {
+  priceTicks: BN;
+  baseQty: BN;
+  side: 0 | 1;
+  maxSlippageTicks: BN;
+  clientOrderId?: BN;
+  orderType?: OrderType;
+  quoteLimit?: BN;
+  selfTradeBehavior?: SelfTradeBehavior;
+  expiresAt?: BN;
+  displayQty?: BN;
+  matchLimit?: number;
+};
+
+export type BatchOrderItem = { cancel: { orderId: BN } } | { place: OrderArgs };
+
+/** Names of the events the program logs; each carries `market` and a per-market `seqNum`. */
+export type KerdosEventName =
+  | "marketCreated"
+  | "orderPlaced"
+  | "orderCancelled"
+  | "orderAmended"
+  | "orderFilled"
+  | "eventsSettled"
+  | "deposit"
+  | "withdraw"
+  | "feesSwept";
+
+function orderParams(args: OrderArgs) {
+  return {
+    priceTicks: args.priceTicks,
+    baseQty: args.baseQty,
+    side: args.side,
+    maxSlippageTicks: args.maxSlippageTicks,
+    clientOrderId: args.clientOrderId ?? new BN(0),
+    orderType: { [args.orderType ?? "limit"]: {} },
+    quoteLimit: args.quoteLimit ?? new BN(0),
+    selfTradeBehavior: { [args.selfTradeBehavior ?? "decrementBoth"]: {} },
+    expiresAt: args.expiresAt ?? new BN(0),
+    displayQty: args.displayQty ?? new BN(0),
+    matchLimit: args.matchLimit ?? 0,
+  };
+}
+
 type PDAs = {
   market: PublicKey;
   bids: PublicKey;
@@ -87,7 +135,15 @@ export class KerdosClient {
     eventQueueCapacity: number;
     tickSize: BN;
     minBaseQty: BN;
-    feesBps: number;
+    takerFeeBps: number;
+    /** Negative for a maker rebate, at most takerFeeBps; defaults to 0. */
+    makerFeeBps?: number;
+    /** Native units per lot; both default to 1. */
+    baseLotSize?: BN;
+    quoteLotSize?: BN;
+    engineKind?: EngineKind;
+    /** Batch auctions only: slots between auctions; defaults to 0. */
+    auctionIntervalSlots?: BN;
     pre?: number;
   }) {
     const { baseMint, quoteMint } = args;
@@ -105,7 +161,12 @@ export class KerdosClient {
         eventQueueCapacity: args.eventQueueCapacity,
         tickSize: args.tickSize,
         minBaseQty: args.minBaseQty,
-        feesBps: args.feesBps,
+        makerFeeBps: args.makerFeeBps ?? 0,
+        takerFeeBps: args.takerFeeBps,
+        baseLotSize: args.baseLotSize ?? new BN(1),
+        quoteLotSize: args.quoteLotSize ?? new BN(1),
+        engineKind: { [args.engineKind ?? "priceTime"]: {} },
+        auctionIntervalSlots: args.auctionIntervalSlots ?? new BN(0),
       })
       .preInstructions(preIxs)
       .accounts({
@@ -248,17 +309,12 @@ export class KerdosClient {
   }
 
   /**
-   * Places an order on the book.
+   * Places an order on the book, locking its collateral from the user balance.
    */
-  async placeOrder(args: {
+  async placeOrder(args: OrderArgs & {
     baseMint: PublicKey;
     quoteMint: PublicKey;
     user: PublicKey;
-    priceTicks: BN;
-    baseQty: BN;
-    side: 0 | 1;
-    lockLamports: BN;
-    maxSlippageTicks: BN;
     pre?: number;
     userSigner: Signer;
   }) {
@@ -269,13 +325,44 @@ export class KerdosClient {
       preIxs.push(ComputeBudgetProgram.setComputeUnitLimit({ units: args.pre }));
     }
     return this.program.methods
-      .placeOrder({
-        priceTicks: args.priceTicks,
-        baseQty: args.baseQty,
-        side: args.side,
-        lockLamports: args.lockLamports,
-        maxSlippageTicks: args.maxSlippageTicks,
+      .placeOrder(orderParams(args))
+      .preInstructions(preIxs)
+      .accounts({
+        payer: user,
+        market: pdas.market,
+        bids: pdas.bids,
+        asks: pdas.asks,
+        eventQueue: pdas.eventQueue,
+        oo: pdas.oo,
+        ub: pdas.ub,
+        systemProgram: SystemProgram.programId,
       })
+      .signers([userSigner])
+      .rpc();
+  }
+
+  /**
+   * Applies cancels and places in order in one transaction; any failure reverts all of them.
+   */
+  async batchOrders(args: {
+    baseMint: PublicKey;
+    quoteMint: PublicKey;
+    user: PublicKey;
+    items: BatchOrderItem[];
+    pre?: number;
+    userSigner: Signer;
+  }) {
+    const { baseMint, quoteMint, user, userSigner } = args;
+    const pdas = this.derivePdas(baseMint, quoteMint, user);
+    const preIxs = [];
+    if (args.pre && args.pre > 0) {
+      preIxs.push(ComputeBudgetProgram.setComputeUnitLimit({ units: args.pre }));
+    }
+    const items = args.items.map((item) =>
+      "cancel" in item ? { cancel: { orderId: item.cancel.orderId } } : { place: { params: orderParams(item.place) } }
+    );
+    return this.program.methods
+      .batchOrders(items)
       .preInstructions(preIxs)
       .accounts({
         payer: user,
@@ -284,6 +371,7 @@ export class KerdosClient {
         asks: pdas.asks,
         eventQueue: pdas.eventQueue,
         oo: pdas.oo,
+        ub: pdas.ub,
         systemProgram: SystemProgram.programId,
       })
       .signers([userSigner])
@@ -291,7 +379,27 @@ export class KerdosClient {
   }
 
   /**
-   * Settles fill events into user balances.
+   * Runs a due batch auction; limit caps the fills (0 = program default).
+   */
+  async runAuction(args: { baseMint: PublicKey; quoteMint: PublicKey; limit?: number }) {
+    const pdas = this.derivePdas(args.baseMint, args.quoteMint, this.provider.wallet.publicKey);
+    return this.program.methods
+      .runAuction(args.limit ?? 0)
+      .accounts({
+        payer: this.provider.wallet.publicKey,
+        market: pdas.market,
+        bids: pdas.bids,
+        asks: pdas.asks,
+        eventQueue: pdas.eventQueue,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+  }
+
+  /**
+   * Settles queued fill and out events into user balances and open orders. Anyone may
+   * call it; with `claimReward` the wallet's own user balance is passed so it receives
+   * the market's crank reward.
    */
   async settleEvents(args: {
     baseMint: PublicKey;
@@ -299,10 +407,12 @@ export class KerdosClient {
     maxEvents: number;
     oos: PublicKey[];
     ubs: PublicKey[];
+    claimReward?: boolean;
   }) {
     const { baseMint, quoteMint, maxEvents, oos, ubs } = args;
     const pdas = this.derivePdas(baseMint, quoteMint, this.provider.wallet.publicKey);
-    const remaining = [...oos, ...ubs].map((pubkey) => ({
+    const rewardUbs = args.claimReward && !ubs.some((ub) => ub.equals(pdas.ub)) ? [pdas.ub] : [];
+    const remaining = [...oos, ...ubs, ...rewardUbs].map((pubkey) => ({
       pubkey,
       isSigner: false,
       isWritable: true,
@@ -310,7 +420,7 @@ export class KerdosClient {
     return this.program.methods
       .settleEvents(maxEvents)
       .accounts({
-        authority: this.provider.wallet.publicKey,
+        cranker: this.provider.wallet.publicKey,
         market: pdas.market,
         eventQueue: pdas.eventQueue,
       })
@@ -318,6 +428,62 @@ export class KerdosClient {
       .rpc();
   }
 
+  /**
+   * Sets the fee authority and the quote token account fees are swept to (market authority only).
+   */
+  async setFeeConfig(args: { baseMint: PublicKey; quoteMint: PublicKey; feeAuthority: PublicKey; treasury: PublicKey }) {
+    const pdas = this.derivePdas(args.baseMint, args.quoteMint, this.provider.wallet.publicKey);
+    return this.program.methods
+      .setFeeConfig()
+      .accounts({
+        authority: this.provider.wallet.publicKey,
+        market: pdas.market,
+        feeAuthority: args.feeAuthority,
+        treasury: args.treasury,
+      } as any)
+      .rpc();
+  }
+
+  /**
+   * Sets the quote reward paid per settled event out of accrued fees (market authority only).
+   */
+  async setCrankReward(args: { baseMint: PublicKey; quoteMint: PublicKey; reward: BN }) {
+    const pdas = this.derivePdas(args.baseMint, args.quoteMint, this.provider.wallet.publicKey);
+    return this.program.methods
+      .setCrankReward(args.reward)
+      .accounts({
+        authority: this.provider.wallet.publicKey,
+        market: pdas.market,
+      } as any)
+      .rpc();
+  }
+
+  /**
+   * Moves accrued quote fees from the vault to the configured treasury.
+   */
+  async sweepFees(args: { baseMint: PublicKey; quoteMint: PublicKey; treasury: PublicKey; feeAuthority?: Signer }) {
+    const pdas = this.derivePdas(args.baseMint, args.quoteMint, this.provider.wallet.publicKey);
+    const builder = this.program.methods
+      .sweepFees()
+      .accounts({
+        feeAuthority: args.feeAuthority?.publicKey ?? this.provider.wallet.publicKey,
+        market: pdas.market,
+        vaultAuth: pdas.vaultAuth,
+        quoteVault: pdas.quoteVault,
+        treasury: args.treasury,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any);
+    return args.feeAuthority ? builder.signers([args.feeAuthority]).rpc() : builder.rpc();
+  }
+
+  /**
+   * Quote fees a sweep would move now, read by simulating `claimable_fees`.
+   */
+  async claimableFees(args: { baseMint: PublicKey; quoteMint: PublicKey }): Promise<BN> {
+    const pdas = this.derivePdas(args.baseMint, args.quoteMint, this.provider.wallet.publicKey);
+    return this.program.methods.claimableFees().accounts({ market: pdas.market }).view();
+  }
+
   /**
    * Fetches a user balance account.
    */
@@ -333,24 +499,136 @@ export class KerdosClient {
   }
 
   /**
-   * Cancels an open order if it exists.
+   * Decodes the program events in a transaction's log messages, in the order they were logged.
+   */
+  parseEvents(logs: string[]): { name: string; data: any }[] {
+    const parser = new EventParser(this.programId, new BorshCoder(this.program.idl));
+    return [...parser.parseLogs(logs)];
+  }
+
+  /**
+   * Calls `cb` for every `name` event the program logs. Returns the id to pass to
+   * `program.removeEventListener`.
+   */
+  onEvent(name: KerdosEventName, cb: (event: any, slot: number, signature: string) => void) {
+    return this.program.addEventListener(name as any, cb as any);
+  }
+
+  /**
+   * Cancels one resting order by its program-assigned order id.
    */
   async cancelOrder(args: {
     baseMint: PublicKey;
     quoteMint: PublicKey;
     user: PublicKey;
+    orderId: BN;
+    userSigner: Signer;
+  }) {
+    const { baseMint, quoteMint, user, orderId, userSigner } = args;
+    const pdas = this.derivePdas(baseMint, quoteMint, user);
+    return this.program.methods
+      .cancelOrder(orderId)
+      .accounts({
+        payer: user,
+        market: pdas.market,
+        bids: pdas.bids,
+        asks: pdas.asks,
+        eventQueue: pdas.eventQueue,
+        oo: pdas.oo,
+        ub: pdas.ub,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([userSigner])
+      .rpc();
+  }
+
+  /**
+   * Cancels one resting order by the client order id it was placed with.
+   */
+  async cancelOrderByClientId(args: {
+    baseMint: PublicKey;
+    quoteMint: PublicKey;
+    user: PublicKey;
+    clientOrderId: BN;
+    userSigner: Signer;
+  }) {
+    const { baseMint, quoteMint, user, clientOrderId, userSigner } = args;
+    const pdas = this.derivePdas(baseMint, quoteMint, user);
+    return this.program.methods
+      .cancelOrderByClientId(clientOrderId)
+      .accounts({
+        payer: user,
+        market: pdas.market,
+        bids: pdas.bids,
+        asks: pdas.asks,
+        eventQueue: pdas.eventQueue,
+        oo: pdas.oo,
+        ub: pdas.ub,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([userSigner])
+      .rpc();
+  }
+
+  /**
+   * Cancels up to `limit` resting orders of the user (0 or omitted = all), optionally only
+   * bids (0) or asks (1).
+   */
+  async cancelAllOrders(args: {
+    baseMint: PublicKey;
+    quoteMint: PublicKey;
+    user: PublicKey;
+    side?: 0 | 1;
+    limit?: number;
     userSigner: Signer;
   }) {
     const { baseMint, quoteMint, user, userSigner } = args;
     const pdas = this.derivePdas(baseMint, quoteMint, user);
     return this.program.methods
-      .cancelOrder()
+      .cancelAllOrders(args.side ?? null, args.limit ?? 0)
       .accounts({
         payer: user,
         market: pdas.market,
         bids: pdas.bids,
         asks: pdas.asks,
+        eventQueue: pdas.eventQueue,
         oo: pdas.oo,
+        ub: pdas.ub,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([userSigner])
+      .rpc();
+  }
+
+  /**
+   * Changes the price and/or size of a resting order. A smaller size at the same
+   * price keeps queue priority; any other change re-queues the order. Collateral is
+   * locked or released from the user balance for the difference.
+   */
+  async amendOrder(args: {
+    baseMint: PublicKey;
+    quoteMint: PublicKey;
+    user: PublicKey;
+    orderId: BN;
+    priceTicks: BN;
+    baseQty: BN;
+    userSigner: Signer;
+  }) {
+    const { baseMint, quoteMint, user, userSigner } = args;
+    const pdas = this.derivePdas(baseMint, quoteMint, user);
+    return this.program.methods
+      .amendOrder({
+        orderId: args.orderId,
+        priceTicks: args.priceTicks,
+        baseQty: args.baseQty,
+      })
+      .accounts({
+        payer: user,
+        market: pdas.market,
+        bids: pdas.bids,
+        asks: pdas.asks,
+        oo: pdas.oo,
+        ub: pdas.ub,
       })
       .signers([userSigner])
       .rpc();
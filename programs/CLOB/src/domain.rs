@@ -12,7 +12,14 @@ pub struct InitParams {
     pub event_queue_capacity: u32,
     pub tick_size: u64,
     pub min_base_qty: u64,
-    pub fees_bps: u16,
+    /// Negative values are a rebate, at most `taker_fee_bps`.
+    pub maker_fee_bps: i16,
+    pub taker_fee_bps: u16,
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub engine_kind: EngineKind,
+    /// Batch auctions only: slots between auctions, counted from `init_market`.
+    pub auction_interval_slots: u64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
@@ -22,7 +29,8 @@ pub struct Profile {
     pub event_queue_capacity: u32,
     pub tick_size: u64,
     pub min_base_qty: u64,
-    pub fees_bps: u16,
+    pub maker_fee_bps: i16,
+    pub taker_fee_bps: u16,
 }
 
 pub mod profiles {
@@ -33,7 +41,8 @@ pub mod profiles {
         event_queue_capacity: 512,
         tick_size: 10_000,
         min_base_qty: 100,
-        fees_bps: 10,
+        maker_fee_bps: 0,
+        taker_fee_bps: 10,
     };
     pub const STD: Profile = Profile {
         bids_capacity: 4096,
@@ -41,7 +50,8 @@ pub mod profiles {
         event_queue_capacity: 2048,
         tick_size: 1_000,
         min_base_qty: 50,
-        fees_bps: 10,
+        maker_fee_bps: 0,
+        taker_fee_bps: 10,
     };
     pub const DEEP: Profile = Profile {
         bids_capacity: 8192,
@@ -49,17 +59,25 @@ pub mod profiles {
         event_queue_capacity: 4096,
         tick_size: 100,
         min_base_qty: 10,
-        fees_bps: 8,
+        maker_fee_bps: -2,
+        taker_fee_bps: 8,
     };
 }
 
 pub mod sizing {
     pub const DISCRIMINATOR: usize = 8;
-    pub const MARKET_HEADER: usize = 8 + 32 + 32 + 32 + 32 + 32 + 8 + 8 + 2 + 1 + 1 + 1 + 1 + 4 + 4 + 4 + 1;
+    /// `Market` after its discriminator, field by field.
+    pub const MARKET_HEADER: usize = 32 + 32 + 32 + 32 + 32 + 32
+        + 8 + 8 + 8 + 8 + 1 + 1 + 2 + 2 + 1 + 1 + 8 + 8
+        + 1 + 1 + 1 + 1
+        + 4 + 4 + 4
+        + 8 + 8 + 32 + 32 + 8 + 8 + 8
+        + 8;
     pub const BLOB_HEADER: usize = 4 + 1 + 4 + 4;
     pub const SLAB_HEADER: usize = 24;
-    pub const SLAB_NODE: usize = 80;
-    pub const EVENT_EST: usize = 48;
+    pub const SLAB_NODE: usize = 152;
+    pub const EVENTQ_HEADER: usize = 4 + 4 + 8;
+    pub const EVENT_EST: usize = 8 + 1 + 200;
 
     pub fn market_space() -> usize {
         DISCRIMINATOR + MARKET_HEADER
@@ -74,7 +92,7 @@ pub mod sizing {
     }
 
     pub fn eventq_space(capacity: u32) -> usize {
-        BLOB_HEADER + (capacity as usize) * EVENT_EST
+        BLOB_HEADER + EVENTQ_HEADER + (capacity as usize) * EVENT_EST
     }
 
     pub fn total_market_space(bids_cap: u32, asks_cap: u32, evq_cap: u32) -> usize {
@@ -89,8 +107,65 @@ pub enum BlobKind {
     EventQueue = 3,
 }
 
+/// How an incoming order treats liquidity on the opposite side.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OrderType {
+    /// Match what crosses, rest the remainder.
+    Limit,
+    /// Rest only; rejected if it would cross.
+    PostOnly,
+    /// Rest only; a crossing price is moved one tick behind the opposite best.
+    PostOnlySlide,
+    /// Match what crosses, drop the remainder.
+    ImmediateOrCancel,
+    /// Match the full quantity or fail.
+    FillOrKill,
+    /// Sweep the opposite book at any price, bounded by `quote_limit`; never rests.
+    Market,
+}
+
+/// What happens when an incoming order would fill against its owner's own resting order.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTradeBehavior {
+    /// Remove the resting order and keep matching.
+    CancelMaker,
+    /// Stop matching and drop the incoming remainder.
+    CancelTaker,
+    /// Reduce both orders by the overlap without a fill.
+    DecrementBoth,
+    /// Fail the transaction.
+    AbortTransaction,
+}
+
+/// Matching strategy a market runs; fixed at `init_market`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EngineKind {
+    /// Best price first, then oldest order within a level.
+    #[default]
+    PriceTime,
+    /// Each level is shared across its makers in proportion to size.
+    ProRata,
+    /// Orders accumulate and `run_auction` clears them at one price per interval.
+    BatchAuction,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
 pub struct GrowParams {
     pub which: u8,
     pub step_bytes: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::sizing;
+    use crate::eventq::QueuedEvent;
+    use crate::slab::SlabNode;
+    use crate::state::Market;
+
+    #[test]
+    fn sizing_matches_account_layouts() {
+        assert_eq!(sizing::market_space(), Market::LEN);
+        assert_eq!(sizing::SLAB_NODE, SlabNode::LEN);
+        assert_eq!(sizing::EVENT_EST, QueuedEvent::LEN);
+    }
+}
@@ -1,15 +1,105 @@
 use anchor_lang::prelude::*;
+use crate::domain::{EngineKind, OrderType, SelfTradeBehavior};
+use crate::ix_orders::OrdersError;
+use crate::lots::LotSizes;
+use crate::slab::SlabNode;
+
+/// The side of the book an incoming order matches against.
+pub trait BookSide {
+    /// Best resting order, expired or not.
+    fn peek_best(&self) -> Result<Option<SlabNode>>;
+    /// Up to `max` orders at the best price, oldest first.
+    fn best_level(&self, max: usize) -> Result<Vec<SlabNode>>;
+    /// Up to `max` orders in match priority across levels.
+    fn best_orders(&self, max: usize) -> Result<Vec<SlabNode>>;
+    /// Takes `qty` off the best order; an emptied order leaves the book.
+    fn consume_best(&mut self, qty: u64) -> Result<()>;
+    /// Takes `qty` off `node`, wherever it sits in its level.
+    fn consume(&mut self, node: &SlabNode, qty: u64) -> Result<()>;
+    /// Removes `node` from the book entirely.
+    fn remove(&mut self, node: &SlabNode) -> Result<()>;
+}
+
+/// Receives everything a match step decides besides the book changes themselves.
+pub trait MatchSink {
+    /// `qty` traded against `maker` at `price_ticks`.
+    fn on_fill(&mut self, maker: &SlabNode, qty: u64, price_ticks: u64) -> Result<()>;
+    /// `maker` had expired and was pruned from the book.
+    fn on_expired(&mut self, maker: &SlabNode) -> Result<()>;
+    /// `qty` of the taker's own resting order was cancelled instead of filled.
+    fn on_self_trade(&mut self, maker: &SlabNode, qty: u64) -> Result<()>;
+}
+
+#[derive(Clone, Copy)]
+pub struct TakerOrder {
+    pub oo: Pubkey,
+    pub price_ticks: u64,
+    pub base_qty: u64,
+    pub is_bid: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct MatchParams {
+    pub order_type: OrderType,
+    pub max_slippage_ticks: u64,
+    pub tick_size: u64,
+    pub min_base_qty: u64,
+    /// Market buys: the most quote spent, taker fees included.
+    pub quote_limit: u64,
+    pub taker_fee_bps: u16,
+    pub lots: LotSizes,
+    pub self_trade_behavior: SelfTradeBehavior,
+    pub match_limit: u16,
+    pub now: i64,
+}
+
+/// What is left of the taker once matching stops.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchOutcome {
+    /// Price to rest at; differs from the request only after a post-only slide.
+    pub price_ticks: u64,
+    pub base_qty: u64,
+    pub rests: bool,
+    /// The match limit ran out with the book still crossing, so a remainder that would
+    /// otherwise rest was dropped instead.
+    pub match_limit_reached: bool,
+}
 
 pub trait MatchingEngine {
     fn validate_tick(&self, tick_size: u64, price_ticks: u64) -> Result<()>;
     fn validate_min_qty(&self, min_base_qty: u64, base_qty: u64) -> Result<()>;
+    /// Whether incoming orders trade on arrival. When false the book may stay crossed
+    /// until the market's own clearing step runs.
+    fn matches_on_place(&self) -> bool {
+        true
+    }
+    /// Matches `taker` against `book` and decides whether the residual rests. Placing the
+    /// residual is left to the caller.
+    fn match_order(
+        &self,
+        book: &mut dyn BookSide,
+        sink: &mut dyn MatchSink,
+        taker: &TakerOrder,
+        p: &MatchParams,
+    ) -> Result<MatchOutcome>;
 }
 
+/// The engine a market was created with.
+pub fn engine_for(kind: EngineKind) -> &'static dyn MatchingEngine {
+    match kind {
+        EngineKind::PriceTime => &BinaryClobEngine,
+        EngineKind::ProRata => &ProRataEngine,
+        EngineKind::BatchAuction => &BatchAuctionEngine,
+    }
+}
+
+/// Price-time priority: best price first, oldest order first within a level.
 pub struct BinaryClobEngine;
 
 impl MatchingEngine for BinaryClobEngine {
     fn validate_tick(&self, tick_size: u64, price_ticks: u64) -> Result<()> {
         require!(tick_size > 0 && price_ticks > 0, KerdosError::InvalidTick);
+        require!(price_ticks.is_multiple_of(tick_size), KerdosError::OffTickGrid);
         Ok(())
     }
 
@@ -17,6 +107,427 @@ impl MatchingEngine for BinaryClobEngine {
         require!(base_qty >= min_base_qty, KerdosError::TooSmallQty);
         Ok(())
     }
+
+    fn match_order(
+        &self,
+        book: &mut dyn BookSide,
+        sink: &mut dyn MatchSink,
+        taker: &TakerOrder,
+        p: &MatchParams,
+    ) -> Result<MatchOutcome> {
+        let is_bid = taker.is_bid;
+        let mut qty = taker.base_qty;
+        let mut makers_left = p.match_limit;
+        let price = post_only_price(book, sink, taker, p, &mut makers_left)?;
+
+        let is_market = p.order_type == OrderType::Market;
+        let mut quote_left = p.quote_limit;
+        let mut quote_received: u64 = 0;
+        let mut taker_cancelled = false;
+
+        while qty > 0 && makers_left > 0 {
+            let Some(maker) = peek_live(book, sink, p.now, &mut makers_left)? else { break; };
+            let top_price = maker.price_ticks;
+
+            if !is_market {
+                if !crosses(is_bid, price, top_price) { break; }
+                if price.abs_diff(top_price) > p.max_slippage_ticks { break; }
+            }
+
+            makers_left -= 1;
+            if maker.oo == taker.oo {
+                match p.self_trade_behavior {
+                    SelfTradeBehavior::AbortTransaction => return err!(OrdersError::SelfTrade),
+                    SelfTradeBehavior::CancelTaker => {
+                        taker_cancelled = true;
+                        break;
+                    }
+                    SelfTradeBehavior::CancelMaker => {
+                        book.remove(&maker)?;
+                        sink.on_self_trade(&maker, maker.base_qty + maker.hidden_qty)?;
+                    }
+                    SelfTradeBehavior::DecrementBoth => {
+                        let overlap = qty.min(maker.base_qty);
+                        book.consume_best(overlap)?;
+                        sink.on_self_trade(&maker, overlap)?;
+                        qty -= overlap;
+                    }
+                }
+                continue;
+            }
+
+            let mut fill = qty.min(maker.base_qty);
+            if is_market && is_bid {
+                // Only whole lots that the remaining budget can pay for, fee included.
+                let lot = p.min_base_qty.max(1);
+                let affordable = p.lots.base_lots_for_quote(quote_left, top_price, p.taker_fee_bps)?;
+                fill = fill.min(affordable - affordable % lot);
+                if fill == 0 { break; }
+                quote_left -= p.lots.quote_with_fee(fill, top_price, p.taker_fee_bps)?;
+            } else if is_market {
+                let quote = p.lots.quote_native(fill, top_price)?;
+                quote_received = quote_received.checked_add(quote).ok_or(OrdersError::Overflow)?;
+            }
+            book.consume_best(fill)?;
+            sink.on_fill(&maker, fill, top_price)?;
+            qty -= fill;
+        }
+
+        finish(book, taker, p, Residual { price_ticks: price, base_qty: qty, makers_left, taker_cancelled, quote_received })
+    }
+}
+
+/// Splits each crossing level across all of its makers in proportion to their visible
+/// size, in `min_base_qty` steps. Steps left over after rounding down go one each to
+/// the oldest makers, so the result is deterministic.
+pub struct ProRataEngine;
+
+impl MatchingEngine for ProRataEngine {
+    fn validate_tick(&self, tick_size: u64, price_ticks: u64) -> Result<()> {
+        BinaryClobEngine.validate_tick(tick_size, price_ticks)
+    }
+
+    fn validate_min_qty(&self, min_base_qty: u64, base_qty: u64) -> Result<()> {
+        BinaryClobEngine.validate_min_qty(min_base_qty, base_qty)
+    }
+
+    fn match_order(
+        &self,
+        book: &mut dyn BookSide,
+        sink: &mut dyn MatchSink,
+        taker: &TakerOrder,
+        p: &MatchParams,
+    ) -> Result<MatchOutcome> {
+        let is_bid = taker.is_bid;
+        let step = p.min_base_qty.max(1);
+        let mut qty = taker.base_qty;
+        let mut makers_left = p.match_limit;
+        let price = post_only_price(book, sink, taker, p, &mut makers_left)?;
+
+        let is_market = p.order_type == OrderType::Market;
+        let mut quote_left = p.quote_limit;
+        let mut quote_received: u64 = 0;
+        let mut taker_cancelled = false;
+
+        'levels: while qty > 0 && makers_left > 0 {
+            let Some(head) = peek_live(book, sink, p.now, &mut makers_left)? else { break; };
+            let level_price = head.price_ticks;
+            if !is_market {
+                if !crosses(is_bid, price, level_price) { break; }
+                if price.abs_diff(level_price) > p.max_slippage_ticks { break; }
+            }
+
+            // Every order in the snapshot counts against the budget, whatever its share.
+            let level = book.best_level(makers_left as usize)?;
+            let mut makers = Vec::with_capacity(level.len());
+            for maker in level {
+                makers_left -= 1;
+                if maker.expires_at != 0 && maker.expires_at <= p.now {
+                    book.remove(&maker)?;
+                    sink.on_expired(&maker)?;
+                } else if maker.oo == taker.oo {
+                    match p.self_trade_behavior {
+                        SelfTradeBehavior::AbortTransaction => return err!(OrdersError::SelfTrade),
+                        SelfTradeBehavior::CancelTaker => {
+                            taker_cancelled = true;
+                            break 'levels;
+                        }
+                        SelfTradeBehavior::CancelMaker => {
+                            book.remove(&maker)?;
+                            sink.on_self_trade(&maker, maker.base_qty + maker.hidden_qty)?;
+                        }
+                        SelfTradeBehavior::DecrementBoth => {
+                            let overlap = qty.min(maker.base_qty);
+                            book.consume(&maker, overlap)?;
+                            sink.on_self_trade(&maker, overlap)?;
+                            qty -= overlap;
+                        }
+                    }
+                } else {
+                    makers.push(maker);
+                }
+            }
+            if makers.is_empty() { continue; }
+
+            let total = makers.iter().try_fold(0u64, |acc, m| acc.checked_add(m.base_qty)).ok_or(OrdersError::Overflow)?;
+            let mut fill = qty.min(total);
+            if is_market && is_bid {
+                // Each maker's fill rounds its fee up on its own, up to one unit more per
+                // extra maker than the level's fee as a whole.
+                let budget = quote_left.saturating_sub(makers.len() as u64 - 1);
+                let affordable = p.lots.base_lots_for_quote(budget, level_price, p.taker_fee_bps)?;
+                fill = fill.min(affordable - affordable % step);
+            }
+            if fill == 0 { break; }
+
+            let sizes: Vec<u64> = makers.iter().map(|m| m.base_qty / step).collect();
+            let shares = pro_rata(fill / step, &sizes);
+            for (maker, share) in makers.iter().zip(shares) {
+                if share == 0 { continue; }
+                let q = share * step;
+                book.consume(maker, q)?;
+                sink.on_fill(maker, q, level_price)?;
+                if is_market && is_bid {
+                    quote_left -= p.lots.quote_with_fee(q, level_price, p.taker_fee_bps)?;
+                }
+            }
+            if is_market && !is_bid {
+                let quote = p.lots.quote_native(fill, level_price)?;
+                quote_received = quote_received.checked_add(quote).ok_or(OrdersError::Overflow)?;
+            }
+            qty -= fill;
+            // A level that was not cleared means the taker, its budget or its quote ran out.
+            if fill < total { break; }
+        }
+
+        finish(book, taker, p, Residual { price_ticks: price, base_qty: qty, makers_left, taker_cancelled, quote_received })
+    }
+}
+
+/// Orders only rest on arrival; `run_auction` later clears everything that crosses at
+/// one uniform price. Only limit orders make sense here.
+pub struct BatchAuctionEngine;
+
+/// Orders per side an auction looks at; deeper orders wait for the next one.
+pub const MAX_AUCTION_ORDERS: usize = 64;
+
+/// Receives the trades of an auction.
+pub trait AuctionSink {
+    /// `qty` crossed between `bid` and `ask`, as they stood before the cross, at the
+    /// clearing price.
+    fn on_cross(&mut self, bid: &SlabNode, ask: &SlabNode, qty: u64, price_ticks: u64) -> Result<()>;
+    /// `node` had expired and was pruned from its book.
+    fn on_expired(&mut self, node: &SlabNode, is_bid: bool) -> Result<()>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuctionOutcome {
+    /// None when nothing crossed.
+    pub price_ticks: Option<u64>,
+    pub fills: u16,
+    /// False when `max_fills` ran out or deeper orders still cross at the price.
+    pub cleared: bool,
+}
+
+impl MatchingEngine for BatchAuctionEngine {
+    fn validate_tick(&self, tick_size: u64, price_ticks: u64) -> Result<()> {
+        BinaryClobEngine.validate_tick(tick_size, price_ticks)
+    }
+
+    fn validate_min_qty(&self, min_base_qty: u64, base_qty: u64) -> Result<()> {
+        BinaryClobEngine.validate_min_qty(min_base_qty, base_qty)
+    }
+
+    fn matches_on_place(&self) -> bool {
+        false
+    }
+
+    fn match_order(
+        &self,
+        _book: &mut dyn BookSide,
+        _sink: &mut dyn MatchSink,
+        taker: &TakerOrder,
+        p: &MatchParams,
+    ) -> Result<MatchOutcome> {
+        require!(p.order_type == OrderType::Limit, KerdosError::UnsupportedOrderType);
+        Ok(MatchOutcome { price_ticks: taker.price_ticks, base_qty: taker.base_qty, rests: true, match_limit_reached: false })
+    }
+}
+
+impl BatchAuctionEngine {
+    /// Finds the clearing price of the top `MAX_AUCTION_ORDERS` of each side and trades
+    /// every crossing order at it, best prices first and oldest first within a price, up
+    /// to `max_fills` trades. Orders of the same open orders account never trade with
+    /// each other: a bid skips over its own asks and both stay on the book.
+    pub fn run_auction(
+        &self,
+        bids: &mut dyn BookSide,
+        asks: &mut dyn BookSide,
+        sink: &mut dyn AuctionSink,
+        tick_size: u64,
+        now: i64,
+        max_fills: u16,
+    ) -> Result<AuctionOutcome> {
+        let mut bid_orders = live_orders(bids, sink, true, now)?;
+        let mut ask_orders = live_orders(asks, sink, false, now)?;
+        let Some(price) = clearing_price(&bid_orders, &ask_orders, tick_size) else {
+            return Ok(AuctionOutcome { price_ticks: None, fills: 0, cleared: true });
+        };
+
+        // Asks before `first_ask` are used up.
+        let mut first_ask = 0;
+        let mut fills = 0u16;
+        for bid in bid_orders.iter_mut() {
+            if bid.price_ticks < price { break; }
+            for ask in ask_orders[first_ask..].iter_mut() {
+                if bid.base_qty == 0 || ask.price_ticks > price { break; }
+                if ask.base_qty == 0 || ask.oo == bid.oo { continue; }
+                if fills == max_fills {
+                    return Ok(AuctionOutcome { price_ticks: Some(price), fills, cleared: false });
+                }
+                let qty = bid.base_qty.min(ask.base_qty);
+                bids.consume(bid, qty)?;
+                asks.consume(ask, qty)?;
+                sink.on_cross(bid, ask, qty, price)?;
+                fills += 1;
+                // Keep the snapshots in step with the book.
+                bid.base_qty -= qty;
+                ask.base_qty -= qty;
+            }
+            while first_ask < ask_orders.len() && ask_orders[first_ask].base_qty == 0 {
+                first_ask += 1;
+            }
+        }
+
+        let cleared = !crossing_pair_left(bids, asks, price)?;
+        Ok(AuctionOutcome { price_ticks: Some(price), fills, cleared })
+    }
+}
+
+/// Whether the books still hold a bid and an ask of different accounts that cross at
+/// `price`: orders past the snapshot, or own orders of more than one account.
+fn crossing_pair_left(bids: &dyn BookSide, asks: &dyn BookSide, price: u64) -> Result<bool> {
+    let bid_oos: Vec<Pubkey> = bids.best_orders(MAX_AUCTION_ORDERS)?.into_iter()
+        .take_while(|n| n.price_ticks >= price).map(|n| n.oo).collect();
+    let ask_oos: Vec<Pubkey> = asks.best_orders(MAX_AUCTION_ORDERS)?.into_iter()
+        .take_while(|n| n.price_ticks <= price).map(|n| n.oo).collect();
+    let Some(first) = bid_oos.first() else { return Ok(false) };
+    Ok(!ask_oos.is_empty() && bid_oos.iter().chain(&ask_oos).any(|oo| oo != first))
+}
+
+/// The top of `book` without expired orders, which are pruned on the way.
+fn live_orders(book: &mut dyn BookSide, sink: &mut dyn AuctionSink, is_bid: bool, now: i64) -> Result<Vec<SlabNode>> {
+    let mut live = Vec::new();
+    for n in book.best_orders(MAX_AUCTION_ORDERS)? {
+        if n.expires_at != 0 && n.expires_at <= now {
+            book.remove(&n)?;
+            sink.on_expired(&n, is_bid)?;
+        } else {
+            live.push(n);
+        }
+    }
+    Ok(live)
+}
+
+/// The price that trades the most volume between `bids` and `asks`. Ties go to the
+/// smallest leftover imbalance, then to the middle of the tied range, rounded down to
+/// the tick grid. None when nothing crosses.
+fn clearing_price(bids: &[SlabNode], asks: &[SlabNode], tick_size: u64) -> Option<u64> {
+    let mut best: Option<(u128, u128, u64, u64)> = None; // (volume, imbalance, lo, hi)
+    for p in bids.iter().chain(asks).map(|n| n.price_ticks) {
+        let demand: u128 = bids.iter().filter(|b| b.price_ticks >= p).map(|b| b.base_qty as u128).sum();
+        let supply: u128 = asks.iter().filter(|a| a.price_ticks <= p).map(|a| a.base_qty as u128).sum();
+        let volume = demand.min(supply);
+        if volume == 0 {
+            continue;
+        }
+        let imbalance = demand.abs_diff(supply);
+        best = match best {
+            Some((v, i, lo, hi)) if v == volume && i == imbalance => Some((v, i, lo.min(p), hi.max(p))),
+            Some((v, i, _, _)) if v > volume || (v == volume && i < imbalance) => best,
+            _ => Some((volume, imbalance, p, p)),
+        };
+    }
+    best.map(|(_, _, lo, hi)| {
+        let mid = lo + (hi - lo) / 2;
+        (mid - mid % tick_size.max(1)).max(lo)
+    })
+}
+
+/// Splits `fill` across `sizes` proportionally, rounding down, then hands the remainder
+/// out one unit at a time from the front. Requires `fill <= sum(sizes)`.
+fn pro_rata(fill: u64, sizes: &[u64]) -> Vec<u64> {
+    let total: u128 = sizes.iter().map(|s| *s as u128).sum();
+    if total == 0 {
+        return vec![0; sizes.len()];
+    }
+    let mut shares: Vec<u64> = sizes.iter().map(|s| (fill as u128 * *s as u128 / total) as u64).collect();
+    let mut left = fill - shares.iter().sum::<u64>();
+    for (share, size) in shares.iter_mut().zip(sizes) {
+        if left == 0 { break; }
+        if *share < *size {
+            *share += 1;
+            left -= 1;
+        }
+    }
+    shares
+}
+
+/// The price an order matches and rests at. Post-only orders must not cross the live
+/// best: a plain post-only is rejected, a sliding one moves one tick behind it.
+fn post_only_price(
+    book: &mut dyn BookSide,
+    sink: &mut dyn MatchSink,
+    taker: &TakerOrder,
+    p: &MatchParams,
+    makers_left: &mut u16,
+) -> Result<u64> {
+    if !matches!(p.order_type, OrderType::PostOnly | OrderType::PostOnlySlide) {
+        return Ok(taker.price_ticks);
+    }
+    let Some(best) = peek_live(book, sink, p.now, makers_left)? else { return Ok(taker.price_ticks); };
+    if !crosses(taker.is_bid, taker.price_ticks, best.price_ticks) {
+        return Ok(taker.price_ticks);
+    }
+    require!(p.order_type == OrderType::PostOnlySlide, OrdersError::WouldCross);
+    let slid = if taker.is_bid {
+        best.price_ticks.checked_sub(p.tick_size).filter(|p| *p > 0)
+    } else {
+        best.price_ticks.checked_add(p.tick_size)
+    };
+    slid.ok_or_else(|| error!(OrdersError::PriceOutOfRange))
+}
+
+/// Where a match loop stopped.
+struct Residual {
+    price_ticks: u64,
+    base_qty: u64,
+    makers_left: u16,
+    taker_cancelled: bool,
+    quote_received: u64,
+}
+
+/// Applies the order type's rules to what is left after matching.
+fn finish(book: &dyn BookSide, taker: &TakerOrder, p: &MatchParams, r: Residual) -> Result<MatchOutcome> {
+    if p.order_type == OrderType::Market && !taker.is_bid {
+        require!(r.quote_received >= p.quote_limit, OrdersError::QuoteLimitNotMet);
+    }
+    let rests = r.base_qty > 0 && match p.order_type {
+        OrderType::FillOrKill => return err!(OrdersError::FillOrKillNotFilled),
+        OrderType::ImmediateOrCancel | OrderType::Market => false,
+        OrderType::Limit | OrderType::PostOnly | OrderType::PostOnlySlide => !r.taker_cancelled,
+    };
+    // Out of match budget with the book still crossing: resting would lock the book, so drop the rest.
+    let locked = rests
+        && r.makers_left == 0
+        && book.peek_best()?.is_some_and(|b| crosses(taker.is_bid, r.price_ticks, b.price_ticks));
+    Ok(MatchOutcome { price_ticks: r.price_ticks, base_qty: r.base_qty, rests: rests && !locked, match_limit_reached: locked })
+}
+
+fn crosses(taker_is_bid: bool, taker_price: u64, maker_price: u64) -> bool {
+    if taker_is_bid { taker_price >= maker_price } else { taker_price <= maker_price }
+}
+
+/// Best order that has not expired. Expired heads are removed on the way and handed to
+/// the sink; pruning costs about as much as a fill, so it draws on the same budget.
+fn peek_live(
+    book: &mut dyn BookSide,
+    sink: &mut dyn MatchSink,
+    now: i64,
+    makers_left: &mut u16,
+) -> Result<Option<SlabNode>> {
+    while let Some(head) = book.peek_best()? {
+        if head.expires_at == 0 || head.expires_at > now {
+            return Ok(Some(head));
+        }
+        if *makers_left == 0 {
+            return Ok(None);
+        }
+        *makers_left -= 1;
+        book.remove(&head)?;
+        sink.on_expired(&head)?;
+    }
+    Ok(None)
 }
 
 #[error_code]
@@ -25,17 +536,102 @@ pub enum KerdosError {
     InvalidTick,
     #[msg("quantity below minimum")]
     TooSmallQty,
+    #[msg("price is not a multiple of the tick size")]
+    OffTickGrid,
+    #[msg("order type not supported by this market's engine")]
+    UnsupportedOrderType,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Opposite book kept in priority order.
+    struct VecBook(Vec<SlabNode>);
+
+    impl BookSide for VecBook {
+        fn peek_best(&self) -> Result<Option<SlabNode>> {
+            Ok(self.0.first().copied())
+        }
+        fn best_orders(&self, max: usize) -> Result<Vec<SlabNode>> {
+            Ok(self.0.iter().take(max).copied().collect())
+        }
+        fn best_level(&self, max: usize) -> Result<Vec<SlabNode>> {
+            let Some(best) = self.0.first() else { return Ok(vec![]) };
+            Ok(self.0.iter().take_while(|n| n.price_ticks == best.price_ticks).take(max).copied().collect())
+        }
+        fn consume(&mut self, node: &SlabNode, qty: u64) -> Result<()> {
+            let i = self.0.iter().position(|n| n.order_id == node.order_id).unwrap();
+            self.0[i].base_qty -= qty;
+            if self.0[i].base_qty == 0 {
+                self.0.remove(i);
+            }
+            Ok(())
+        }
+        fn consume_best(&mut self, qty: u64) -> Result<()> {
+            self.0[0].base_qty -= qty;
+            if self.0[0].base_qty == 0 {
+                self.0.remove(0);
+            }
+            Ok(())
+        }
+        fn remove(&mut self, node: &SlabNode) -> Result<()> {
+            self.0.retain(|n| n.order_id != node.order_id);
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        fills: Vec<(u64, u64, u64)>,
+        expired: Vec<u64>,
+        self_trades: Vec<(u64, u64)>,
+    }
+
+    impl MatchSink for Recorder {
+        fn on_fill(&mut self, maker: &SlabNode, qty: u64, price_ticks: u64) -> Result<()> {
+            self.fills.push((maker.order_id, qty, price_ticks));
+            Ok(())
+        }
+        fn on_expired(&mut self, maker: &SlabNode) -> Result<()> {
+            self.expired.push(maker.order_id);
+            Ok(())
+        }
+        fn on_self_trade(&mut self, maker: &SlabNode, qty: u64) -> Result<()> {
+            self.self_trades.push((maker.order_id, qty));
+            Ok(())
+        }
+    }
+
+    fn ask(order_id: u64, price_ticks: u64, base_qty: u64, owner: Pubkey) -> SlabNode {
+        SlabNode { order_id, price_ticks, base_qty, oo: owner, ..SlabNode::default() }
+    }
+
+    fn params(order_type: OrderType) -> MatchParams {
+        MatchParams {
+            order_type,
+            max_slippage_ticks: u64::MAX,
+            tick_size: 1,
+            min_base_qty: 1,
+            quote_limit: 0,
+            taker_fee_bps: 0,
+            lots: LotSizes { base_lot_size: 1, quote_lot_size: 1 },
+            self_trade_behavior: SelfTradeBehavior::DecrementBoth,
+            match_limit: 32,
+            now: 100,
+        }
+    }
+
+    fn bid(oo: Pubkey, price_ticks: u64, base_qty: u64) -> TakerOrder {
+        TakerOrder { oo, price_ticks, base_qty, is_bid: true }
+    }
+
     #[test]
     fn ticks_ok() {
         let e = BinaryClobEngine;
         assert!(e.validate_tick(1, 1).is_ok());
-        assert!(e.validate_tick(10, 5).is_ok());
+        assert!(e.validate_tick(10, 20).is_ok());
+        assert!(e.validate_tick(10, u64::MAX - u64::MAX % 10).is_ok());
     }
 
     #[test]
@@ -43,6 +639,8 @@ mod tests {
         let e = BinaryClobEngine;
         assert!(e.validate_tick(0, 1).is_err());
         assert!(e.validate_tick(1, 0).is_err());
+        assert!(e.validate_tick(10, 5).is_err());
+        assert!(e.validate_tick(10, 25).is_err());
     }
 
     #[test]
@@ -52,4 +650,226 @@ mod tests {
         assert!(e.validate_min_qty(10, 11).is_ok());
         assert!(e.validate_min_qty(10, 9).is_err());
     }
+
+    #[test]
+    fn fills_in_price_time_order_and_rests_the_residual() {
+        let (maker, taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut book = VecBook(vec![ask(1, 10, 5, maker), ask(2, 10, 5, maker), ask(3, 12, 5, maker)]);
+        let mut sink = Recorder::default();
+        let out = BinaryClobEngine
+            .match_order(&mut book, &mut sink, &bid(taker, 11, 12), &params(OrderType::Limit))
+            .unwrap();
+        assert_eq!(sink.fills, vec![(1, 5, 10), (2, 5, 10)]);
+        assert_eq!(out, MatchOutcome { price_ticks: 11, base_qty: 2, rests: true, match_limit_reached: false });
+        assert_eq!(book.0.len(), 1);
+    }
+
+    #[test]
+    fn immediate_or_cancel_drops_the_residual() {
+        let (maker, taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut book = VecBook(vec![ask(1, 10, 5, maker)]);
+        let mut sink = Recorder::default();
+        let out = BinaryClobEngine
+            .match_order(&mut book, &mut sink, &bid(taker, 10, 8), &params(OrderType::ImmediateOrCancel))
+            .unwrap();
+        assert_eq!(out.base_qty, 3);
+        assert!(!out.rests);
+    }
+
+    #[test]
+    fn prunes_expired_makers_and_respects_the_match_limit() {
+        let (maker, taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let stale = SlabNode { expires_at: 50, ..ask(1, 10, 5, maker) };
+        let mut book = VecBook(vec![stale, ask(2, 10, 5, maker), ask(3, 10, 5, maker)]);
+        let mut sink = Recorder::default();
+        let p = MatchParams { match_limit: 2, ..params(OrderType::Limit) };
+        let out = BinaryClobEngine.match_order(&mut book, &mut sink, &bid(taker, 10, 15), &p).unwrap();
+        assert_eq!(sink.expired, vec![1]);
+        assert_eq!(sink.fills, vec![(2, 5, 10)]);
+        // Budget spent while order 3 still crosses: the residual must not rest.
+        assert_eq!(out, MatchOutcome { price_ticks: 10, base_qty: 10, rests: false, match_limit_reached: true });
+    }
+
+    #[test]
+    fn self_trade_behaviors() {
+        let me = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let book = || VecBook(vec![ask(1, 10, 5, me), ask(2, 10, 5, other)]);
+
+        let mut sink = Recorder::default();
+        let p = MatchParams { self_trade_behavior: SelfTradeBehavior::CancelMaker, ..params(OrderType::Limit) };
+        let out = BinaryClobEngine.match_order(&mut book(), &mut sink, &bid(me, 10, 5), &p).unwrap();
+        assert_eq!((sink.self_trades, sink.fills, out.base_qty), (vec![(1, 5)], vec![(2, 5, 10)], 0));
+
+        let mut sink = Recorder::default();
+        let p = MatchParams { self_trade_behavior: SelfTradeBehavior::CancelTaker, ..params(OrderType::Limit) };
+        let out = BinaryClobEngine.match_order(&mut book(), &mut sink, &bid(me, 10, 5), &p).unwrap();
+        assert!(sink.fills.is_empty() && !out.rests);
+
+        let p = MatchParams { self_trade_behavior: SelfTradeBehavior::AbortTransaction, ..params(OrderType::Limit) };
+        assert!(BinaryClobEngine.match_order(&mut book(), &mut Recorder::default(), &bid(me, 10, 5), &p).is_err());
+    }
+
+    #[test]
+    fn market_buys_spend_no_more_than_the_quote_limit_with_fees() {
+        let (a, b, c, taker) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let p = MatchParams { quote_limit: 1_000, taker_fee_bps: 100, ..params(OrderType::Market) };
+        let spent = |fills: &[(u64, u64, u64)]| -> u64 {
+            fills.iter().map(|&(_, q, px)| p.lots.quote_with_fee(q, px, p.taker_fee_bps).unwrap()).sum()
+        };
+
+        // 99 at 10 is 990 plus a 10 fee; the 100th unit would overspend.
+        let mut book = VecBook(vec![ask(1, 10, 50, a), ask(2, 10, 50, b), ask(3, 10, 50, c)]);
+        let mut sink = Recorder::default();
+        BinaryClobEngine.match_order(&mut book, &mut sink, &bid(taker, 0, 150), &p).unwrap();
+        assert_eq!(sink.fills, vec![(1, 50, 10), (2, 49, 10)]);
+        assert!(spent(&sink.fills) <= p.quote_limit);
+
+        let mut book = VecBook(vec![ask(1, 10, 50, a), ask(2, 10, 50, b), ask(3, 10, 50, c)]);
+        let mut sink = Recorder::default();
+        ProRataEngine.match_order(&mut book, &mut sink, &bid(taker, 0, 150), &p).unwrap();
+        assert!(!sink.fills.is_empty());
+        assert!(spent(&sink.fills) <= p.quote_limit);
+    }
+
+    #[test]
+    fn pro_rata_shares_round_down_then_go_to_the_oldest() {
+        assert_eq!(pro_rata(20, &[10, 10, 20]), vec![5, 5, 10]);
+        assert_eq!(pro_rata(25, &[10, 10, 20]), vec![7, 6, 12]);
+        assert_eq!(pro_rata(2, &[1, 1, 1]), vec![1, 1, 0]);
+        assert_eq!(pro_rata(3, &[1, 1, 1]), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn pro_rata_splits_a_level_by_size() {
+        let (a, b, c, taker) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut book = VecBook(vec![ask(1, 10, 100, a), ask(2, 10, 100, b), ask(3, 10, 200, c), ask(4, 11, 100, a)]);
+        let mut sink = Recorder::default();
+        let p = MatchParams { min_base_qty: 10, ..params(OrderType::Limit) };
+        let out = ProRataEngine.match_order(&mut book, &mut sink, &bid(taker, 11, 250), &p).unwrap();
+        assert_eq!(sink.fills, vec![(1, 70, 10), (2, 60, 10), (3, 120, 10)]);
+        assert_eq!(out, MatchOutcome { price_ticks: 11, base_qty: 0, rests: false, match_limit_reached: false });
+
+        // The rest of the level, then the next one.
+        let mut sink = Recorder::default();
+        let out = ProRataEngine.match_order(&mut book, &mut sink, &bid(taker, 11, 200), &p).unwrap();
+        assert_eq!(sink.fills, vec![(1, 30, 10), (2, 40, 10), (3, 80, 10), (4, 50, 11)]);
+        assert_eq!(out.base_qty, 0);
+        assert_eq!(book.0[0].base_qty, 50);
+    }
+
+    #[test]
+    fn pro_rata_skips_own_and_expired_orders() {
+        let (me, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let stale = SlabNode { expires_at: 50, ..ask(2, 10, 100, other) };
+        let mut book = VecBook(vec![ask(1, 10, 100, other), stale, ask(3, 10, 100, me), ask(4, 10, 100, other)]);
+        let mut sink = Recorder::default();
+        let p = MatchParams { self_trade_behavior: SelfTradeBehavior::CancelMaker, ..params(OrderType::Limit) };
+        let out = ProRataEngine.match_order(&mut book, &mut sink, &bid(me, 10, 100), &p).unwrap();
+        assert_eq!(sink.expired, vec![2]);
+        assert_eq!(sink.self_trades, vec![(3, 100)]);
+        assert_eq!(sink.fills, vec![(1, 50, 10), (4, 50, 10)]);
+        assert_eq!(out.base_qty, 0);
+    }
+
+    #[test]
+    fn post_only_slides_behind_the_best() {
+        let (maker, taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut book = VecBook(vec![ask(1, 10, 5, maker)]);
+        let p = MatchParams { tick_size: 2, ..params(OrderType::PostOnly) };
+        assert!(BinaryClobEngine.match_order(&mut book, &mut Recorder::default(), &bid(taker, 12, 5), &p).is_err());
+
+        let p = MatchParams { order_type: OrderType::PostOnlySlide, ..p };
+        let mut sink = Recorder::default();
+        let out = BinaryClobEngine.match_order(&mut book, &mut sink, &bid(taker, 12, 5), &p).unwrap();
+        assert!(sink.fills.is_empty());
+        assert_eq!(out, MatchOutcome { price_ticks: 8, base_qty: 5, rests: true, match_limit_reached: false });
+    }
+
+    #[derive(Default)]
+    struct Crosses {
+        trades: Vec<(u64, u64, u64, u64)>,
+        /// Bid and ask size each cross saw.
+        sizes: Vec<(u64, u64)>,
+        expired: Vec<(u64, bool)>,
+    }
+
+    impl AuctionSink for Crosses {
+        fn on_cross(&mut self, bid: &SlabNode, ask: &SlabNode, qty: u64, price_ticks: u64) -> Result<()> {
+            self.trades.push((bid.order_id, ask.order_id, qty, price_ticks));
+            self.sizes.push((bid.base_qty, ask.base_qty));
+            Ok(())
+        }
+        fn on_expired(&mut self, node: &SlabNode, is_bid: bool) -> Result<()> {
+            self.expired.push((node.order_id, is_bid));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn clearing_price_maximises_volume_then_takes_the_middle() {
+        let o = Pubkey::new_unique();
+        let bids = [ask(3, 60, 100, o), ask(4, 45, 100, o)];
+        let asks = [ask(1, 40, 100, o), ask(2, 50, 100, o)];
+        assert_eq!(clearing_price(&bids, &asks, 10), Some(50));
+        assert_eq!(clearing_price(&bids, &asks, 20), Some(40));
+        assert_eq!(clearing_price(&bids[1..], &asks[1..], 1), None);
+
+        // 45 trades 150 against 100 anywhere else.
+        let bids = [ask(3, 60, 100, o), ask(4, 45, 100, o)];
+        let asks = [ask(1, 40, 100, o), ask(2, 45, 50, o)];
+        assert_eq!(clearing_price(&bids, &asks, 5), Some(45));
+    }
+
+    #[test]
+    fn auction_trades_every_cross_at_one_price() {
+        let (b, s) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let stale = SlabNode { expires_at: 50, ..ask(5, 70, 100, b) };
+        let mut bids = VecBook(vec![stale, ask(3, 60, 100, b), ask(4, 45, 100, b)]);
+        let mut asks = VecBook(vec![ask(1, 40, 60, s), ask(2, 50, 100, s)]);
+        let mut sink = Crosses::default();
+        let out = BatchAuctionEngine.run_auction(&mut bids, &mut asks, &mut sink, 10, 100, 32).unwrap();
+        assert_eq!(out, AuctionOutcome { price_ticks: Some(50), fills: 2, cleared: true });
+        assert_eq!(sink.expired, vec![(5, true)]);
+        assert_eq!(sink.trades, vec![(3, 1, 60, 50), (3, 2, 40, 50)]);
+        // The second cross sees what the first left of bid 3.
+        assert_eq!(sink.sizes, vec![(100, 60), (40, 100)]);
+        assert_eq!(bids.0.iter().map(|n| n.order_id).collect::<Vec<_>>(), vec![4]);
+        assert_eq!(asks.0[0].base_qty, 60);
+    }
+
+    #[test]
+    fn auction_stops_at_max_fills_and_stays_due() {
+        let (b, s) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut bids = VecBook(vec![ask(3, 60, 100, b)]);
+        let mut asks = VecBook(vec![ask(1, 40, 50, s), ask(2, 50, 50, s)]);
+        let mut sink = Crosses::default();
+        let out = BatchAuctionEngine.run_auction(&mut bids, &mut asks, &mut sink, 10, 100, 1).unwrap();
+        assert_eq!(out, AuctionOutcome { price_ticks: Some(50), fills: 1, cleared: false });
+
+        let out = BatchAuctionEngine.run_auction(&mut bids, &mut asks, &mut sink, 10, 100, 1).unwrap();
+        assert_eq!(out.fills, 1);
+        assert!(out.cleared);
+        assert!(bids.0.is_empty() && asks.0.is_empty());
+    }
+
+    #[test]
+    fn auction_never_crosses_an_account_with_itself() {
+        let (me, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // My ask is first in line for my bid; the bid skips it and takes the other's.
+        let mut bids = VecBook(vec![ask(3, 60, 100, me)]);
+        let mut asks = VecBook(vec![ask(1, 40, 100, me), ask(2, 40, 100, other)]);
+        let mut sink = Crosses::default();
+        let out = BatchAuctionEngine.run_auction(&mut bids, &mut asks, &mut sink, 10, 100, 32).unwrap();
+        assert_eq!(sink.trades, vec![(3, 2, 100, 50)]);
+        assert!(out.cleared);
+        assert_eq!(asks.0.iter().map(|n| n.order_id).collect::<Vec<_>>(), vec![1]);
+
+        // Only my own orders cross: nothing trades and the auction counts as cleared.
+        let mut bids = VecBook(vec![ask(4, 60, 100, me)]);
+        let mut sink = Crosses::default();
+        let out = BatchAuctionEngine.run_auction(&mut bids, &mut asks, &mut sink, 10, 100, 32).unwrap();
+        assert!(sink.trades.is_empty());
+        assert_eq!((out.fills, out.cleared), (0, true));
+    }
 }
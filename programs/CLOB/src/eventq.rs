@@ -0,0 +1,217 @@
+//! The event queue: a ring of fixed-size slots after the blob header.
+//!
+//! `head` is the slot of the oldest event and `count` how many follow it; positions wrap
+//! at the blob capacity (the market's `eventq_capacity`). Every push takes the next
+//! `seq_num`, which is stored with the event so consumers can resume where they left
+//! off and indexers can spot gaps. The account grows lazily, so a push may target a slot
+//! past the allocated length; callers grow it first (see `grow_target`).
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use crate::state::{Blob, Event, BLOB_MAGIC};
+
+/// Slots one growth step adds: as many as fit in a single realloc.
+pub const GROW_SLOTS: usize = MAX_PERMITTED_DATA_INCREASE / QueuedEvent::LEN;
+
+#[repr(C)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct EventQueueHeader {
+    pub head: u32,
+    pub count: u32,
+    /// Sequence number of the next pushed event.
+    pub seq_num: u64,
+}
+impl EventQueueHeader {
+    pub const LEN: usize = 4 + 4 + 8;
+}
+
+/// One slot: the event and the sequence number it was pushed with.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct QueuedEvent {
+    pub seq_num: u64,
+    pub event: Event,
+}
+impl QueuedEvent {
+    pub const LEN: usize = 8 + Event::LEN;
+}
+
+#[error_code]
+pub enum EventQueueError {
+    #[msg("invalid event queue header")] InvalidHeader,
+    #[msg("event queue full")] Full,
+    #[msg("event slot not allocated")] NotAllocated,
+    #[msg("fewer events queued than requested")] Underflow,
+}
+
+pub fn region_len_for(slots: u32) -> usize {
+    EventQueueHeader::LEN + QueuedEvent::LEN * slots as usize
+}
+
+/// Slots an account of `total_len` bytes has room for.
+pub fn allocated_slots(total_len: usize) -> usize {
+    total_len.saturating_sub(Blob::LEN + EventQueueHeader::LEN) / QueuedEvent::LEN
+}
+
+fn header_off() -> usize { Blob::LEN }
+fn slot_off(idx: usize) -> usize { Blob::LEN + EventQueueHeader::LEN + idx * QueuedEvent::LEN }
+
+/// Ring size, from the blob header.
+pub fn capacity(ai: &AccountInfo<'_>) -> Result<usize> {
+    let data = ai.try_borrow_data()?;
+    let mut rd: &[u8] = &data;
+    let b = Blob::deserialize(&mut rd)?;
+    require!(b.magic == BLOB_MAGIC, EventQueueError::InvalidHeader);
+    Ok(b.capacity as usize)
+}
+
+pub fn load_header(ai: &AccountInfo<'_>) -> Result<EventQueueHeader> {
+    let data = ai.try_borrow_data()?;
+    require!(data.len() >= Blob::LEN + EventQueueHeader::LEN, EventQueueError::InvalidHeader);
+    let mut rd: &[u8] = &data[header_off()..header_off() + EventQueueHeader::LEN];
+    Ok(EventQueueHeader::deserialize(&mut rd)?)
+}
+
+fn store_header(ai: &AccountInfo<'_>, h: &EventQueueHeader) -> Result<()> {
+    let mut data = ai.try_borrow_mut_data()?;
+    let start = header_off();
+    let mut cur = std::io::Cursor::new(&mut data[start..start + EventQueueHeader::LEN]);
+    h.serialize(&mut cur)?;
+    Ok(())
+}
+
+fn read_slot(ai: &AccountInfo<'_>, idx: usize) -> Result<QueuedEvent> {
+    let data = ai.try_borrow_data()?;
+    let off = slot_off(idx);
+    require!(off + QueuedEvent::LEN <= data.len(), EventQueueError::NotAllocated);
+    let mut rd: &[u8] = &data[off..off + QueuedEvent::LEN];
+    Ok(QueuedEvent::deserialize(&mut rd)?)
+}
+
+fn write_slot(ai: &AccountInfo<'_>, idx: usize, ev: &QueuedEvent) -> Result<()> {
+    let mut data = ai.try_borrow_mut_data()?;
+    let off = slot_off(idx);
+    require!(off + QueuedEvent::LEN <= data.len(), EventQueueError::NotAllocated);
+    let mut cur = std::io::Cursor::new(&mut data[off..off + QueuedEvent::LEN]);
+    ev.serialize(&mut cur)?;
+    Ok(())
+}
+
+/// Empty queue starting at sequence number 0.
+pub fn init(ai: &AccountInfo<'_>) -> Result<()> {
+    store_header(ai, &EventQueueHeader::default())
+}
+
+/// Ring position of the event `i` places after the head.
+fn slot_of(h: &EventQueueHeader, cap: usize, i: usize) -> usize {
+    (h.head as usize + i) % cap
+}
+
+/// Slots that must be allocated before `extra` more events can be pushed, or `None`
+/// when they would not fit in the ring.
+pub fn slots_for_pushes(h: &EventQueueHeader, cap: usize, extra: usize) -> Option<usize> {
+    if h.count as usize + extra > cap {
+        return None;
+    }
+    if extra == 0 {
+        return Some(0);
+    }
+    let start = slot_of(h, cap, h.count as usize);
+    // Wrapping past the end reuses slots that were allocated on the way there.
+    Some(if start + extra > cap { cap } else { start + extra })
+}
+
+/// Slots to have allocated before `extra` more events can be pushed, given `allocated`
+/// now, or `None` when they would not fit in the ring. Grows by `GROW_SLOTS` steps so
+/// each resize stays within what the runtime allows.
+pub fn grow_target(h: &EventQueueHeader, cap: usize, allocated: usize, extra: usize) -> Option<usize> {
+    let need = slots_for_pushes(h, cap, extra)?;
+    if need <= allocated {
+        return Some(allocated);
+    }
+    Some(cap.min(allocated + (need - allocated).div_ceil(GROW_SLOTS) * GROW_SLOTS))
+}
+
+/// Appends `ev` and returns its sequence number.
+pub fn push(ai: &AccountInfo<'_>, ev: &Event) -> Result<u64> {
+    let cap = capacity(ai)?;
+    let mut h = load_header(ai)?;
+    require!((h.count as usize) < cap, EventQueueError::Full);
+    let seq_num = h.seq_num;
+    write_slot(ai, slot_of(&h, cap, h.count as usize), &QueuedEvent { seq_num, event: *ev })?;
+    h.count += 1;
+    h.seq_num = h.seq_num.checked_add(1).ok_or(EventQueueError::InvalidHeader)?;
+    store_header(ai, &h)?;
+    Ok(seq_num)
+}
+
+/// The event `i` places after the oldest one.
+pub fn peek(ai: &AccountInfo<'_>, i: usize) -> Result<QueuedEvent> {
+    let cap = capacity(ai)?;
+    let h = load_header(ai)?;
+    require!(i < h.count as usize, EventQueueError::Underflow);
+    read_slot(ai, slot_of(&h, cap, i))
+}
+
+/// Drops the `n` oldest events.
+pub fn pop_front(ai: &AccountInfo<'_>, n: usize) -> Result<()> {
+    if n == 0 {
+        return Ok(());
+    }
+    let cap = capacity(ai)?;
+    let mut h = load_header(ai)?;
+    require!(n <= h.count as usize, EventQueueError::Underflow);
+    h.head = slot_of(&h, cap, n) as u32;
+    h.count -= n as u32;
+    store_header(ai, &h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdr(head: u32, count: u32) -> EventQueueHeader {
+        EventQueueHeader { head, count, seq_num: 0 }
+    }
+
+    #[test]
+    fn pushes_need_slots_up_to_the_write_position() {
+        assert_eq!(slots_for_pushes(&hdr(0, 0), 8, 3), Some(3));
+        assert_eq!(slots_for_pushes(&hdr(2, 3), 8, 2), Some(7));
+        // The ring wraps: everything up to the end is needed, nothing more.
+        assert_eq!(slots_for_pushes(&hdr(5, 2), 8, 2), Some(8));
+        assert_eq!(slots_for_pushes(&hdr(6, 3), 8, 1), Some(2));
+        assert_eq!(slots_for_pushes(&hdr(6, 3), 8, 5), Some(6));
+        assert_eq!(slots_for_pushes(&hdr(6, 3), 8, 6), None);
+        assert_eq!(slots_for_pushes(&hdr(0, 0), 0, 1), None);
+    }
+
+    #[test]
+    fn growth_steps_fit_one_realloc() {
+        // Push one event at a time well past two steps, growing whenever asked to.
+        let cap = 512;
+        let mut allocated = 4;
+        let mut grown = 0;
+        for count in 0..3 * GROW_SLOTS as u32 + 5 {
+            let want = grow_target(&hdr(0, count), cap, allocated, 1).unwrap();
+            assert!((want - allocated) * QueuedEvent::LEN <= MAX_PERMITTED_DATA_INCREASE);
+            if want > allocated {
+                grown += 1;
+            }
+            allocated = want;
+            assert!(allocated > count as usize);
+        }
+        assert_eq!(grown, 4);
+        // Never past the ring, and nothing when the ring is full.
+        assert_eq!(grow_target(&hdr(0, 500), cap, 500, 3), Some(cap));
+        assert_eq!(grow_target(&hdr(0, 512), cap, cap, 1), None);
+    }
+
+    #[test]
+    fn slots_wrap_at_capacity() {
+        assert_eq!(slot_of(&hdr(6, 0), 8, 0), 6);
+        assert_eq!(slot_of(&hdr(6, 0), 8, 3), 1);
+        assert_eq!(allocated_slots(Blob::LEN + region_len_for(5)), 5);
+        assert_eq!(allocated_slots(Blob::LEN + region_len_for(5) - 1), 4);
+        assert_eq!(allocated_slots(3), 0);
+    }
+}
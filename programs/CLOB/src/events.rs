@@ -0,0 +1,153 @@
+//! Anchor events logged for indexers.
+//!
+//! Every event names its market and carries `seq_num`, taken from the market's
+//! `event_seq`: it counts up by one per logged event, so a subscriber can order them and
+//! spot any it missed. These are records only; the program never reads them back.
+
+use anchor_lang::prelude::*;
+use crate::state::{Market, OutReason};
+
+/// Hands out the market's event sequence numbers for one instruction.
+pub struct EventLog<'a> {
+    pub market: Pubkey,
+    seq: &'a mut u64,
+}
+
+impl<'a> EventLog<'a> {
+    pub fn new(market: &'a mut Account<'_, Market>) -> Self {
+        Self { market: market.key(), seq: &mut market.event_seq }
+    }
+
+    pub fn next_seq(&mut self) -> u64 {
+        let seq = *self.seq;
+        *self.seq = seq.wrapping_add(1);
+        seq
+    }
+}
+
+#[event]
+pub struct MarketCreated {
+    pub market: Pubkey,
+    pub authority: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub tick_size: u64,
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub seq_num: u64,
+}
+
+/// An order was accepted. `filled_qty` traded on arrival and `resting_qty` went on the
+/// book; whatever is left of `base_qty` was dropped (IOC, market, self-trade or the
+/// match limit). Logged once matching is done, so it follows the `OrderFilled`s of its
+/// own fills.
+#[event]
+pub struct OrderPlaced {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub owner_oo: Pubkey,
+    pub order_id: u64,
+    pub client_order_id: u64,
+    /// 0 = bid, 1 = ask.
+    pub side: u8,
+    /// Limit price; where a post-only slide moved it, the price it rests at.
+    pub price_ticks: u64,
+    pub base_qty: u64,
+    pub filled_qty: u64,
+    pub resting_qty: u64,
+    /// The remainder still crossed when the match limit ran out and was dropped.
+    pub match_limit_reached: bool,
+    pub seq_num: u64,
+}
+
+/// A resting order lost `base_qty` without trading: cancelled by its owner, expired, or
+/// decremented by a self-trade.
+#[event]
+pub struct OrderCancelled {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub owner_oo: Pubkey,
+    pub order_id: u64,
+    pub client_order_id: u64,
+    pub side: u8,
+    pub price_ticks: u64,
+    pub base_qty: u64,
+    pub reason: OutReason,
+    pub seq_num: u64,
+}
+
+/// A resting order was amended. `requeued` is false when it only shrank in place and
+/// kept its time priority.
+#[event]
+pub struct OrderAmended {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub owner_oo: Pubkey,
+    pub order_id: u64,
+    pub client_order_id: u64,
+    pub side: u8,
+    pub old_price_ticks: u64,
+    pub new_price_ticks: u64,
+    /// Resting size before and after, hidden iceberg size included.
+    pub old_base_qty: u64,
+    pub new_base_qty: u64,
+    pub requeued: bool,
+    pub seq_num: u64,
+}
+
+/// One trade, logged when it matched. Balances move when the queued fill settles.
+#[event]
+pub struct OrderFilled {
+    pub market: Pubkey,
+    pub maker_owner: Pubkey,
+    pub taker_owner: Pubkey,
+    pub maker_oo: Pubkey,
+    pub taker_oo: Pubkey,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+    pub taker_side: u8,
+    pub price_ticks: u64,
+    pub base_qty: u64,
+    /// Whether the maker order left the book with this fill.
+    pub maker_done: bool,
+    pub seq_num: u64,
+}
+
+#[event]
+pub struct EventsSettled {
+    pub market: Pubkey,
+    pub cranker: Pubkey,
+    /// Queue sequence number of the first settled event.
+    pub first_event_seq: u64,
+    pub events: u32,
+    /// Events still queued.
+    pub remaining: u32,
+    pub crank_reward: u64,
+    pub seq_num: u64,
+}
+
+#[event]
+pub struct Deposit {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub seq_num: u64,
+}
+
+#[event]
+pub struct Withdraw {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub seq_num: u64,
+}
+
+#[event]
+pub struct FeesSwept {
+    pub market: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub seq_num: u64,
+}
@@ -2,6 +2,7 @@ use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
 
 use crate::state::{Market, UserBalance};
+use crate::events::{Deposit, EventLog, Withdraw};
 
 #[derive(Accounts)]
 pub struct InitVaults<'info> {
@@ -65,6 +66,8 @@ pub fn init_user_balance_handler(ctx: Context<InitUserBalance>) -> Result<()> {
     ub.market = ctx.accounts.market.key();
     ub.base_free = 0;
     ub.quote_free = 0;
+    ub.base_locked = 0;
+    ub.quote_locked = 0;
     ub.bump = ctx.bumps.ub;
     Ok(())
 }
@@ -99,6 +102,15 @@ pub fn deposit_base_handler(ctx: Context<DepositBase>, amount: u64) -> Result<()
 
     let ub = &mut ctx.accounts.ub;
     ub.base_free = ub.base_free.checked_add(amount).ok_or(BalancesError::Overflow)?;
+
+    let mut log = EventLog::new(&mut ctx.accounts.market);
+    emit!(Deposit {
+        market: log.market,
+        owner: ctx.accounts.user.key(),
+        mint: ctx.accounts.base_vault.mint,
+        amount,
+        seq_num: log.next_seq(),
+    });
     Ok(())
 }
 
@@ -132,6 +144,15 @@ pub fn deposit_quote_handler(ctx: Context<DepositQuote>, amount: u64) -> Result<
 
     let ub = &mut ctx.accounts.ub;
     ub.quote_free = ub.quote_free.checked_add(amount).ok_or(BalancesError::Overflow)?;
+
+    let mut log = EventLog::new(&mut ctx.accounts.market);
+    emit!(Deposit {
+        market: log.market,
+        owner: ctx.accounts.user.key(),
+        mint: ctx.accounts.quote_vault.mint,
+        amount,
+        seq_num: log.next_seq(),
+    });
     Ok(())
 }
 
@@ -174,6 +195,15 @@ pub fn withdraw_base_handler(ctx: Context<WithdrawBase>, amount: u64) -> Result<
 
     let ub = &mut ctx.accounts.ub;
     ub.base_free = ub.base_free.checked_sub(amount).ok_or(BalancesError::Overflow)?;
+
+    let mut log = EventLog::new(&mut ctx.accounts.market);
+    emit!(Withdraw {
+        market: log.market,
+        owner: ctx.accounts.user.key(),
+        mint: ctx.accounts.base_vault.mint,
+        amount,
+        seq_num: log.next_seq(),
+    });
     Ok(())
 }
 
@@ -216,6 +246,15 @@ pub fn withdraw_quote_handler(ctx: Context<WithdrawQuote>, amount: u64) -> Resul
 
     let ub = &mut ctx.accounts.ub;
     ub.quote_free = ub.quote_free.checked_sub(amount).ok_or(BalancesError::Overflow)?;
+
+    let mut log = EventLog::new(&mut ctx.accounts.market);
+    emit!(Withdraw {
+        market: log.market,
+        owner: ctx.accounts.user.key(),
+        mint: ctx.accounts.quote_vault.mint,
+        amount,
+        seq_num: log.next_seq(),
+    });
     Ok(())
 }
 
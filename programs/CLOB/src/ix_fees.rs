@@ -0,0 +1,101 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+
+use crate::state::Market;
+use crate::events::{EventLog, FeesSwept};
+
+#[derive(Accounts)]
+pub struct SetFeeConfig<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, has_one = authority)]
+    pub market: Account<'info, Market>,
+    /// CHECK: key-only; signs future sweeps.
+    pub fee_authority: UncheckedAccount<'info>,
+    #[account(constraint = treasury.mint == market.quote_mint @ FeesError::InvalidTreasury)]
+    pub treasury: Account<'info, TokenAccount>,
+}
+
+/// Sets who may sweep fees and where they go. Only the market authority can change it.
+pub fn set_fee_config_handler(ctx: Context<SetFeeConfig>) -> Result<()> {
+    let m = &mut ctx.accounts.market;
+    m.fee_authority = ctx.accounts.fee_authority.key();
+    m.treasury = ctx.accounts.treasury.key();
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetCrankReward<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, has_one = authority)]
+    pub market: Account<'info, Market>,
+}
+
+/// Sets the per-event quote reward `settle_events` pays its caller out of accrued fees.
+pub fn set_crank_reward_handler(ctx: Context<SetCrankReward>, reward: u64) -> Result<()> {
+    ctx.accounts.market.crank_reward = reward;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SweepFees<'info> {
+    pub fee_authority: Signer<'info>,
+    #[account(mut, has_one = fee_authority @ FeesError::Unauthorized, has_one = treasury @ FeesError::InvalidTreasury)]
+    pub market: Account<'info, Market>,
+    /// CHECK: PDA used as token owner; validated by seeds
+    #[account(seeds = [b"kerdos_vault_auth", market.key().as_ref()], bump)]
+    pub vault_auth: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"kerdos_vault_quote", market.key().as_ref()], bump)]
+    pub quote_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub treasury: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Moves `fees_accrued` from the quote vault to the treasury and resets the counter.
+pub fn sweep_fees_handler(ctx: Context<SweepFees>) -> Result<()> {
+    let amount = ctx.accounts.market.fees_accrued;
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let market_key = ctx.accounts.market.key();
+    let bump = ctx.bumps.vault_auth;
+    let seeds: &[&[u8]] = &[b"kerdos_vault_auth", market_key.as_ref(), &[bump]];
+    let signer: &[&[&[u8]]] = &[seeds];
+
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.quote_vault.to_account_info(),
+        to: ctx.accounts.treasury.to_account_info(),
+        authority: ctx.accounts.vault_auth.to_account_info(),
+    };
+    let cpi = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
+    token::transfer(cpi, amount)?;
+
+    ctx.accounts.market.fees_accrued = 0;
+    let mut log = EventLog::new(&mut ctx.accounts.market);
+    emit!(FeesSwept {
+        market: log.market,
+        treasury: ctx.accounts.treasury.key(),
+        amount,
+        seq_num: log.next_seq(),
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ClaimableFees<'info> {
+    pub market: Account<'info, Market>,
+}
+
+/// Quote fees a sweep would move right now. Read-only; meant for simulation.
+pub fn claimable_fees_handler(ctx: Context<ClaimableFees>) -> Result<u64> {
+    Ok(ctx.accounts.market.fees_accrued)
+}
+
+#[error_code]
+pub enum FeesError {
+    #[msg("signer is not the market's fee authority")]
+    Unauthorized,
+    #[msg("treasury must be the configured quote token account")]
+    InvalidTreasury,
+}
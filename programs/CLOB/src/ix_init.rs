@@ -3,8 +3,12 @@ use anchor_lang::solana_program::rent::Rent;
 use anchor_lang::system_program as sys;
 use crate::domain::{InitParams, BlobKind};
 use crate::domain::sizing;
-use crate::state::{Market, Blob, BLOB_MAGIC, FillEvent};
+use crate::state::{Market, Blob, BLOB_MAGIC};
 use crate::slab;
+use crate::eventq;
+use crate::lots;
+use crate::events::{EventLog, MarketCreated};
+use anchor_spl::token::Mint;
 
 const BOOT_NODES: u32 = 64;
 const BOOT_EVENTS: u32 = 128;
@@ -20,11 +24,11 @@ pub struct InitMarket<'info> {
     /// CHECK: External authority (key-only). Its Pubkey is persisted in `Market.authority`. Signature is enforced by business logic (e.g. `settle_events` checks it matches the signer).
     pub authority: UncheckedAccount<'info>,
 
-    /// CHECK: External SPL base mint (key-only). We never read/deserialize it; we just store the Pubkey and use it in seeds.
-    pub base_mint: UncheckedAccount<'info>,
+    /// SPL base mint; its decimals bound `base_lot_size`.
+    pub base_mint: Account<'info, Mint>,
 
-    /// CHECK: External SPL quote mint (key-only). We never read/deserialize it; we just store the Pubkey and use it in seeds.
-    pub quote_mint: UncheckedAccount<'info>,
+    /// SPL quote mint; its decimals bound `quote_lot_size`.
+    pub quote_mint: Account<'info, Mint>,
 
     #[account(
         init,
@@ -51,6 +55,15 @@ pub struct InitMarket<'info> {
 }
 
 pub fn init_market_handler(ctx: Context<InitMarket>, params: InitParams) -> Result<()> {
+    require!(params.tick_size > 0 && params.min_base_qty > 0, InitError::InvalidMarketParams);
+    require_keys_eq!(params.base_mint, ctx.accounts.base_mint.key(), InitError::MintMismatch);
+    require_keys_eq!(params.quote_mint, ctx.accounts.quote_mint.key(), InitError::MintMismatch);
+    let base_decimals = ctx.accounts.base_mint.decimals;
+    let quote_decimals = ctx.accounts.quote_mint.decimals;
+    lots::validate_lot_size(params.base_lot_size, base_decimals)?;
+    lots::validate_lot_size(params.quote_lot_size, quote_decimals)?;
+    let (maker_bps, taker_bps, max_bps) = (params.maker_fee_bps as i64, params.taker_fee_bps as i64, lots::BPS_DENOM as i64);
+    require!(taker_bps <= max_bps && maker_bps <= max_bps && -maker_bps <= taker_bps, InitError::InvalidFees);
     assert_preinit_or_owned(&ctx.accounts.bids.to_account_info())?;
     assert_preinit_or_owned(&ctx.accounts.asks.to_account_info())?;
     assert_preinit_or_owned(&ctx.accounts.event_queue.to_account_info())?;
@@ -79,7 +92,7 @@ pub fn init_market_handler(ctx: Context<InitMarket>, params: InitParams) -> Resu
     let boot_evq_cap  = core::cmp::min(params.event_queue_capacity, BOOT_EVENTS);
     let bids_init_len = core::cmp::min(bids_len, Blob::LEN + slab::SlabHeader::LEN + slab::SlabNode::LEN * (boot_bids_cap as usize));
     let asks_init_len = core::cmp::min(asks_len, Blob::LEN + slab::SlabHeader::LEN + slab::SlabNode::LEN * (boot_asks_cap as usize));
-    let evq_init_len  = core::cmp::min(evq_len,  Blob::LEN + FillEvent::LEN * (boot_evq_cap as usize));
+    let evq_init_len  = core::cmp::min(evq_len,  Blob::LEN + eventq::region_len_for(boot_evq_cap));
 
     create_pda_zero(
         &ctx.accounts.payer,
@@ -100,25 +113,25 @@ pub fn init_market_handler(ctx: Context<InitMarket>, params: InitParams) -> Resu
         &ctx.accounts.event_queue,
         &ctx.accounts.system_program,
         &[b"kerdos_eventq", ctx.accounts.market.key().as_ref(), &[evq_bump]],
-        Some(core::cmp::min(evq_init_len.max(Blob::LEN), MAX_INIT_ALLOC)),
+        Some(core::cmp::min(evq_init_len.max(Blob::LEN + eventq::EventQueueHeader::LEN), MAX_INIT_ALLOC)),
     )?;
 
     // Write headers for preallocated blobs. Slab init only spans the bytes currently available.
     let bids_boot_cap = slab::capacity_from_len(ctx.accounts.bids.data_len()).min(params.bids_capacity);
     let asks_boot_cap = slab::capacity_from_len(ctx.accounts.asks.data_len()).min(params.asks_capacity);
-    let evq_boot_cap  = ctx.accounts.event_queue.data_len().saturating_sub(Blob::LEN) / FillEvent::LEN;
+    let evq_boot_cap  = eventq::allocated_slots(ctx.accounts.event_queue.data_len());
     init_blob_full(
         &ctx.accounts.bids.to_account_info(),
         BlobKind::Bids,
         params.bids_capacity,
-        bids_boot_cap as u32,
+        bids_boot_cap,
         true,
     )?;
     init_blob_full(
         &ctx.accounts.asks.to_account_info(),
         BlobKind::Asks,
         params.asks_capacity,
-        asks_boot_cap as u32,
+        asks_boot_cap,
         true,
     )?;
     init_blob_full(
@@ -144,8 +157,16 @@ pub fn init_market_handler(ctx: Context<InitMarket>, params: InitParams) -> Resu
     m.event_queue     = ctx.accounts.event_queue.key();
     m.tick_size       = params.tick_size;
     m.min_base_qty    = params.min_base_qty;
-    m.fees_bps        = params.fees_bps;
+    m.base_lot_size   = params.base_lot_size;
+    m.quote_lot_size  = params.quote_lot_size;
+    m.base_decimals   = base_decimals;
+    m.quote_decimals  = quote_decimals;
+    m.maker_fee_bps   = params.maker_fee_bps;
+    m.taker_fee_bps   = params.taker_fee_bps;
     m.paused          = false;
+    m.engine_kind     = params.engine_kind;
+    m.auction_interval_slots = params.auction_interval_slots;
+    m.last_auction_slot      = Clock::get()?.slot;
     m.bump_market     = market_bump;
     m.bump_bids       = bids_bump;
     m.bump_asks       = asks_bump;
@@ -154,7 +175,25 @@ pub fn init_market_handler(ctx: Context<InitMarket>, params: InitParams) -> Resu
     m.asks_capacity   = params.asks_capacity;
     m.eventq_capacity = params.event_queue_capacity;
     m.fees_accrued    = 0;
-
+    m.rebates_paid    = 0;
+    m.fee_authority   = ctx.accounts.authority.key();
+    m.treasury        = Pubkey::default();
+    m.crank_reward    = 0;
+    m.crank_rewards_paid = 0;
+    m.next_order_id   = 0;
+    m.event_seq       = 0;
+
+    let mut log = EventLog::new(m);
+    emit!(MarketCreated {
+        market: log.market,
+        authority: ctx.accounts.authority.key(),
+        base_mint: params.base_mint,
+        quote_mint: params.quote_mint,
+        tick_size: params.tick_size,
+        base_lot_size: params.base_lot_size,
+        quote_lot_size: params.quote_lot_size,
+        seq_num: log.next_seq(),
+    });
     Ok(())
 }
 
@@ -180,7 +219,7 @@ pub struct GrowBlob<'info> {
 }
 
 pub fn grow_blob_handler(ctx: Context<GrowBlob>, params: crate::domain::GrowParams) -> Result<()> {
-    let step = core::cmp::max(1, core::cmp::min(params.step_bytes as usize, 10_240));
+    let step = (params.step_bytes as usize).clamp(1, 10_240);
     let m = &ctx.accounts.market;
     match params.which {
         0 => grow_one(&ctx.accounts.payer, &ctx.accounts.system_program, &ctx.accounts.bids.to_account_info(), BlobKind::Bids, sizing::bids_space(m.bids_capacity), m.bids_capacity, step)?,
@@ -222,9 +261,12 @@ fn grow_one<'info>(
                     slab::grow(target, cap_for_len)?;
                 }
             }
-        } else if matches!(kind, BlobKind::EventQueue) && new_len >= Blob::LEN {
-            // Rewrite header for event queue if missing.
-            write_blob_header(target, kind, cap)?;
+        } else if matches!(kind, BlobKind::EventQueue) {
+            // The queue header is written once, when the account first has room for it.
+            let hdr_end = Blob::LEN + eventq::EventQueueHeader::LEN;
+            if cur < hdr_end && new_len >= hdr_end {
+                eventq::init(target)?;
+            }
         }
     }
     Ok(())
@@ -252,7 +294,7 @@ pub fn write_blob_header<'info>(ai: &AccountInfo<'info>, kind: BlobKind, capacit
     use anchor_lang::AnchorSerialize;
     let blob = Blob { magic: BLOB_MAGIC, kind: kind as u8, capacity, used: 0 };
     let mut data_ref = ai.try_borrow_mut_data()?;
-    let data_slice: &mut [u8] = &mut *data_ref;
+    let data_slice: &mut [u8] = &mut data_ref;
     let mut cur = std::io::Cursor::new(data_slice);
     blob.serialize(&mut cur)?;
     Ok(())
@@ -293,6 +335,9 @@ fn init_blob_full<'info>(
     if init_slab {
         let cap_by_len = slab::capacity_from_len(have);
         slab::init(target, core::cmp::min(init_cap, cap_by_len))?;
+    } else if matches!(kind, BlobKind::EventQueue) {
+        require!(have >= Blob::LEN + eventq::EventQueueHeader::LEN, InitError::BadBlobSize);
+        eventq::init(target)?;
     }
     Ok(())
 }
@@ -305,6 +350,12 @@ pub enum InitError {
     BadBlobSize,
     #[msg("Requested capacity exceeds CPI allocation limit")]
     CapacityTooLarge,
+    #[msg("Tick size and minimum quantity must be non-zero")]
+    InvalidMarketParams,
+    #[msg("Mint account does not match params")]
+    MintMismatch,
+    #[msg("Fees exceed 100% or the maker rebate exceeds the taker fee")]
+    InvalidFees,
 }
 
 fn assert_preinit_or_owned(ai: &AccountInfo<'_>) -> Result<()> {
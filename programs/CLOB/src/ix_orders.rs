@@ -1,15 +1,18 @@
 use anchor_lang::prelude::*;
-use anchor_lang::system_program as sys;
 use anchor_lang::{AnchorDeserialize, AnchorSerialize};
-use crate::engine::{BinaryClobEngine, MatchingEngine};
-use crate::state::{Market, OpenOrdersLite, Blob, FillEvent, BLOB_MAGIC};
-use crate::domain::BlobKind;
+use crate::engine::{self, AuctionSink, BatchAuctionEngine, BookSide, MatchParams, MatchSink, MatchingEngine, TakerOrder};
+use crate::state::{Market, OpenOrdersLite, OrderSlot, MAX_OPEN_ORDERS, Blob, Event, FillEvent, OutEvent, OutReason, UserBalance, BLOB_MAGIC};
+use crate::domain::{BlobKind, EngineKind, OrderType, SelfTradeBehavior};
 use crate::ix_init::{ensure_funded_resize, write_blob_header};
 use crate::slab;
-use crate::slab::remove_by_oo as book_remove_oo;
+use crate::eventq;
+use crate::lots::{Collateral, FillFees, LotSizes};
+use crate::events::{EventLog, OrderAmended, OrderCancelled, OrderFilled, OrderPlaced};
 
-const EV_CHUNK: usize = 128;
 const BOOK_BOOT_NODES: u32 = 64;
+/// Makers a single order may touch when `PlaceOrderParams.match_limit` is 0. An order
+/// that reaches it stays under 600k compute units, the limit a taker should request.
+pub const DEFAULT_MATCH_LIMIT: u16 = 32;
 
 #[derive(Accounts)]
 pub struct PlaceOrder<'info> {
@@ -38,7 +41,11 @@ pub struct PlaceOrder<'info> {
         seeds = [b"kerdos_oo", market.key().as_ref(), payer.key().as_ref()],
         bump
     )]
-    pub oo: Account<'info, OpenOrdersLite>,
+    pub oo: Box<Account<'info, OpenOrdersLite>>,
+
+    /// Collateral for the order is reserved from here.
+    #[account(mut, seeds = [b"kerdos_user", market.key().as_ref(), payer.key().as_ref()], bump = ub.bump)]
+    pub ub: Box<Account<'info, UserBalance>>,
 
     pub system_program: Program<'info, System>,
 }
@@ -48,104 +55,251 @@ pub struct PlaceOrderParams {
     pub price_ticks: u64,
     pub base_qty: u64,
     pub side: u8,
-    pub lock_lamports: u64,
     pub max_slippage_ticks: u64,
+    pub client_order_id: u64,
+    pub order_type: OrderType,
+    /// Market orders only: maximum native quote spent by a buy, taker fees included, or
+    /// minimum received by a sell before fees.
+    pub quote_limit: u64,
+    pub self_trade_behavior: SelfTradeBehavior,
+    /// Unix timestamp at which a resting order stops trading; 0 = good-til-cancelled.
+    pub expires_at: i64,
+    /// Iceberg orders only: size shown on the book at a time; 0 shows the full size.
+    pub display_qty: u64,
+    /// Most makers this order may fill or prune before it stops matching; 0 = `DEFAULT_MATCH_LIMIT`.
+    /// A limit or post-only remainder that still crosses once the limit is reached is
+    /// cancelled rather than rested, and `OrderPlaced.match_limit_reached` says so.
+    pub match_limit: u16,
+}
+
+/// How a residual rests once matching is done.
+#[derive(Clone, Copy)]
+struct RestOpts {
+    expires_at: i64,
+    display_qty: u64,
 }
 
 pub fn place_order_handler(ctx: Context<PlaceOrder>, params: PlaceOrderParams) -> Result<()> {
-    let m = &ctx.accounts.market;
-    let eng = BinaryClobEngine;
+    let oo_bump = ctx.bumps.oo;
+    prepare_books(ctx.accounts, oo_bump)?;
+    place_one(ctx.accounts, params)
+}
 
-    require!(params.side == 0 || params.side == 1, OrdersError::InvalidSide);
-    require!(params.price_ticks <= u32::MAX as u64, OrdersError::PriceOutOfRange);
-    require!(params.base_qty % m.min_base_qty == 0, OrdersError::InvalidQtyStep);
+/// One step of a `batch_orders` call, applied in order.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub enum BatchOrderItem {
+    Cancel { order_id: u64 },
+    Place { params: PlaceOrderParams },
+}
 
-    eng.validate_tick(m.tick_size, params.price_ticks)?;
-    eng.validate_min_qty(m.min_base_qty, params.base_qty)?;
-    require!(!m.paused, OrdersError::Paused);
-    require!(params.lock_lamports > 0, OrdersError::InvalidAmount);
-
-    require_keys_eq!(m.bids,        ctx.accounts.bids.key(),        OrdersError::BadBookAccount);
-    require_keys_eq!(m.asks,        ctx.accounts.asks.key(),        OrdersError::BadBookAccount);
-    require_keys_eq!(m.event_queue, ctx.accounts.event_queue.key(), OrdersError::BadBookAccount);
-
-    validate_blob(&ctx.accounts.bids.to_account_info(), BlobKind::Bids)?;
-    validate_blob(&ctx.accounts.asks.to_account_info(), BlobKind::Asks)?;
-    validate_blob(&ctx.accounts.event_queue.to_account_info(), BlobKind::EventQueue)?;
-
-    if ctx.accounts.oo.active {
-        let refund = ctx.accounts.oo.locked_lamports;
-        let mut oo_ai = ctx.accounts.oo.to_account_info();
-        let mut payer_ai = ctx.accounts.payer.to_account_info();
-        require!(oo_ai.lamports() >= refund, OrdersError::InsufficientLamports);
-        **oo_ai.try_borrow_mut_lamports()? = oo_ai.lamports().checked_sub(refund).ok_or(OrdersError::Overflow)?;
-        **payer_ai.try_borrow_mut_lamports()? = payer_ai.lamports().checked_add(refund).ok_or(OrdersError::Overflow)?;
-        let removed = if ctx.accounts.oo.side == 0 {
-            book_remove_oo(&ctx.accounts.bids.to_account_info(), true, ctx.accounts.oo.key())?
-        } else {
-            book_remove_oo(&ctx.accounts.asks.to_account_info(), false, ctx.accounts.oo.key())?
+pub const MAX_BATCH_ITEMS: usize = 2 * MAX_OPEN_ORDERS;
+
+/// Runs cancels and places against one loaded market. Any failing item reverts the whole
+/// batch; its index is logged before the error is returned.
+pub fn batch_orders_handler(ctx: Context<PlaceOrder>, items: Vec<BatchOrderItem>) -> Result<()> {
+    require!(!items.is_empty() && items.len() <= MAX_BATCH_ITEMS, OrdersError::InvalidBatch);
+    let oo_bump = ctx.bumps.oo;
+    let accs = ctx.accounts;
+    prepare_books(accs, oo_bump)?;
+
+    for (i, item) in items.iter().enumerate() {
+        let res = match *item {
+            BatchOrderItem::Cancel { order_id } => match accs.oo.find_order(order_id) {
+                Some(slot) => cancel_slot(
+                    &mut accs.oo,
+                    &mut accs.ub,
+                    Collateral::of(&accs.market),
+                    &accs.bids.to_account_info(),
+                    &accs.asks.to_account_info(),
+                    &mut OutQueue {
+                        eventq_ai: &accs.event_queue.to_account_info(),
+                        payer: &accs.payer,
+                        sys_prog: &accs.system_program,
+                        evq_cap: accs.market.eventq_capacity as usize,
+                        log: EventLog::new(&mut accs.market),
+                    },
+                    slot,
+                ),
+                None => err!(OrdersError::NotActive),
+            },
+            BatchOrderItem::Place { params } => place_one(accs, params),
         };
-        if !removed {
-            msg!("OO cleanup: entry already absent from book");
+        if let Err(e) = res {
+            msg!("batch_orders: item {} failed", i);
+            return Err(e);
         }
-        let oo = &mut ctx.accounts.oo;
-        oo.locked_lamports = 0;
-        oo.active = false;
     }
+    Ok(())
+}
 
-    let cpi_accounts = sys::Transfer {
-        from: ctx.accounts.payer.to_account_info(),
-        to:   ctx.accounts.oo.to_account_info(),
-    };
-    let cpi = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
-    sys::transfer(cpi, params.lock_lamports)?;
-
-    let oo = &mut ctx.accounts.oo;
-    oo.user            = ctx.accounts.payer.key();
-    oo.market          = m.key();
-    oo.locked_lamports = params.lock_lamports;
-    oo.price_ticks     = params.price_ticks;
-    oo.base_qty        = params.base_qty;
-    oo.side            = params.side;
-    oo.active          = true;
-    oo.bump            = ctx.bumps.oo;
+/// Market-level checks and account setup shared by every order in an instruction.
+fn prepare_books(accs: &mut PlaceOrder<'_>, oo_bump: u8) -> Result<()> {
+    let m = &accs.market;
+    require!(!m.paused, OrdersError::Paused);
+
+    require_keys_eq!(m.bids,        accs.bids.key(),        OrdersError::BadBookAccount);
+    require_keys_eq!(m.asks,        accs.asks.key(),        OrdersError::BadBookAccount);
+    require_keys_eq!(m.event_queue, accs.event_queue.key(), OrdersError::BadBookAccount);
+
+    validate_blob(&accs.bids.to_account_info(), BlobKind::Bids)?;
+    validate_blob(&accs.asks.to_account_info(), BlobKind::Asks)?;
+    validate_blob(&accs.event_queue.to_account_info(), BlobKind::EventQueue)?;
+
+    let (bids_cap, asks_cap, evq_cap) = (m.bids_capacity, m.asks_capacity, m.eventq_capacity);
+
+    if accs.oo.user == Pubkey::default() {
+        let oo = &mut accs.oo;
+        oo.user   = accs.payer.key();
+        oo.market = accs.market.key();
+        oo.bump   = oo_bump;
+    }
+    require_keys_eq!(accs.oo.user, accs.payer.key(), OrdersError::Unauthorized);
+    require_keys_eq!(accs.ub.market, accs.market.key(), OrdersError::Unauthorized);
 
     ensure_book_boot(
-        &ctx.accounts.payer, &ctx.accounts.system_program,
-        &ctx.accounts.bids.to_account_info(), BlobKind::Bids, m.bids_capacity
+        &accs.payer, &accs.system_program,
+        &accs.bids.to_account_info(), BlobKind::Bids, bids_cap
     )?;
     ensure_book_boot(
-        &ctx.accounts.payer, &ctx.accounts.system_program,
-        &ctx.accounts.asks.to_account_info(), BlobKind::Asks, m.asks_capacity
+        &accs.payer, &accs.system_program,
+        &accs.asks.to_account_info(), BlobKind::Asks, asks_cap
     )?;
     ensure_eventq_min(
-        &ctx.accounts.payer, &ctx.accounts.system_program,
-        &ctx.accounts.event_queue.to_account_info(),
-        m.eventq_capacity, Blob::LEN + FillEvent::LEN * 4
+        &accs.payer, &accs.system_program,
+        &accs.event_queue.to_account_info(),
+        evq_cap, Blob::LEN + eventq::region_len_for(4)
     )?;
+    Ok(())
+}
+
+fn place_one(accs: &mut PlaceOrder<'_>, params: PlaceOrderParams) -> Result<()> {
+    let m = &accs.market;
+    let eng = engine::engine_for(m.engine_kind);
+
+    require!(params.side == 0 || params.side == 1, OrdersError::InvalidSide);
+    require!(params.base_qty.is_multiple_of(m.min_base_qty), OrdersError::InvalidQtyStep);
+
+    if params.order_type == OrderType::Market {
+        // The price is ignored; a buy needs something to spend.
+        require!(params.side == 1 || params.quote_limit > 0, OrdersError::InvalidAmount);
+    } else {
+        eng.validate_tick(m.tick_size, params.price_ticks)?;
+    }
+    eng.validate_min_qty(m.min_base_qty, params.base_qty)?;
+    let now = Clock::get()?.unix_timestamp;
+    require!(params.expires_at == 0 || params.expires_at > now, OrdersError::AlreadyExpired);
+    if params.display_qty > 0 {
+        require!(
+            matches!(params.order_type, OrderType::Limit | OrderType::PostOnly | OrderType::PostOnlySlide),
+            OrdersError::InvalidDisplayQty
+        );
+        require!(params.display_qty.is_multiple_of(m.min_base_qty), OrdersError::InvalidDisplayQty);
+    }
+
+    let (bids_cap, asks_cap, evq_cap) = (m.bids_capacity, m.asks_capacity, m.eventq_capacity);
+    let mp = MatchParams {
+        order_type: params.order_type,
+        max_slippage_ticks: params.max_slippage_ticks,
+        tick_size: m.tick_size,
+        min_base_qty: m.min_base_qty,
+        quote_limit: params.quote_limit,
+        taker_fee_bps: m.taker_fee_bps,
+        lots: LotSizes::of(m),
+        self_trade_behavior: params.self_trade_behavior,
+        match_limit: if params.match_limit == 0 { DEFAULT_MATCH_LIMIT } else { params.match_limit },
+        now,
+    };
+    let rest = RestOpts { expires_at: params.expires_at, display_qty: params.display_qty };
+
+    let slot = accs.oo.free_slot().ok_or(OrdersError::NoFreeSlot)?;
+    require!(
+        accs.oo.find_client_order(params.client_order_id).is_none(),
+        OrdersError::DuplicateClientOrderId
+    );
+
+    // Reserve the worst case up front; it is trued up once matching is done.
+    let is_bid = params.side == 0;
+    let col = Collateral::of(m);
+    let fees = (m.maker_fee_bps, m.taker_fee_bps);
+    let locked = if params.order_type == OrderType::Market && is_bid {
+        col.for_market_buy(params.quote_limit)
+    } else {
+        col.for_order(is_bid, params.base_qty, params.price_ticks)?
+    };
+    lock_collateral(&mut accs.ub, is_bid, locked)?;
+
+    let m = &mut accs.market;
+    let order_id = m.next_order_id;
+    m.next_order_id = m.next_order_id.checked_add(1).ok_or(OrdersError::Overflow)?;
+
+    let oo = &mut accs.oo;
+    oo.slots[slot] = OrderSlot {
+        order_id,
+        client_order_id: params.client_order_id,
+        price_ticks:     params.price_ticks,
+        base_qty:        params.base_qty,
+        filled_qty:      0,
+        side:            params.side,
+        active:          true,
+        pad:             [0; 6],
+    };
 
-    if params.side == 0 {
+    let (fills_locked, match_limit_reached) = if is_bid {
         match_and_place(
-            &ctx.accounts.asks.to_account_info(),
-            &ctx.accounts.bids.to_account_info(),
-            &ctx.accounts.event_queue.to_account_info(),
-            &ctx.accounts.payer, &ctx.accounts.system_program,
-            oo, true,
-            m.eventq_capacity as usize,
-            m.bids_capacity as usize,
-            params.max_slippage_ticks,
-        )?;
+            &accs.asks.to_account_info(),
+            &accs.bids.to_account_info(),
+            &accs.event_queue.to_account_info(),
+            &accs.payer, &accs.system_program,
+            oo, &mut accs.ub, slot, true,
+            evq_cap as usize,
+            bids_cap as usize,
+            eng, mp, rest, col, fees,
+            EventLog::new(&mut accs.market),
+        )?
     } else {
         match_and_place(
-            &ctx.accounts.bids.to_account_info(),
-            &ctx.accounts.asks.to_account_info(),
-            &ctx.accounts.event_queue.to_account_info(),
-            &ctx.accounts.payer, &ctx.accounts.system_program,
-            oo, false,
-            m.eventq_capacity as usize,
-            m.asks_capacity as usize,
-            params.max_slippage_ticks,
-        )?;
+            &accs.bids.to_account_info(),
+            &accs.asks.to_account_info(),
+            &accs.event_queue.to_account_info(),
+            &accs.payer, &accs.system_program,
+            oo, &mut accs.ub, slot, false,
+            evq_cap as usize,
+            asks_cap as usize,
+            eng, mp, rest, col, fees,
+            EventLog::new(&mut accs.market),
+        )?
+    };
+
+    // Keep exactly what the unsettled fills and the resting remainder need. Price
+    // improvement and unfilled size come back.
+    let order = accs.oo.slots[slot];
+    let resting = if order.active { col.for_order(is_bid, order.base_qty, order.price_ticks)? } else { 0 };
+    let needed = fills_locked.checked_add(resting).ok_or(OrdersError::Overflow)?;
+    if needed > locked {
+        lock_collateral(&mut accs.ub, is_bid, needed - locked)?;
+    } else {
+        release_collateral(&mut accs.ub, is_bid, locked - needed)?;
+    }
+
+    let mut log = EventLog::new(&mut accs.market);
+    emit!(OrderPlaced {
+        market: log.market,
+        owner: accs.payer.key(),
+        owner_oo: accs.oo.key(),
+        order_id,
+        client_order_id: params.client_order_id,
+        side: params.side,
+        price_ticks: order.price_ticks,
+        base_qty: params.base_qty,
+        filled_qty: order.filled_qty,
+        resting_qty: if order.active { order.base_qty } else { 0 },
+        match_limit_reached,
+        seq_num: log.next_seq(),
+    });
+
+    // Orders that did not rest are done: free the slot.
+    if !order.active {
+        accs.oo.slots[slot] = OrderSlot::default();
     }
 
     Ok(())
@@ -167,61 +321,294 @@ pub struct CancelOrder<'info> {
     #[account(mut, seeds = [b"kerdos_asks", market.key().as_ref()], bump, owner = crate::id())]
     pub asks: UncheckedAccount<'info>,
 
+    /// CHECK: PDA owned by this program (validated by seeds + owner)
+    #[account(mut, seeds = [b"kerdos_eventq", market.key().as_ref()], bump, owner = crate::id())]
+    pub event_queue: UncheckedAccount<'info>,
+
     #[account(
         mut,
         seeds = [b"kerdos_oo", market.key().as_ref(), payer.key().as_ref()],
         bump = oo.bump
     )]
-    pub oo: Account<'info, OpenOrdersLite>,
+    pub oo: Box<Account<'info, OpenOrdersLite>>,
+
+    #[account(mut, seeds = [b"kerdos_user", market.key().as_ref(), payer.key().as_ref()], bump = ub.bump)]
+    pub ub: Box<Account<'info, UserBalance>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> CancelOrder<'info> {
+    fn cancel(&mut self, slot: usize) -> Result<()> {
+        let col = Collateral::of(&self.market);
+        let mut evq = OutQueue {
+            eventq_ai: &self.event_queue.to_account_info(),
+            payer: &self.payer,
+            sys_prog: &self.system_program,
+            evq_cap: self.market.eventq_capacity as usize,
+            log: EventLog::new(&mut self.market),
+        };
+        cancel_slot(&mut self.oo, &mut self.ub, col, &self.bids.to_account_info(), &self.asks.to_account_info(), &mut evq, slot)
+    }
+}
+
+pub fn cancel_order_handler(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
+    require_keys_eq!(ctx.accounts.oo.user, ctx.accounts.payer.key(), OrdersError::Unauthorized);
+    let slot = ctx.accounts.oo.find_order(order_id).ok_or(OrdersError::NotActive)?;
+    ctx.accounts.cancel(slot)
+}
+
+pub fn cancel_order_by_client_id_handler(ctx: Context<CancelOrder>, client_order_id: u64) -> Result<()> {
+    require_keys_eq!(ctx.accounts.oo.user, ctx.accounts.payer.key(), OrdersError::Unauthorized);
+    let slot = ctx.accounts.oo.find_client_order(client_order_id).ok_or(OrdersError::NotActive)?;
+    ctx.accounts.cancel(slot)
 }
 
-pub fn cancel_order_handler(ctx: Context<CancelOrder>) -> Result<()> {
-    require!(ctx.accounts.oo.active, OrdersError::NotActive);
+/// Cancels up to `limit` of the payer's resting orders (0 = all of them), optionally only
+/// on one side (0 = bids, 1 = asks), in slot order.
+pub fn cancel_all_orders_handler(ctx: Context<CancelOrder>, side: Option<u8>, limit: u8) -> Result<()> {
     require_keys_eq!(ctx.accounts.oo.user, ctx.accounts.payer.key(), OrdersError::Unauthorized);
+    if let Some(side) = side {
+        require!(side == 0 || side == 1, OrdersError::InvalidSide);
+    }
+    let limit = if limit == 0 { MAX_OPEN_ORDERS } else { limit as usize };
+    let accs = ctx.accounts;
+    let mut cancelled = 0usize;
+    for slot in 0..MAX_OPEN_ORDERS {
+        if cancelled == limit { break; }
+        let order = accs.oo.slots[slot];
+        if !order.active || side.is_some_and(|s| s != order.side) { continue; }
+        accs.cancel(slot)?;
+        cancelled += 1;
+    }
+    msg!("cancelled {} orders", cancelled);
+    Ok(())
+}
+
+/// Where a cancel records its `Out` event and logs its `OrderCancelled`.
+struct OutQueue<'a, 'info> {
+    eventq_ai: &'a AccountInfo<'info>,
+    payer: &'a Signer<'info>,
+    sys_prog: &'a Program<'info, System>,
+    evq_cap: usize,
+    log: EventLog<'a>,
+}
+
+/// Takes the order off the book and returns the collateral its remaining size held.
+fn cancel_slot<'info>(
+    oo: &mut Account<'info, OpenOrdersLite>,
+    ub: &mut UserBalance,
+    col: Collateral,
+    bids: &AccountInfo<'info>,
+    asks: &AccountInfo<'info>,
+    evq: &mut OutQueue<'_, 'info>,
+    slot: usize,
+) -> Result<()> {
+    let order = oo.slots[slot];
+    let order_id = order.order_id;
+    let is_bid = order.side == 0;
+    let book = if is_bid { bids } else { asks };
+
+    // Whatever already traded or expired is released by settlement, not here.
+    match slab::find_order(book, order.price_ticks, oo.key(), order_id)? {
+        Some(node) => {
+            let qty = node.base_qty + node.hidden_qty;
+            slab::remove_order(book, is_bid, order.price_ticks, oo.key(), order_id)?;
+            release_collateral(ub, is_bid, col.for_order(is_bid, qty, node.price_ticks)?)?;
+            let ev = out_event(&node, is_bid, qty, 0, OutReason::Cancelled);
+            push_record(evq.payer, evq.sys_prog, evq.eventq_ai, evq.evq_cap, &ev)?;
+            log_cancelled(&mut evq.log, &node, is_bid, qty, OutReason::Cancelled);
+        }
+        None => msg!("order {} already absent from book; freeing its slot", order_id),
+    }
+
+    oo.slots[slot] = OrderSlot::default();
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct AmendOrder<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// CHECK: PDA owned by this program (validated by seeds + owner)
+    #[account(mut, seeds = [b"kerdos_bids", market.key().as_ref()], bump, owner = crate::id())]
+    pub bids: UncheckedAccount<'info>,
+
+    /// CHECK: PDA owned by this program (validated by seeds + owner)
+    #[account(mut, seeds = [b"kerdos_asks", market.key().as_ref()], bump, owner = crate::id())]
+    pub asks: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"kerdos_oo", market.key().as_ref(), payer.key().as_ref()],
+        bump = oo.bump
+    )]
+    pub oo: Box<Account<'info, OpenOrdersLite>>,
 
-    let amount = ctx.accounts.oo.locked_lamports;
-    let from = &ctx.accounts.oo.to_account_info();
-    let to   = &ctx.accounts.payer.to_account_info();
+    #[account(mut, seeds = [b"kerdos_user", market.key().as_ref(), payer.key().as_ref()], bump = ub.bump)]
+    pub ub: Box<Account<'info, UserBalance>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct AmendOrderParams {
+    pub order_id: u64,
+    pub price_ticks: u64,
+    pub base_qty: u64,
+}
 
-    require!(from.lamports() >= amount, OrdersError::InsufficientLamports);
-    **from.try_borrow_mut_lamports()? = from.lamports().checked_sub(amount).ok_or(OrdersError::Overflow)?;
-    **to.try_borrow_mut_lamports()?   = to.lamports().checked_add(amount).ok_or(OrdersError::Overflow)?;
+/// Changes a resting order's price and/or size. A smaller size at the same price is
+/// applied in place and keeps time priority; anything else re-queues the order at the
+/// back of its (new) level. Amendments never take liquidity. Collateral follows the new
+/// size and price.
+pub fn amend_order_handler(ctx: Context<AmendOrder>, params: AmendOrderParams) -> Result<()> {
+    let m = &ctx.accounts.market;
+    let eng = engine::engine_for(m.engine_kind);
 
-    let oo = &mut ctx.accounts.oo;
-    // Remove from the book so the slab frees the node.
-    let removed = if oo.side == 0 {
-        book_remove_oo(&ctx.accounts.bids.to_account_info(), true, oo.key())?
+    require_keys_eq!(ctx.accounts.oo.user, ctx.accounts.payer.key(), OrdersError::Unauthorized);
+    require!(!m.paused, OrdersError::Paused);
+    eng.validate_tick(m.tick_size, params.price_ticks)?;
+    eng.validate_min_qty(m.min_base_qty, params.base_qty)?;
+    require!(params.base_qty.is_multiple_of(m.min_base_qty), OrdersError::InvalidQtyStep);
+    let col = Collateral::of(m);
+
+    let slot = ctx.accounts.oo.find_order(params.order_id).ok_or(OrdersError::NotActive)?;
+    let order = ctx.accounts.oo.slots[slot];
+    let oo_key = ctx.accounts.oo.key();
+    let is_bid = order.side == 0;
+    let (book_ai, opposite_ai) = if is_bid {
+        (ctx.accounts.bids.to_account_info(), ctx.accounts.asks.to_account_info())
     } else {
-        book_remove_oo(&ctx.accounts.asks.to_account_info(), false, oo.key())?
+        (ctx.accounts.asks.to_account_info(), ctx.accounts.bids.to_account_info())
     };
-    // If the node is already gone from the book (e.g. fully consumed), still allow unlocking funds.
-    if !removed {
-        msg!("open orders entry already absent from book; unlocking lamports");
+
+    let node = slab::find_order(&book_ai, order.price_ticks, oo_key, order.order_id)?
+        .ok_or(OrdersError::BookEntryNotFound)?;
+    let resting = node.base_qty + node.hidden_qty;
+
+    let in_place = params.price_ticks == order.price_ticks && params.base_qty <= resting;
+    if in_place {
+        slab::reduce_order(&book_ai, order.price_ticks, oo_key, order.order_id, params.base_qty)?;
+    } else {
+        if let Some(best) = book_peek_head(&opposite_ai)?.filter(|_| eng.matches_on_place()) {
+            let cross = if is_bid { params.price_ticks >= best.price_ticks } else { params.price_ticks <= best.price_ticks };
+            require!(!cross, OrdersError::WouldCross);
+        }
+        slab::remove_order(&book_ai, is_bid, order.price_ticks, oo_key, order.order_id)?;
+        let (shown, hidden) = split_display(params.base_qty, node.display_qty);
+        book_insert_order(
+            &book_ai,
+            is_bid,
+            slab::SlabNode {
+                price_ticks: params.price_ticks,
+                base_qty: shown,
+                hidden_qty: hidden,
+                ts: Clock::get()?.slot,
+                ..node
+            },
+        )?;
     }
 
-    oo.locked_lamports = 0;
-    oo.active = false;
+    let held = col.for_order(is_bid, resting, order.price_ticks)?;
+    let needed = col.for_order(is_bid, params.base_qty, params.price_ticks)?;
+    if needed > held {
+        lock_collateral(&mut ctx.accounts.ub, is_bid, needed - held)?;
+    } else {
+        release_collateral(&mut ctx.accounts.ub, is_bid, held - needed)?;
+    }
+
+    // Fills that have not settled yet still come off the slot when they do.
+    let s = &mut ctx.accounts.oo.slots[slot];
+    let unsettled = s.base_qty.saturating_sub(resting);
+    s.price_ticks = params.price_ticks;
+    s.base_qty = params.base_qty.checked_add(unsettled).ok_or(OrdersError::Overflow)?;
+
+    let mut log = EventLog::new(&mut ctx.accounts.market);
+    emit!(OrderAmended {
+        market: log.market,
+        owner: ctx.accounts.payer.key(),
+        owner_oo: oo_key,
+        order_id: order.order_id,
+        client_order_id: order.client_order_id,
+        side: order.side,
+        old_price_ticks: order.price_ticks,
+        new_price_ticks: params.price_ticks,
+        old_base_qty: resting,
+        new_base_qty: params.base_qty,
+        requeued: !in_place,
+        seq_num: log.next_seq(),
+    });
     Ok(())
 }
 
 #[derive(Accounts)]
-pub struct ClearEventQ<'info> {
-    pub authority: Signer<'info>,
+pub struct RunAuction<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
 
     #[account(mut)]
     pub market: Account<'info, Market>,
 
-    /// CHECK: Market EventQ PDA, owned by this program
+    /// CHECK: PDA owned by this program (validated by seeds + owner)
+    #[account(mut, seeds = [b"kerdos_bids", market.key().as_ref()], bump, owner = crate::id())]
+    pub bids: UncheckedAccount<'info>,
+
+    /// CHECK: PDA owned by this program (validated by seeds + owner)
+    #[account(mut, seeds = [b"kerdos_asks", market.key().as_ref()], bump, owner = crate::id())]
+    pub asks: UncheckedAccount<'info>,
+
+    /// CHECK: PDA owned by this program (validated by seeds + owner)
     #[account(mut, seeds = [b"kerdos_eventq", market.key().as_ref()], bump, owner = crate::id())]
     pub event_queue: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
-pub fn clear_eventq_handler(ctx: Context<ClearEventQ>) -> Result<()> {
-    require_keys_eq!(ctx.accounts.market.authority, ctx.accounts.authority.key(), OrdersError::Unauthorized);
-    let ai = ctx.accounts.event_queue.to_account_info();
-    let mut b = blob_load(&ai)?;
-    b.used = 0;
-    blob_store(&ai, &b)?;
+/// Permissionless crank for batch-auction markets: once `auction_interval_slots` have
+/// passed since the last cleared auction, trades everything that crosses at one uniform
+/// price. A crank that runs out of `limit` fills (0 = `DEFAULT_MATCH_LIMIT`) leaves the
+/// auction due, so the next call finishes it.
+pub fn run_auction_handler(ctx: Context<RunAuction>, limit: u16) -> Result<()> {
+    let accs = ctx.accounts;
+    let m = &accs.market;
+    require!(!m.paused, OrdersError::Paused);
+    require!(m.engine_kind == EngineKind::BatchAuction, OrdersError::NotAuctionMarket);
+    let slot = Clock::get()?.slot;
+    require!(slot >= m.last_auction_slot.saturating_add(m.auction_interval_slots), OrdersError::AuctionNotDue);
+
+    let bids_ai = accs.bids.to_account_info();
+    let asks_ai = accs.asks.to_account_info();
+    let evq_ai = accs.event_queue.to_account_info();
+    validate_blob(&bids_ai, BlobKind::Bids)?;
+    validate_blob(&asks_ai, BlobKind::Asks)?;
+    validate_blob(&evq_ai, BlobKind::EventQueue)?;
+    ensure_book_boot(&accs.payer, &accs.system_program, &bids_ai, BlobKind::Bids, m.bids_capacity)?;
+    ensure_book_boot(&accs.payer, &accs.system_program, &asks_ai, BlobKind::Asks, m.asks_capacity)?;
+
+    let (evq_cap, col, tick_size) = (m.eventq_capacity as usize, Collateral::of(m), m.tick_size);
+    let outcome = BatchAuctionEngine.run_auction(
+        &mut SlabBook { ai: &bids_ai, is_bid: true },
+        &mut SlabBook { ai: &asks_ai, is_bid: false },
+        &mut AuctionQueue {
+            eventq_ai: &evq_ai,
+            payer: &accs.payer,
+            sys_prog: &accs.system_program,
+            evq_cap,
+            col,
+            log: EventLog::new(&mut accs.market),
+        },
+        tick_size,
+        Clock::get()?.unix_timestamp,
+        if limit == 0 { DEFAULT_MATCH_LIMIT } else { limit },
+    )?;
+    msg!("auction price={:?} fills={} cleared={}", outcome.price_ticks, outcome.fills, outcome.cleared);
+
+    if outcome.cleared {
+        accs.market.last_auction_slot = slot;
+    }
     Ok(())
 }
 
@@ -238,14 +625,19 @@ pub struct CloseOo<'info> {
         seeds = [b"kerdos_oo", market.key().as_ref(), payer.key().as_ref()],
         bump = oo.bump
     )]
-    pub oo: Account<'info, OpenOrdersLite>,
+    pub oo: Box<Account<'info, OpenOrdersLite>>,
 }
 
+/// Fills and outs still queued for the account's orders settle against the owner's
+/// user balance, so closing does not wait for the crank.
 pub fn close_oo_handler(ctx: Context<CloseOo>) -> Result<()> {
-    require!(!ctx.accounts.oo.active, OrdersError::StillActive);
+    require!(!ctx.accounts.oo.has_active(), OrdersError::StillActive);
     Ok(())
 }
 
+/// Returns the collateral the taker's fills owe and whether the match limit dropped a
+/// remainder that would have rested.
+#[allow(clippy::too_many_arguments)]
 fn match_and_place<'info>(
     opposite_ai: &AccountInfo<'info>,
     same_side_ai: &AccountInfo<'info>,
@@ -253,60 +645,353 @@ fn match_and_place<'info>(
     payer: &Signer<'info>,
     sys_prog: &Program<'info, System>,
     taker_oo: &mut Account<'info, OpenOrdersLite>,
+    ub: &mut UserBalance,
+    slot: usize,
     taker_is_bid: bool,
     evq_cap: usize,
     same_cap: usize,
-    max_slippage_ticks: u64,
-) -> Result<()> {
-    loop {
-        let best = book_peek_head(opposite_ai)?;
-        if best.is_none() { break; }
-        let (maker_oo, top_price, top_qty) = best.unwrap();
-
-        let cross = if taker_is_bid { taker_oo.price_ticks >= top_price } else { taker_oo.price_ticks <= top_price };
-        if !cross { break; }
-
-        let diff = if taker_is_bid {
-            taker_oo.price_ticks.saturating_sub(top_price)
-        } else {
-            top_price.saturating_sub(taker_oo.price_ticks)
-        };
-        if diff > max_slippage_ticks { break; }
-
-        let fill = core::cmp::min(taker_oo.base_qty, top_qty);
-        book_consume_head_by(opposite_ai, !taker_is_bid, fill)?;
-
-        ensure_event_capacity(payer, sys_prog, eventq_ai, 1, evq_cap)?;
-        let price_ticks_u32: u32 = (top_price as u64).try_into().unwrap_or(u32::MAX);
-        let ev = FillEvent {
-            maker_oo,
-            taker_oo: taker_oo.key(),
-            base_qty: fill,
-            price_ticks: price_ticks_u32,
-            taker_side: if taker_is_bid { 0 } else { 1 },
-            pad: [0; 3],
+    eng: &dyn MatchingEngine,
+    mp: MatchParams,
+    rest: RestOpts,
+    col: Collateral,
+    (maker_fee_bps, taker_fee_bps): (i16, u16),
+    log: EventLog<'_>,
+) -> Result<(u64, bool)> {
+    let taker_key = taker_oo.key();
+    let taker_owner = taker_oo.user;
+    // Work on a copy: self-trades touch the maker's slot in the same account.
+    let mut taker = taker_oo.slots[slot];
+
+    let (outcome, fills_locked, filled) = {
+        let mut book = SlabBook { ai: opposite_ai, is_bid: !taker_is_bid };
+        let mut sink = QueueSink {
+            eventq_ai, payer, sys_prog, evq_cap,
+            taker_oo: &mut *taker_oo,
+            ub,
+            taker_key,
+            taker_owner,
+            taker_order_id: taker.order_id,
+            taker_client_order_id: taker.client_order_id,
+            taker_slot: slot as u8,
+            taker_is_bid,
+            col,
+            maker_fee_bps,
+            taker_fee_bps,
+            fills_locked: 0,
+            filled: 0,
+            log,
         };
-        event_push(eventq_ai, &ev)?;
-
-        taker_oo.base_qty = taker_oo.base_qty.saturating_sub(fill);
-        if taker_oo.base_qty == 0 {
-            taker_oo.active = false;
-            break;
-        }
-    }
+        let order = TakerOrder { oo: taker_key, price_ticks: taker.price_ticks, base_qty: taker.base_qty, is_bid: taker_is_bid };
+        let outcome = eng.match_order(&mut book, &mut sink, &order, &mp)?;
+        (outcome, sink.fills_locked, sink.filled)
+    };
+    taker.price_ticks = outcome.price_ticks;
+    taker.base_qty = outcome.base_qty;
+    taker.filled_qty = filled;
 
-    if taker_oo.base_qty > 0 {
+    if !outcome.rests {
+        taker.active = false;
+    } else {
         ensure_book_free(payer, sys_prog, same_side_ai, same_cap as u32, 1)?;
         let ts = Clock::get()?.slot;
+        let (shown, hidden) = split_display(taker.base_qty, rest.display_qty);
         book_insert_order(
             same_side_ai,
             taker_is_bid,
-            taker_oo.price_ticks,
-            taker_oo.base_qty,
-            taker_oo.key(),
-            ts,
+            slab::SlabNode {
+                price_ticks: taker.price_ticks,
+                base_qty: shown,
+                oo: taker_key,
+                owner: taker_owner,
+                order_id: taker.order_id,
+                client_order_id: taker.client_order_id,
+                slot: slot as u8,
+                ts,
+                expires_at: rest.expires_at,
+                hidden_qty: hidden,
+                display_qty: rest.display_qty,
+                flags: if rest.display_qty > 0 { slab::FLAG_ICEBERG } else { 0 },
+                ..slab::SlabNode::default()
+            },
         )?;
     }
+    taker_oo.slots[slot] = taker;
+    Ok((fills_locked, outcome.match_limit_reached))
+}
+
+/// One side of the on-chain slab as the engine sees it.
+struct SlabBook<'a, 'info> {
+    ai: &'a AccountInfo<'info>,
+    is_bid: bool,
+}
+
+impl BookSide for SlabBook<'_, '_> {
+    fn peek_best(&self) -> Result<Option<slab::SlabNode>> {
+        book_peek_head(self.ai)
+    }
+
+    fn best_level(&self, max: usize) -> Result<Vec<slab::SlabNode>> {
+        slab::best_level(self.ai, max)
+    }
+
+    fn best_orders(&self, max: usize) -> Result<Vec<slab::SlabNode>> {
+        slab::best_orders(self.ai, self.is_bid, max)
+    }
+
+    fn consume_best(&mut self, qty: u64) -> Result<()> {
+        book_consume_head_by(self.ai, self.is_bid, qty)
+    }
+
+    fn consume(&mut self, node: &slab::SlabNode, qty: u64) -> Result<()> {
+        slab::consume_order_by(self.ai, self.is_bid, node.price_ticks, node.oo, node.order_id, qty)
+    }
+
+    fn remove(&mut self, node: &slab::SlabNode) -> Result<()> {
+        slab::remove_order(self.ai, self.is_bid, node.price_ticks, node.oo, node.order_id)?;
+        Ok(())
+    }
+}
+
+/// Turns match decisions into event queue entries and self-trade slot releases.
+struct QueueSink<'a, 'info> {
+    eventq_ai: &'a AccountInfo<'info>,
+    payer: &'a Signer<'info>,
+    sys_prog: &'a Program<'info, System>,
+    evq_cap: usize,
+    taker_oo: &'a mut Account<'info, OpenOrdersLite>,
+    /// The taker's balance, which also backs any of its own orders it trades against.
+    ub: &'a mut UserBalance,
+    taker_key: Pubkey,
+    taker_owner: Pubkey,
+    taker_order_id: u64,
+    taker_client_order_id: u64,
+    taker_slot: u8,
+    taker_is_bid: bool,
+    col: Collateral,
+    maker_fee_bps: i16,
+    taker_fee_bps: u16,
+    /// Taker collateral now owed to unsettled fills: exactly what settlement will debit.
+    fills_locked: u64,
+    /// Taker size filled, which placement records in its slot straight away.
+    filled: u64,
+    log: EventLog<'a>,
+}
+
+impl MatchSink for QueueSink<'_, '_> {
+    fn on_fill(&mut self, maker: &slab::SlabNode, qty: u64, price_ticks: u64) -> Result<()> {
+        let taker_locked = if self.taker_is_bid {
+            let quote = self.col.lots.quote_native(qty, price_ticks)?;
+            let fees = FillFees::of(quote, self.maker_fee_bps, self.taker_fee_bps)?;
+            quote.checked_add(fees.taker_fee).ok_or(OrdersError::Overflow)?
+        } else {
+            self.col.lots.base_native(qty)?
+        };
+        self.fills_locked = self.fills_locked.checked_add(taker_locked).ok_or(OrdersError::Overflow)?;
+        self.filled = self.filled.checked_add(qty).ok_or(OrdersError::Overflow)?;
+        let maker_done = qty == maker.base_qty + maker.hidden_qty;
+        ensure_event_capacity(self.payer, self.sys_prog, self.eventq_ai, 1 + maker_done as usize, self.evq_cap)?;
+        event_push(self.eventq_ai, &Event::Fill(FillEvent {
+            maker_oo: maker.oo,
+            taker_oo: self.taker_key,
+            maker_owner: maker.owner,
+            taker_owner: self.taker_owner,
+            base_qty: qty,
+            maker_client_order_id: maker.client_order_id,
+            taker_client_order_id: self.taker_client_order_id,
+            price_ticks,
+            maker_locked: self.col.for_order(!self.taker_is_bid, qty, maker.price_ticks)?,
+            taker_locked,
+            maker_order_id: maker.order_id,
+            taker_order_id: self.taker_order_id,
+            taker_side: if self.taker_is_bid { 0 } else { 1 },
+            maker_slot: maker.slot,
+            taker_slot: self.taker_slot,
+            taker_resting: false,
+            pad: [0; 4],
+        }))?;
+        if maker_done {
+            event_push(self.eventq_ai, &out_event(maker, !self.taker_is_bid, 0, 0, OutReason::Filled))?;
+        }
+        emit!(OrderFilled {
+            market: self.log.market,
+            maker_owner: maker.owner,
+            taker_owner: self.taker_owner,
+            maker_oo: maker.oo,
+            taker_oo: self.taker_key,
+            maker_order_id: maker.order_id,
+            taker_order_id: self.taker_order_id,
+            taker_side: if self.taker_is_bid { 0 } else { 1 },
+            price_ticks,
+            base_qty: qty,
+            maker_done,
+            seq_num: self.log.next_seq(),
+        });
+        Ok(())
+    }
+
+    /// Reported as an `Out` event so settlement can free the owner's slot.
+    fn on_expired(&mut self, maker: &slab::SlabNode) -> Result<()> {
+        let qty = maker.base_qty + maker.hidden_qty;
+        let locked = self.col.for_order(!self.taker_is_bid, qty, maker.price_ticks)?;
+        ensure_event_capacity(self.payer, self.sys_prog, self.eventq_ai, 1, self.evq_cap)?;
+        event_push(self.eventq_ai, &out_event(maker, !self.taker_is_bid, qty, locked, OutReason::Expired))?;
+        log_cancelled(&mut self.log, maker, !self.taker_is_bid, qty, OutReason::Expired);
+        Ok(())
+    }
+
+    /// The maker is the taker's own order, so its slot and collateral are released here;
+    /// one that leaves the book is still recorded as an `Out` event.
+    fn on_self_trade(&mut self, maker: &slab::SlabNode, qty: u64) -> Result<()> {
+        let maker_is_bid = !self.taker_is_bid;
+        release_collateral(self.ub, maker_is_bid, self.col.for_order(maker_is_bid, qty, maker.price_ticks)?)?;
+        release_maker_slot(self.taker_oo, maker, qty)?;
+        if qty == maker.base_qty + maker.hidden_qty {
+            let ev = out_event(maker, maker_is_bid, qty, 0, OutReason::SelfTrade);
+            push_record(self.payer, self.sys_prog, self.eventq_ai, self.evq_cap, &ev)?;
+        }
+        log_cancelled(&mut self.log, maker, maker_is_bid, qty, OutReason::SelfTrade);
+        Ok(())
+    }
+}
+
+/// Auction trades as fill events. Neither side took liquidity, so the younger order
+/// is reported as the taker.
+struct AuctionQueue<'a, 'info> {
+    eventq_ai: &'a AccountInfo<'info>,
+    payer: &'a Signer<'info>,
+    sys_prog: &'a Program<'info, System>,
+    evq_cap: usize,
+    col: Collateral,
+    log: EventLog<'a>,
+}
+
+impl AuctionSink for AuctionQueue<'_, '_> {
+    fn on_cross(&mut self, bid: &slab::SlabNode, ask: &slab::SlabNode, qty: u64, price_ticks: u64) -> Result<()> {
+        let bid_is_taker = bid.order_id > ask.order_id;
+        let (maker, taker) = if bid_is_taker { (ask, bid) } else { (bid, ask) };
+        // Both sides rested, so each gives up the collateral of its own price.
+        let bid_locked = self.col.for_order(true, qty, bid.price_ticks)?;
+        let ask_locked = self.col.for_order(false, qty, ask.price_ticks)?;
+        let (maker_locked, taker_locked) = if bid_is_taker { (ask_locked, bid_locked) } else { (bid_locked, ask_locked) };
+        let bid_done = qty == bid.base_qty + bid.hidden_qty;
+        let ask_done = qty == ask.base_qty + ask.hidden_qty;
+        ensure_event_capacity(self.payer, self.sys_prog, self.eventq_ai, 1 + bid_done as usize + ask_done as usize, self.evq_cap)?;
+        event_push(self.eventq_ai, &Event::Fill(FillEvent {
+            maker_oo: maker.oo,
+            taker_oo: taker.oo,
+            maker_owner: maker.owner,
+            taker_owner: taker.owner,
+            base_qty: qty,
+            maker_client_order_id: maker.client_order_id,
+            taker_client_order_id: taker.client_order_id,
+            price_ticks,
+            maker_locked,
+            taker_locked,
+            maker_order_id: maker.order_id,
+            taker_order_id: taker.order_id,
+            taker_side: if bid_is_taker { 0 } else { 1 },
+            maker_slot: maker.slot,
+            taker_slot: taker.slot,
+            taker_resting: true,
+            pad: [0; 4],
+        }))?;
+        if bid_done {
+            event_push(self.eventq_ai, &out_event(bid, true, 0, 0, OutReason::Filled))?;
+        }
+        if ask_done {
+            event_push(self.eventq_ai, &out_event(ask, false, 0, 0, OutReason::Filled))?;
+        }
+        emit!(OrderFilled {
+            market: self.log.market,
+            maker_owner: maker.owner,
+            taker_owner: taker.owner,
+            maker_oo: maker.oo,
+            taker_oo: taker.oo,
+            maker_order_id: maker.order_id,
+            taker_order_id: taker.order_id,
+            taker_side: if bid_is_taker { 0 } else { 1 },
+            price_ticks,
+            base_qty: qty,
+            maker_done: if bid_is_taker { ask_done } else { bid_done },
+            seq_num: self.log.next_seq(),
+        });
+        Ok(())
+    }
+
+    fn on_expired(&mut self, node: &slab::SlabNode, is_bid: bool) -> Result<()> {
+        let qty = node.base_qty + node.hidden_qty;
+        let locked = self.col.for_order(is_bid, qty, node.price_ticks)?;
+        ensure_event_capacity(self.payer, self.sys_prog, self.eventq_ai, 1, self.evq_cap)?;
+        event_push(self.eventq_ai, &out_event(node, is_bid, qty, locked, OutReason::Expired))?;
+        log_cancelled(&mut self.log, node, is_bid, qty, OutReason::Expired);
+        Ok(())
+    }
+}
+
+/// `node` left the book with `qty` unfilled and `locked` collateral for settlement to return.
+fn out_event(node: &slab::SlabNode, is_bid: bool, qty: u64, locked: u64, reason: OutReason) -> Event {
+    Event::Out(OutEvent {
+        owner_oo: node.oo,
+        owner: node.owner,
+        order_id: node.order_id,
+        client_order_id: node.client_order_id,
+        base_qty: qty,
+        locked,
+        side: if is_bid { 0 } else { 1 },
+        slot: node.slot,
+        reason,
+        pad: [0; 5],
+    })
+}
+
+/// Logs `qty` of `node` leaving the book without trading.
+fn log_cancelled(log: &mut EventLog<'_>, node: &slab::SlabNode, is_bid: bool, qty: u64, reason: OutReason) {
+    emit!(OrderCancelled {
+        market: log.market,
+        owner: node.owner,
+        owner_oo: node.oo,
+        order_id: node.order_id,
+        client_order_id: node.client_order_id,
+        side: if is_bid { 0 } else { 1 },
+        price_ticks: node.price_ticks,
+        base_qty: qty,
+        reason,
+        seq_num: log.next_seq(),
+    });
+}
+
+/// Visible and hidden parts of `qty` for an iceberg showing `display_qty` (0 = all).
+fn split_display(qty: u64, display_qty: u64) -> (u64, u64) {
+    let shown = if display_qty == 0 { qty } else { qty.min(display_qty) };
+    (shown, qty - shown)
+}
+
+/// Take `qty` off the taker's own resting order after a self-trade; an emptied order
+/// frees its slot.
+fn release_maker_slot(oo: &mut OpenOrdersLite, maker: &slab::SlabNode, qty: u64) -> Result<()> {
+    let idx = maker.slot as usize;
+    let known = idx < oo.slots.len() && oo.slots[idx].active && oo.slots[idx].order_id == maker.order_id;
+    require!(known, OrdersError::BookEntryNotFound);
+    if qty < maker.base_qty + maker.hidden_qty {
+        oo.slots[idx].base_qty = oo.slots[idx].base_qty.saturating_sub(qty);
+        return Ok(());
+    }
+    oo.slots[idx] = OrderSlot::default();
+    Ok(())
+}
+
+/// Moves `amount` from free to locked in the currency the side pays with (quote for bids).
+fn lock_collateral(ub: &mut UserBalance, is_bid: bool, amount: u64) -> Result<()> {
+    let (free, locked) = if is_bid { (&mut ub.quote_free, &mut ub.quote_locked) } else { (&mut ub.base_free, &mut ub.base_locked) };
+    *free = free.checked_sub(amount).ok_or(OrdersError::InsufficientFunds)?;
+    *locked = locked.checked_add(amount).ok_or(OrdersError::Overflow)?;
+    Ok(())
+}
+
+fn release_collateral(ub: &mut UserBalance, is_bid: bool, amount: u64) -> Result<()> {
+    let (free, locked) = if is_bid { (&mut ub.quote_free, &mut ub.quote_locked) } else { (&mut ub.base_free, &mut ub.base_locked) };
+    *locked = locked.checked_sub(amount).ok_or(OrdersError::Overflow)?;
+    *free = free.checked_add(amount).ok_or(OrdersError::Overflow)?;
     Ok(())
 }
 
@@ -352,10 +1037,11 @@ fn ensure_eventq_min<'info>(
     cap: u32,
     min_len: usize,
 ) -> Result<()> {
-    let cur = ai.data_len();
-    if cur < Blob::LEN {
-        ensure_funded_resize(payer, sys_prog, ai, Blob::LEN)?;
+    let header_len = Blob::LEN + eventq::EventQueueHeader::LEN;
+    if ai.data_len() < header_len {
+        ensure_funded_resize(payer, sys_prog, ai, header_len)?;
         write_blob_header(ai, BlobKind::EventQueue, cap)?;
+        eventq::init(ai)?;
     }
     if ai.data_len() < min_len {
         ensure_funded_resize(payer, sys_prog, ai, min_len)?;
@@ -363,7 +1049,7 @@ fn ensure_eventq_min<'info>(
     Ok(())
 }
 
-fn book_peek_head(ai: &AccountInfo<'_>) -> Result<Option<(Pubkey, u64, u64)>> {
+fn book_peek_head(ai: &AccountInfo<'_>) -> Result<Option<slab::SlabNode>> {
     slab::peek_best(ai)
 }
 
@@ -371,31 +1057,13 @@ fn book_consume_head_by(ai: &AccountInfo<'_>, is_bid_book: bool, qty: u64) -> Re
     slab::consume_best_by(ai, is_bid_book, qty)
 }
 
-fn book_insert_order(
-    ai: &AccountInfo<'_>,
-    is_bid_book: bool,
-    price_ticks: u64,
-    base_qty: u64,
-    oo: Pubkey,
-    ts: u64,
-) -> Result<()> {
-    slab::insert_order(ai, is_bid_book, price_ticks, base_qty, oo, ts)
-}
-
-fn event_off(idx: usize) -> usize { Blob::LEN + idx * FillEvent::LEN }
-
-fn write_event(ai: &AccountInfo<'_>, idx: usize, ev: &FillEvent) -> Result<()> {
-    let mut data = ai.try_borrow_mut_data()?;
-    let off = event_off(idx);
-    let mut cur = std::io::Cursor::new(&mut data[off..off + FillEvent::LEN]);
-    ev.serialize(&mut cur)?;
+fn book_insert_order(ai: &AccountInfo<'_>, is_bid_book: bool, order: slab::SlabNode) -> Result<()> {
+    slab::insert_order(ai, is_bid_book, order)?;
     Ok(())
 }
 
-fn event_push(ai: &AccountInfo<'_>, ev: &FillEvent) -> Result<()> {
-    let used = book_used(ai)?;
-    write_event(ai, used, ev)?;
-    set_book_used(ai, used + 1)?;
+fn event_push(ai: &AccountInfo<'_>, ev: &Event) -> Result<()> {
+    eventq::push(ai, ev)?;
     Ok(())
 }
 
@@ -409,8 +1077,13 @@ fn validate_blob(ai: &AccountInfo<'_>, expected: BlobKind) -> Result<()> {
     require!(b.kind == expected as u8, OrdersError::BadBlobHeader);
 
     if matches!(expected, BlobKind::EventQueue) {
-        let max_entries = (len - Blob::LEN) / FillEvent::LEN;
-        require!((b.used as usize) <= max_entries, OrdersError::BadBlobHeader);
+        if len < Blob::LEN + eventq::EventQueueHeader::LEN {
+            return Ok(());
+        }
+        let h = eventq::load_header(ai)?;
+        let cap = b.capacity as usize;
+        require!(h.count as usize <= cap, OrdersError::BadBlobHeader);
+        require!(cap == 0 || (h.head as usize) < cap, OrdersError::BadBlobHeader);
         return Ok(());
     }
 
@@ -439,13 +1112,24 @@ fn validate_blob(ai: &AccountInfo<'_>, expected: BlobKind) -> Result<()> {
         #[msg("price out of range")]             PriceOutOfRange,
         #[msg("invalid qty step")]               InvalidQtyStep,
         #[msg("bad blob header or bounds")]      BadBlobHeader,
-        #[msg("insufficient lamports for refund")] InsufficientLamports,
+        #[msg("insufficient free balance for collateral")] InsufficientFunds,
     #[msg("arithmetic overflow")]            Overflow,
     #[msg("event queue full")]               EventqFull,
     #[msg("book full")]                      BookFull,
     #[msg("bad book account for market")]    BadBookAccount,
     #[msg("unauthorized")]                   Unauthorized,
     #[msg("order still active")]             StillActive,
+    #[msg("no free open orders slot")]       NoFreeSlot,
+    #[msg("client order id already in use")] DuplicateClientOrderId,
+    #[msg("post-only order would cross")]    WouldCross,
+    #[msg("fill-or-kill order not filled")]  FillOrKillNotFilled,
+    #[msg("market sell below minimum quote")] QuoteLimitNotMet,
+    #[msg("order would trade against own order")] SelfTrade,
+    #[msg("expiry is not in the future")]    AlreadyExpired,
+    #[msg("invalid iceberg display size")]   InvalidDisplayQty,
+    #[msg("empty or oversized batch")]       InvalidBatch,
+    #[msg("market does not run batch auctions")] NotAuctionMarket,
+    #[msg("next auction is not due yet")]    AuctionNotDue,
 }
 
 fn blob_load(ai: &AccountInfo<'_>) -> Result<Blob> {
@@ -455,24 +1139,6 @@ fn blob_load(ai: &AccountInfo<'_>) -> Result<Blob> {
     Ok(b)
 }
 
-fn blob_store(ai: &AccountInfo<'_>, b: &Blob) -> Result<()> {
-    let mut data = ai.try_borrow_mut_data()?;
-    let mut cur = std::io::Cursor::new(&mut data[..]);
-    b.serialize(&mut cur)?;
-    Ok(())
-}
-
-fn book_used(ai: &AccountInfo<'_>) -> Result<usize> {
-    let b = blob_load(ai)?;
-    Ok(b.used as usize)
-}
-
-fn set_book_used(ai: &AccountInfo<'_>, used: usize) -> Result<()> {
-    let mut b = blob_load(ai)?;
-    b.used = used as u32;
-    blob_store(ai, &b)
-}
-
 fn ensure_event_capacity<'info>(
     payer: &Signer<'info>,
     sys_prog: &Program<'info, System>,
@@ -480,17 +1146,30 @@ fn ensure_event_capacity<'info>(
     extra: usize,
     cap: usize,
 ) -> Result<()> {
-    let used = book_used(ai)?;
-    let need_ev = used + extra;
-    require!(need_ev <= cap, OrdersError::EventqFull);
-
-    let elem = FillEvent::LEN;
-    let have_len = ai.data_len();
-    let have_ev = if have_len >= Blob::LEN { (have_len - Blob::LEN) / elem } else { 0 };
-    if need_ev > have_ev {
-        let want_ev = core::cmp::min(((need_ev + EV_CHUNK - 1) / EV_CHUNK) * EV_CHUNK, cap);
-        let want_len = Blob::LEN + want_ev * elem;
-        ensure_funded_resize(payer, sys_prog, ai, want_len)?;
+    let h = eventq::load_header(ai)?;
+    let allocated = eventq::allocated_slots(ai.data_len());
+    let want_ev = eventq::grow_target(&h, cap, allocated, extra).ok_or(OrdersError::EventqFull)?;
+    if want_ev > allocated {
+        ensure_funded_resize(payer, sys_prog, ai, Blob::LEN + eventq::region_len_for(want_ev as u32))?;
     }
     Ok(())
 }
+
+/// Queues an event that settlement only reads as a record (a cancel or self-trade out).
+/// Those are applied already, so a full queue skips the record rather than failing the
+/// instruction; the `OrderCancelled` log still carries it.
+fn push_record<'info>(
+    payer: &Signer<'info>,
+    sys_prog: &Program<'info, System>,
+    ai: &AccountInfo<'info>,
+    cap: usize,
+    ev: &Event,
+) -> Result<()> {
+    let h = eventq::load_header(ai)?;
+    if eventq::grow_target(&h, cap, eventq::allocated_slots(ai.data_len()), 1).is_none() {
+        msg!("event queue full; out record not queued");
+        return Ok(());
+    }
+    ensure_event_capacity(payer, sys_prog, ai, 1, cap)?;
+    event_push(ai, ev)
+}
@@ -1,13 +1,18 @@
 use anchor_lang::prelude::*;
 use anchor_lang::{AccountDeserialize, AccountSerialize, Discriminator};
 use std::io::Cursor;
-use crate::state::{Market, Blob, FillEvent, OpenOrdersLite, UserBalance, BLOB_MAGIC};
-
-const BPS_DENOM: u64 = 10_000;
-
+use crate::state::{Market, Event, FillEvent, OutEvent, OutReason, OpenOrdersLite, OrderSlot, UserBalance};
+use crate::lots::{FillFees, LotSizes};
+use crate::eventq;
+use crate::events::{EventLog, EventsSettled};
+
+/// Anyone may crank: every OO and user balance an event touches is resolved from
+/// `remaining_accounts` and checked against the event and the market before use.
+/// Balances are found by the owner wallets the events carry and user balances cannot be
+/// closed, while a closed OO is just skipped, so no owner can stall the queue.
 #[derive(Accounts)]
 pub struct SettleEvents<'info> {
-    pub authority: Signer<'info>,
+    pub cranker: Signer<'info>,
     #[account(mut)]
     pub market: Account<'info, Market>,
     /// CHECK: Market EventQ PDA.
@@ -16,120 +21,193 @@ pub struct SettleEvents<'info> {
 }
 
 pub fn settle_events_handler(ctx: Context<SettleEvents>, max_events: u16) -> Result<()> {
-    require_keys_eq!(ctx.accounts.market.authority, ctx.accounts.authority.key(), SettleError::Unauthorized);
-
+    let market = ctx.accounts.market.key();
     let mkt = &mut ctx.accounts.market;
     let evq_ai = ctx.accounts.event_queue.to_account_info();
 
-    let mut bh = blob_load(&evq_ai)?;
-    let used = bh.used as usize;
-    if used == 0 {
+    let queued = eventq::load_header(&evq_ai)?.count as usize;
+    if queued == 0 {
         return Ok(());
     }
-    let take_n = core::cmp::min(used, max_events as usize);
+    let take_n = core::cmp::min(queued, max_events as usize);
+    let first_event_seq = eventq::peek(&evq_ai, 0)?.seq_num;
 
+    let mut rewarded = 0u64;
     for i in 0..take_n {
-        let ev = read_event(&evq_ai, i)?;
-
-        let mut maker_oo_ai = None;
-        for ai in ctx.remaining_accounts.iter() {
-            if ai.key() == ev.maker_oo { maker_oo_ai = Some(ai); break; }
+        let event = eventq::peek(&evq_ai, i)?.event;
+        rewarded += event.pays_crank_reward() as u64;
+        match event {
+            Event::Fill(ev) => settle_fill(mkt, market, ctx.remaining_accounts, &ev)?,
+            Event::Out(ev) => settle_out(market, ctx.remaining_accounts, &ev)?,
         }
-        let maker_oo_ai = maker_oo_ai.ok_or(SettleError::MissingOpenOrders)?;
-        require!(*maker_oo_ai.owner == crate::id(), SettleError::MissingOpenOrders);
-        let maker_oo = load_open_orders(maker_oo_ai)?;
+    }
+    eventq::pop_front(&evq_ai, take_n)?;
+
+    let cranker = ctx.accounts.cranker.key();
+    let crank_reward = pay_crank_reward(mkt, market, ctx.remaining_accounts, cranker, rewarded)?;
+    let mut log = EventLog::new(mkt);
+    emit!(EventsSettled {
+        market,
+        cranker,
+        first_event_seq,
+        events: take_n as u32,
+        remaining: (queued - take_n) as u32,
+        crank_reward,
+        seq_num: log.next_seq(),
+    });
+    Ok(())
+}
 
-        let mut taker_oo_ai = None;
-        for ai in ctx.remaining_accounts.iter() {
-            if ai.key() == ev.taker_oo { taker_oo_ai = Some(ai); break; }
+/// Credits the cranker's user balance, when it is among the remaining accounts, with
+/// `crank_reward` per settled event that did any work (see `Event::pays_crank_reward`).
+/// Paid out of accrued fees and never more than them, so the reward cannot touch trader
+/// funds. Returns what was paid.
+fn pay_crank_reward(mkt: &mut Market, market: Pubkey, remaining: &[AccountInfo<'_>], cranker: Pubkey, events: u64) -> Result<u64> {
+    let reward = mkt.crank_reward.saturating_mul(events).min(mkt.fees_accrued);
+    if reward == 0 {
+        return Ok(0);
+    }
+    let Ok((ub_ai, mut ub)) = find_user_balance(remaining, market, cranker) else {
+        return Ok(0);
+    };
+    ub.quote_free = ub.quote_free.checked_add(reward).ok_or(SettleError::Overflow)?;
+    store_user_balance(ub_ai, &ub)?;
+    mkt.fees_accrued -= reward;
+    mkt.crank_rewards_paid = mkt.crank_rewards_paid.checked_add(reward).ok_or(SettleError::Overflow)?;
+    Ok(reward)
+}
+
+fn settle_fill(mkt: &mut Market, market: Pubkey, remaining: &[AccountInfo<'_>], ev: &FillEvent) -> Result<()> {
+    // Resting orders learn about their fills here. A taker that did not rest was done
+    // at placement. Either side may have cancelled the filled order and closed its
+    // account since; its balance below still settles.
+    if let Some((maker_oo_ai, mut maker_oo)) = live_open_orders(remaining, market, ev.maker_oo)? {
+        apply_fill(&mut maker_oo, ev.maker_slot, ev.maker_order_id, ev.base_qty);
+        if ev.taker_resting && ev.taker_oo == ev.maker_oo {
+            apply_fill(&mut maker_oo, ev.taker_slot, ev.taker_order_id, ev.base_qty);
         }
-        let taker_oo_ai = taker_oo_ai.ok_or(SettleError::MissingOpenOrders)?;
-        require!(*taker_oo_ai.owner == crate::id(), SettleError::MissingOpenOrders);
-        let taker_oo = load_open_orders(taker_oo_ai)?;
-
-        require_keys_eq!(maker_oo.market, mkt.key(), SettleError::WrongMarket);
-        require_keys_eq!(taker_oo.market, mkt.key(), SettleError::WrongMarket);
-
-        let maker_user = maker_oo.user;
-        let taker_user = taker_oo.user;
-
-        let (maker_ub_ai, mut maker_ub) = {
-            let mut found = None;
-            for ai in ctx.remaining_accounts.iter() {
-                if *ai.owner != crate::id() || !ai.is_writable { continue; }
-                if let Ok(ub) = load_user_balance(ai) {
-                    if ub.market == mkt.key() && ub.user == maker_user {
-                        found = Some((ai, ub));
-                        break;
-                    }
-                }
-            }
-            found.ok_or(SettleError::MissingUserBalance)?
-        };
-
-        let (taker_ub_ai, mut taker_ub) = {
-            let mut found = None;
-            for ai in ctx.remaining_accounts.iter() {
-                if *ai.owner != crate::id() || !ai.is_writable { continue; }
-                if let Ok(ub) = load_user_balance(ai) {
-                    if ub.market == mkt.key() && ub.user == taker_user {
-                        found = Some((ai, ub));
-                        break;
-                    }
-                }
-            }
-            found.ok_or(SettleError::MissingUserBalance)?
-        };
-
-        let quote_u128 = (ev.base_qty as u128)
-            .checked_mul(ev.price_ticks as u128)
-            .ok_or(SettleError::Overflow)?;
-        let quote: u64 = quote_u128.try_into().map_err(|_| SettleError::Overflow)?;
-
-        let fee: u64 = if mkt.fees_bps == 0 {
-            0
-        } else {
-            ((quote as u128)
-                .checked_mul(mkt.fees_bps as u128).ok_or(SettleError::Overflow)? / (BPS_DENOM as u128))
-                .try_into().map_err(|_| SettleError::Overflow)?
-        };
-
-        if ev.taker_side == 0 {
-            maker_ub.base_free = maker_ub.base_free.checked_sub(ev.base_qty).ok_or(SettleError::InsufficientBalance)?;
-            maker_ub.quote_free = maker_ub.quote_free.checked_add(quote).ok_or(SettleError::Overflow)?;
-            taker_ub.base_free = taker_ub.base_free.checked_add(ev.base_qty).ok_or(SettleError::Overflow)?;
-            taker_ub.quote_free = taker_ub.quote_free.checked_sub(quote.checked_add(fee).ok_or(SettleError::Overflow)?).ok_or(SettleError::InsufficientBalance)?;
-        } else {
-            maker_ub.base_free = maker_ub.base_free.checked_add(ev.base_qty).ok_or(SettleError::Overflow)?;
-            maker_ub.quote_free = maker_ub.quote_free.checked_sub(quote).ok_or(SettleError::InsufficientBalance)?;
-            taker_ub.base_free = taker_ub.base_free.checked_sub(ev.base_qty).ok_or(SettleError::InsufficientBalance)?;
-            taker_ub.quote_free = taker_ub.quote_free.checked_add(quote.checked_sub(fee).ok_or(SettleError::Underflow)?).ok_or(SettleError::Overflow)?;
+        store_open_orders(maker_oo_ai, &maker_oo)?;
+    }
+    if ev.taker_resting && ev.taker_oo != ev.maker_oo {
+        if let Some((taker_oo_ai, mut taker_oo)) = live_open_orders(remaining, market, ev.taker_oo)? {
+            apply_fill(&mut taker_oo, ev.taker_slot, ev.taker_order_id, ev.base_qty);
+            store_open_orders(taker_oo_ai, &taker_oo)?;
         }
+    }
+
+    let lot_sizes = LotSizes::of(mkt);
+    let base = lot_sizes.base_native(ev.base_qty)?;
+    let quote = lot_sizes.quote_native(ev.base_qty, ev.price_ticks)?;
+    let fees = FillFees::of(quote, mkt.maker_fee_bps, mkt.taker_fee_bps)?;
+    let maker_fee_net = fees.maker_fee as i128 - fees.maker_rebate as i128;
+    let taker_is_bid = ev.taker_side == 0;
 
-        store_user_balance(maker_ub_ai, &maker_ub)?;
-        store_user_balance(taker_ub_ai, &taker_ub)?;
+    // One at a time, so a trade between two orders of the same user lands in one balance.
+    let (maker_ub_ai, mut maker_ub) = find_user_balance(remaining, market, ev.maker_owner)?;
+    settle_party(&mut maker_ub, !taker_is_bid, ev.maker_locked, base, quote, maker_fee_net)?;
+    store_user_balance(maker_ub_ai, &maker_ub)?;
 
-        mkt.fees_accrued = mkt.fees_accrued.checked_add(fee).ok_or(SettleError::Overflow)?;
+    let (taker_ub_ai, mut taker_ub) = find_user_balance(remaining, market, ev.taker_owner)?;
+    settle_party(&mut taker_ub, taker_is_bid, ev.taker_locked, base, quote, fees.taker_fee as i128)?;
+    store_user_balance(taker_ub_ai, &taker_ub)?;
+
+    mkt.fees_accrued = mkt.fees_accrued.checked_add(fees.net()?).ok_or(SettleError::Overflow)?;
+    mkt.rebates_paid = mkt.rebates_paid.checked_add(fees.maker_rebate).ok_or(SettleError::Overflow)?;
+    Ok(())
+}
+
+fn apply_fill(oo: &mut OpenOrdersLite, slot: u8, order_id: u64, qty: u64) {
+    if let Some(s) = oo.slots.get_mut(slot as usize) {
+        s.apply_fill(order_id, qty);
     }
+}
 
-    if take_n == used {
-        bh.used = 0;
-        blob_store(&evq_ai, &bh)?;
+/// One side of a fill. `locked` is the collateral the order reserved for it; the bid pays
+/// `quote + fee` out of it and receives `base`, the ask hands over `base` and receives
+/// `quote - fee`. Whatever the collateral over-covered is freed.
+fn settle_party(ub: &mut UserBalance, is_bid: bool, locked: u64, base: u64, quote: u64, fee: i128) -> Result<()> {
+    if is_bid {
+        ub.quote_locked = ub.quote_locked.checked_sub(locked).ok_or(SettleError::InsufficientBalance)?;
+        ub.quote_free = apply_quote(ub.quote_free, locked as i128 - quote as i128 - fee)?;
+        ub.base_free = ub.base_free.checked_add(base).ok_or(SettleError::Overflow)?;
     } else {
-        for i in take_n..used {
-            let ev = read_event(&evq_ai, i)?;
-            write_event(&evq_ai, i - take_n, &ev)?;
+        ub.base_locked = ub.base_locked.checked_sub(locked).ok_or(SettleError::InsufficientBalance)?;
+        let spare = locked.checked_sub(base).ok_or(SettleError::InsufficientBalance)?;
+        ub.base_free = ub.base_free.checked_add(spare).ok_or(SettleError::Overflow)?;
+        ub.quote_free = apply_quote(ub.quote_free, quote as i128 - fee)?;
+    }
+    Ok(())
+}
+
+/// `free + delta`, failing when a debit exceeds the balance.
+fn apply_quote(free: u64, delta: i128) -> Result<u64> {
+    let v = free as i128 + delta;
+    require!(v >= 0, SettleError::InsufficientBalance);
+    u64::try_from(v).map_err(|_| error!(SettleError::Overflow))
+}
+
+fn find_user_balance<'a, 'info>(
+    remaining: &'a [AccountInfo<'info>],
+    market: Pubkey,
+    user: Pubkey,
+) -> Result<(&'a AccountInfo<'info>, UserBalance)> {
+    for ai in remaining.iter() {
+        if *ai.owner != crate::id() || !ai.is_writable { continue; }
+        if let Ok(ub) = load_user_balance(ai) {
+            if ub.market == market && ub.user == user {
+                return Ok((ai, ub));
+            }
         }
-        bh.used = (used - take_n) as u32;
-        blob_store(&evq_ai, &bh)?;
     }
+    err!(SettleError::MissingUserBalance)
+}
 
-    Ok(())
+/// An order left the book: free its slot and return whatever collateral it still held.
+/// Cancels and self-trades were applied by the owner's own instruction, so those events
+/// are only a record.
+fn settle_out(market: Pubkey, remaining: &[AccountInfo<'_>], ev: &OutEvent) -> Result<()> {
+    if matches!(ev.reason, OutReason::Cancelled | OutReason::SelfTrade) {
+        return Ok(());
+    }
+    // The owner may have cancelled it after it left the book, and even closed the
+    // account; the slot is then free already, but any collateral is still released below.
+    if let Some((oo_ai, mut oo)) = live_open_orders(remaining, market, ev.owner_oo)? {
+        let idx = ev.slot as usize;
+        if idx < oo.slots.len() && oo.slots[idx].active && oo.slots[idx].order_id == ev.order_id {
+            oo.slots[idx] = OrderSlot::default();
+            store_open_orders(oo_ai, &oo)?;
+        }
+    }
+    if ev.locked == 0 {
+        return Ok(());
+    }
+
+    let (ub_ai, mut ub) = find_user_balance(remaining, market, ev.owner)?;
+    let (free, locked) = if ev.side == 0 { (&mut ub.quote_free, &mut ub.quote_locked) } else { (&mut ub.base_free, &mut ub.base_locked) };
+    *locked = locked.checked_sub(ev.locked).ok_or(SettleError::InsufficientBalance)?;
+    *free = free.checked_add(ev.locked).ok_or(SettleError::Overflow)?;
+    store_user_balance(ub_ai, &ub)
+}
+
+/// The open orders account `key`, or None once its owner has closed it. It must be
+/// passed either way, so a crank cannot skip the update of a live account.
+fn live_open_orders<'a, 'info>(
+    remaining: &'a [AccountInfo<'info>],
+    market: Pubkey,
+    key: Pubkey,
+) -> Result<Option<(&'a AccountInfo<'info>, OpenOrdersLite)>> {
+    let ai = remaining.iter().find(|ai| ai.key() == key).ok_or(SettleError::MissingOpenOrders)?;
+    if *ai.owner != crate::id() || ai.data_is_empty() {
+        return Ok(None);
+    }
+    require!(ai.is_writable, SettleError::MissingOpenOrders);
+    let oo = load_open_orders(ai)?;
+    require_keys_eq!(oo.market, market, SettleError::WrongMarket);
+    Ok(Some((ai, oo)))
 }
 
 #[error_code]
 pub enum SettleError {
-    Unauthorized,
     MissingOpenOrders,
     MissingUserBalance,
     WrongMarket,
@@ -139,7 +217,7 @@ pub enum SettleError {
 }
 
 fn load_open_orders(ai: &AccountInfo<'_>) -> Result<OpenOrdersLite> {
-    let mut data_ref = ai.try_borrow_data()?;
+    let data_ref = ai.try_borrow_data()?;
     let mut bytes: &[u8] = &data_ref;
     let disc = <OpenOrdersLite as Discriminator>::DISCRIMINATOR;
     require!(bytes.len() >= 8 && &bytes[..8] == disc, SettleError::MissingOpenOrders);
@@ -148,7 +226,7 @@ fn load_open_orders(ai: &AccountInfo<'_>) -> Result<OpenOrdersLite> {
 }
 
 fn load_user_balance(ai: &AccountInfo<'_>) -> Result<UserBalance> {
-    let mut data_ref = ai.try_borrow_data()?;
+    let data_ref = ai.try_borrow_data()?;
     let mut bytes: &[u8] = &data_ref;
     let disc = <UserBalance as Discriminator>::DISCRIMINATOR;
     require!(bytes.len() >= 8 && &bytes[..8] == disc, SettleError::MissingUserBalance);
@@ -156,41 +234,18 @@ fn load_user_balance(ai: &AccountInfo<'_>) -> Result<UserBalance> {
     Ok(acc)
 }
 
-fn store_user_balance(ai: &AccountInfo<'_>, ub: &UserBalance) -> Result<()> {
+fn store_open_orders(ai: &AccountInfo<'_>, oo: &OpenOrdersLite) -> Result<()> {
     let mut data_ref = ai.try_borrow_mut_data()?;
-    let data_slice: &mut [u8] = &mut *data_ref;
+    let data_slice: &mut [u8] = &mut data_ref;
     let mut cur = Cursor::new(data_slice);
-    ub.try_serialize(&mut cur)?;
+    oo.try_serialize(&mut cur)?;
     Ok(())
 }
 
-fn event_off(idx: usize) -> usize { Blob::LEN + idx * FillEvent::LEN }
-
-fn read_event(ai: &AccountInfo<'_>, idx: usize) -> Result<FillEvent> {
-    let data = ai.try_borrow_data()?;
-    let off = event_off(idx);
-    let mut rd: &[u8] = &data[off..off + FillEvent::LEN];
-    FillEvent::deserialize(&mut rd).map_err(Into::into)
-}
-
-fn write_event(ai: &AccountInfo<'_>, idx: usize, ev: &FillEvent) -> Result<()> {
-    let mut data = ai.try_borrow_mut_data()?;
-    let off = event_off(idx);
-    let mut cur = std::io::Cursor::new(&mut data[off..off + FillEvent::LEN]);
-    ev.serialize(&mut cur).map_err(Into::into)
-}
-
-fn blob_load(ai: &AccountInfo<'_>) -> Result<Blob> {
-    let data = ai.try_borrow_data()?;
-    let mut rd: &[u8] = &data;
-    let b = Blob::deserialize(&mut rd)?;
-    require!(b.magic == BLOB_MAGIC, SettleError::Overflow);
-    Ok(b)
-}
-
-fn blob_store(ai: &AccountInfo<'_>, b: &Blob) -> Result<()> {
-    let mut data = ai.try_borrow_mut_data()?;
-    let mut cur = std::io::Cursor::new(&mut data[..]);
-    b.serialize(&mut cur)?;
+fn store_user_balance(ai: &AccountInfo<'_>, ub: &UserBalance) -> Result<()> {
+    let mut data_ref = ai.try_borrow_mut_data()?;
+    let data_slice: &mut [u8] = &mut data_ref;
+    let mut cur = Cursor::new(data_slice);
+    ub.try_serialize(&mut cur)?;
     Ok(())
 }
@@ -1,3 +1,8 @@
+// `#[program]` emits its IDL handlers, which call the deprecated `AccountInfo::realloc`
+// in anchor 0.31, as a crate-root `__private` module beside `kerdos_markets`, so an
+// allow on that module does not reach them.
+#![allow(deprecated)]
+
 use anchor_lang::prelude::*;
 
 pub mod domain;
@@ -7,14 +12,19 @@ pub mod ix_init;
 pub mod ix_orders;
 pub mod ix_balances;
 pub mod ix_settle;
+pub mod ix_fees;
 pub mod slab;
+pub mod eventq;
+pub mod lots;
+pub mod events;
 
-pub use crate::domain::{InitParams, GrowParams};
-pub use crate::ix_orders::PlaceOrderParams;
+pub use crate::domain::{InitParams, GrowParams, OrderType, SelfTradeBehavior, EngineKind};
+pub use crate::ix_orders::{PlaceOrderParams, AmendOrderParams, BatchOrderItem};
 use ix_init::*;
 use ix_orders::*;
 use ix_balances::*;
 use ix_settle::*;
+use ix_fees::*;
 
 declare_id!("DjcqZWPwPaB6EwnMXNdcgxkFk26ub6t6FXdSDE7aK3Sb");
 
@@ -34,12 +44,28 @@ pub mod kerdos_markets {
         place_order_handler(ctx, params)
     }
 
-    pub fn cancel_order(ctx: Context<CancelOrder>) -> Result<()> {
-        cancel_order_handler(ctx)
+    pub fn batch_orders(ctx: Context<PlaceOrder>, items: Vec<BatchOrderItem>) -> Result<()> {
+        batch_orders_handler(ctx, items)
+    }
+
+    pub fn cancel_order(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
+        cancel_order_handler(ctx, order_id)
+    }
+
+    pub fn cancel_order_by_client_id(ctx: Context<CancelOrder>, client_order_id: u64) -> Result<()> {
+        cancel_order_by_client_id_handler(ctx, client_order_id)
+    }
+
+    pub fn amend_order(ctx: Context<AmendOrder>, params: AmendOrderParams) -> Result<()> {
+        amend_order_handler(ctx, params)
+    }
+
+    pub fn cancel_all_orders(ctx: Context<CancelOrder>, side: Option<u8>, limit: u8) -> Result<()> {
+        cancel_all_orders_handler(ctx, side, limit)
     }
 
-    pub fn clear_eventq(ctx: Context<ClearEventQ>) -> Result<()> {
-        clear_eventq_handler(ctx)
+    pub fn run_auction(ctx: Context<RunAuction>, limit: u16) -> Result<()> {
+        run_auction_handler(ctx, limit)
     }
 
     pub fn close_oo(ctx: Context<CloseOo>) -> Result<()> {
@@ -73,4 +99,20 @@ pub mod kerdos_markets {
     pub fn settle_events(ctx: Context<SettleEvents>, max_events: u16) -> Result<()> {
         settle_events_handler(ctx, max_events)
     }
+
+    pub fn set_fee_config(ctx: Context<SetFeeConfig>) -> Result<()> {
+        set_fee_config_handler(ctx)
+    }
+
+    pub fn set_crank_reward(ctx: Context<SetCrankReward>, reward: u64) -> Result<()> {
+        set_crank_reward_handler(ctx, reward)
+    }
+
+    pub fn sweep_fees(ctx: Context<SweepFees>) -> Result<()> {
+        sweep_fees_handler(ctx)
+    }
+
+    pub fn claimable_fees(ctx: Context<ClaimableFees>) -> Result<u64> {
+        claimable_fees_handler(ctx)
+    }
 }
@@ -0,0 +1,243 @@
+//! Unit conversions between order terms and native token amounts.
+//!
+//! Orders count size in base lots and price in quote lots per base lot, so
+//! `quote_native = base_lots * price_ticks * quote_lot_size`. Everything that turns
+//! order terms into token amounts goes through here; each division names its rounding.
+
+use anchor_lang::prelude::*;
+use crate::state::Market;
+
+pub const BPS_DENOM: u64 = 10_000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+#[derive(Clone, Copy)]
+pub struct LotSizes {
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+}
+
+impl LotSizes {
+    pub fn of(m: &Market) -> Self {
+        Self { base_lot_size: m.base_lot_size, quote_lot_size: m.quote_lot_size }
+    }
+
+    /// Native base amount of `base_lots`. Exact.
+    pub fn base_native(&self, base_lots: u64) -> Result<u64> {
+        base_lots.checked_mul(self.base_lot_size).ok_or_else(|| error!(LotsError::Overflow))
+    }
+
+    /// Native quote amount of `base_lots` at `price_ticks`. Exact.
+    pub fn quote_native(&self, base_lots: u64, price_ticks: u64) -> Result<u64> {
+        let q = (base_lots as u128)
+            .checked_mul(price_ticks as u128)
+            .and_then(|v| v.checked_mul(self.quote_lot_size as u128))
+            .ok_or(LotsError::Overflow)?;
+        u64::try_from(q).map_err(|_| error!(LotsError::Overflow))
+    }
+
+    /// What a taker pays for `base_lots` at `price_ticks`: the quote plus a `fee_bps`
+    /// taker fee, rounded up as `FillFees` rounds it.
+    pub fn quote_with_fee(&self, base_lots: u64, price_ticks: u64, fee_bps: u16) -> Result<u64> {
+        let quote = self.quote_native(base_lots, price_ticks)?;
+        quote
+            .checked_add(bps_of(quote, fee_bps, Rounding::Up)?)
+            .ok_or_else(|| error!(LotsError::Overflow))
+    }
+
+    /// Base lots whose `quote_with_fee` fits in `quote_native`, rounded down.
+    pub fn base_lots_for_quote(&self, quote_native: u64, price_ticks: u64, fee_bps: u16) -> Result<u64> {
+        let per_lot = (price_ticks as u128)
+            .checked_mul(self.quote_lot_size as u128)
+            .and_then(|v| v.checked_mul(BPS_DENOM as u128 + fee_bps as u128))
+            .ok_or(LotsError::Overflow)?;
+        let lots = div_round(quote_native as u128 * BPS_DENOM as u128, per_lot, Rounding::Down)?;
+        let mut lots = u64::try_from(lots).map_err(|_| error!(LotsError::Overflow))?;
+        // The fee rounds up, which can push the last lot over the budget.
+        while lots > 0 && self.quote_with_fee(lots, price_ticks, fee_bps)? > quote_native {
+            lots -= 1;
+        }
+        Ok(lots)
+    }
+}
+
+/// `amount * bps / 10_000` with the given rounding.
+pub fn bps_of(amount: u64, bps: u16, rounding: Rounding) -> Result<u64> {
+    let num = (amount as u128).checked_mul(bps as u128).ok_or(LotsError::Overflow)?;
+    let v = div_round(num, BPS_DENOM as u128, rounding)?;
+    u64::try_from(v).map_err(|_| error!(LotsError::Overflow))
+}
+
+/// Quote paid on one fill, besides the traded amount.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FillFees {
+    pub taker_fee: u64,
+    pub maker_fee: u64,
+    pub maker_rebate: u64,
+}
+
+impl FillFees {
+    /// Fees round up and rebates round down, and a rebate is capped at the taker fee of
+    /// the same fill, so the market never pays out more than it collects.
+    pub fn of(quote: u64, maker_fee_bps: i16, taker_fee_bps: u16) -> Result<Self> {
+        let taker_fee = bps_of(quote, taker_fee_bps, Rounding::Up)?;
+        let maker_bps = maker_fee_bps.unsigned_abs();
+        Ok(if maker_fee_bps >= 0 {
+            Self { taker_fee, maker_fee: bps_of(quote, maker_bps, Rounding::Up)?, maker_rebate: 0 }
+        } else {
+            Self { taker_fee, maker_fee: 0, maker_rebate: bps_of(quote, maker_bps, Rounding::Down)?.min(taker_fee) }
+        })
+    }
+
+    /// What the market keeps.
+    pub fn net(&self) -> Result<u64> {
+        self.taker_fee
+            .checked_add(self.maker_fee)
+            .and_then(|v| v.checked_sub(self.maker_rebate))
+            .ok_or_else(|| error!(LotsError::Overflow))
+    }
+}
+
+/// What resting and in-flight orders reserve from their owner's balance: base for an
+/// ask, quote plus the highest fee the order could pay for a bid.
+#[derive(Clone, Copy)]
+pub struct Collateral {
+    pub lots: LotSizes,
+    pub fee_bps: u16,
+}
+
+impl Collateral {
+    pub fn of(m: &Market) -> Self {
+        Self { lots: LotSizes::of(m), fee_bps: m.taker_fee_bps.max(m.maker_fee_bps.max(0) as u16) }
+    }
+
+    /// Collateral for `base_lots` at `price_ticks`. The bid fee allowance is rounded up
+    /// per lot, so the amount is linear in `base_lots` and covers the fee of any fill at
+    /// or below `price_ticks`, however the order is split.
+    pub fn for_order(&self, is_bid: bool, base_lots: u64, price_ticks: u64) -> Result<u64> {
+        if !is_bid {
+            return self.lots.base_native(base_lots);
+        }
+        let per_lot = self.lots.quote_native(1, price_ticks)?;
+        let per_lot = per_lot
+            .checked_add(bps_of(per_lot, self.fee_bps, Rounding::Up)?)
+            .ok_or(LotsError::Overflow)?;
+        base_lots.checked_mul(per_lot).ok_or_else(|| error!(LotsError::Overflow))
+    }
+
+    /// Collateral for a market buy: `quote_limit` itself, which covers its fees too.
+    pub fn for_market_buy(&self, quote_limit: u64) -> u64 {
+        quote_limit
+    }
+}
+
+fn div_round(num: u128, den: u128, rounding: Rounding) -> Result<u128> {
+    require!(den > 0, LotsError::ZeroDivisor);
+    Ok(match rounding {
+        Rounding::Down => num / den,
+        Rounding::Up => num.div_ceil(den),
+    })
+}
+
+/// Lot sizes must split one whole token evenly, or a whole-token price would not be
+/// expressible in ticks.
+pub fn validate_lot_size(lot_size: u64, decimals: u8) -> Result<()> {
+    let one = 10u64.checked_pow(decimals as u32).ok_or(LotsError::Overflow)?;
+    require!(lot_size > 0 && lot_size <= one && one.is_multiple_of(lot_size), LotsError::InvalidLotSize);
+    Ok(())
+}
+
+#[error_code]
+pub enum LotsError {
+    #[msg("conversion overflow")]
+    Overflow,
+    #[msg("division by zero")]
+    ZeroDivisor,
+    #[msg("lot size must divide one whole token")]
+    InvalidLotSize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOTS: LotSizes = LotSizes { base_lot_size: 1_000, quote_lot_size: 10 };
+
+    #[test]
+    fn native_amounts() {
+        assert_eq!(LOTS.base_native(7).unwrap(), 7_000);
+        assert_eq!(LOTS.quote_native(7, 3).unwrap(), 210);
+        assert!(LOTS.quote_native(u64::MAX, 2).is_err());
+        assert!(LOTS.base_native(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn lots_for_quote_rounds_down() {
+        assert_eq!(LOTS.base_lots_for_quote(209, 3, 0).unwrap(), 6);
+        assert_eq!(LOTS.base_lots_for_quote(210, 3, 0).unwrap(), 7);
+        assert!(LOTS.base_lots_for_quote(210, 0, 0).is_err());
+    }
+
+    #[test]
+    fn lots_for_quote_leave_room_for_the_fee() {
+        // 7 lots cost 210 plus a fee of 1 once any fee is charged.
+        assert_eq!(LOTS.quote_with_fee(7, 3, 10).unwrap(), 211);
+        assert_eq!(LOTS.base_lots_for_quote(210, 3, 10).unwrap(), 6);
+        assert_eq!(LOTS.base_lots_for_quote(211, 3, 10).unwrap(), 7);
+        for budget in 0..2_000 {
+            let lots = LOTS.base_lots_for_quote(budget, 3, 25).unwrap();
+            assert!(LOTS.quote_with_fee(lots, 3, 25).unwrap() <= budget);
+            assert!(LOTS.quote_with_fee(lots + 1, 3, 25).unwrap() > budget);
+        }
+    }
+
+    #[test]
+    fn bps_rounding() {
+        assert_eq!(bps_of(10_001, 10, Rounding::Down).unwrap(), 10);
+        assert_eq!(bps_of(10_001, 10, Rounding::Up).unwrap(), 11);
+        assert_eq!(bps_of(10_000, 10, Rounding::Up).unwrap(), 10);
+        assert_eq!(bps_of(u64::MAX, 10_000, Rounding::Down).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn lot_sizes_against_decimals() {
+        assert!(validate_lot_size(1, 0).is_ok());
+        assert!(validate_lot_size(1_000, 6).is_ok());
+        assert!(validate_lot_size(1_000_000, 6).is_ok());
+        assert!(validate_lot_size(0, 6).is_err());
+        assert!(validate_lot_size(3, 6).is_err());
+        assert!(validate_lot_size(10_000_000, 6).is_err());
+    }
+
+    #[test]
+    fn maker_fees_and_rebates() {
+        let f = FillFees::of(10_001, 2, 10).unwrap();
+        assert_eq!(f, FillFees { taker_fee: 11, maker_fee: 3, maker_rebate: 0 });
+        assert_eq!(f.net().unwrap(), 14);
+
+        let f = FillFees::of(10_001, -2, 10).unwrap();
+        assert_eq!(f, FillFees { taker_fee: 11, maker_fee: 0, maker_rebate: 2 });
+        assert_eq!(f.net().unwrap(), 9);
+
+        // A rebate rate above the taker rate still only hands back the taker fee.
+        let f = FillFees::of(10_000, -20, 10).unwrap();
+        assert_eq!((f.maker_rebate, f.net().unwrap()), (10, 0));
+    }
+
+    #[test]
+    fn collateral_covers_every_split() {
+        let c = Collateral { lots: LOTS, fee_bps: 10 };
+        assert_eq!(c.for_order(false, 7, 3).unwrap(), 7_000);
+        // 30 quote per lot plus a 1-unit fee allowance.
+        assert_eq!(c.for_order(true, 7, 3).unwrap(), 217);
+        for q in 1..=7 {
+            let fee = FillFees::of(LOTS.quote_native(q, 3).unwrap(), 0, 10).unwrap().taker_fee;
+            assert!(LOTS.quote_native(q, 3).unwrap() + fee <= c.for_order(true, q, 3).unwrap());
+        }
+        assert_eq!(c.for_market_buy(10_001), 10_001);
+    }
+}
@@ -3,6 +3,9 @@ use crate::state::Blob;
 
 pub const IDX_NULL: u32 = u32::MAX;
 
+/// `SlabNode.flags`: only `base_qty` is visible; `hidden_qty` refills it in `display_qty` slices.
+pub const FLAG_ICEBERG: u8 = 1;
+
 #[repr(C)]
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
 pub struct SlabHeader {
@@ -18,23 +21,37 @@ impl SlabHeader {
     pub const LEN: usize = 4 + 4 + 4 + 4 + 4 + 1 + 3;
 }
 
+/// One resting order of wallet `owner`, held in slot `slot` of its open orders
+/// account `oo`. The price tree holds a single node per price level (the level
+/// head, oldest order); younger orders at the same price queue behind it through
+/// `next`/`prev`. The head's `prev` points at the level tail (IDX_NULL while it
+/// is alone) and every other node's `prev` at its predecessor. Free nodes reuse
+/// `next` as the freelist link.
 #[repr(C)]
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
 pub struct SlabNode {
     pub price_ticks: u64,
     pub base_qty: u64,
     pub oo: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub client_order_id: u64,
     pub ts: u64,
+    /// Unix timestamp after which the order no longer trades; 0 = good-til-cancelled.
+    pub expires_at: i64,
+    pub hidden_qty: u64,
+    pub display_qty: u64,
     pub parent: u32,
     pub left: u32,
     pub right: u32,
     pub next: u32,
     pub prev: u32,
     pub flags: u8,
-    pub pad: [u8; 3],
+    pub slot: u8,
+    pub pad: [u8; 2],
 }
 impl SlabNode {
-    pub const LEN: usize = 8 + 8 + 32 + 8 + (4 * 5) + 1 + 3;
+    pub const LEN: usize = 8 + 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + (4 * 5) + 1 + 1 + 2;
 }
 
 #[error_code]
@@ -43,6 +60,8 @@ pub enum SlabError {
     #[msg("invalid grow argument")] InvalidGrow,
     #[msg("no free nodes")] NoFreeNodes,
     #[msg("cannot grow capacity")] NoGrowth,
+    #[msg("price level not found")] LevelNotFound,
+    #[msg("invalid order quantity")] InvalidQty,
 }
 
 pub fn region_len_for(nodes: u32) -> usize {
@@ -103,8 +122,7 @@ pub fn init(ai: &AccountInfo<'_>, cap: u32) -> Result<()> {
     };
     store_header(ai, &h)?;
     for i in 0..cap {
-        let mut n = SlabNode::default();
-        n.next = if i + 1 < cap { i + 1 } else { IDX_NULL };
+        let n = SlabNode { next: if i + 1 < cap { i + 1 } else { IDX_NULL }, ..SlabNode::default() };
         write_node(ai, i, &n)?;
     }
     store_header(ai, &h)
@@ -114,8 +132,7 @@ pub fn grow(ai: &AccountInfo<'_>, new_cap: u32) -> Result<()> {
     let mut h = load_header(ai)?;
     require!(new_cap >= h.capacity, SlabError::InvalidGrow);
     for i in (h.capacity)..new_cap {
-        let mut n = SlabNode::default();
-        n.next = h.free_head;
+        let n = SlabNode { next: h.free_head, ..SlabNode::default() };
         write_node(ai, i, &n)?;
         h.free_head = i;
     }
@@ -132,8 +149,7 @@ fn alloc(ai: &AccountInfo<'_>) -> Result<u32> {
     // Fallback: if free list is still empty but capacity remains, scan for an unused slot.
     if h.free_head == IDX_NULL && h.used < h.capacity {
         if let Some(idx) = find_unused_index(ai, h.capacity, h.root)? {
-            let mut n = SlabNode::default();
-            write_node(ai, idx, &n)?;
+            write_node(ai, idx, &SlabNode::default())?;
             h.used = h.used.saturating_add(1);
             store_header(ai, &h)?;
             return Ok(idx);
@@ -151,14 +167,12 @@ fn alloc(ai: &AccountInfo<'_>) -> Result<u32> {
     Ok(idx)
 }
 
-fn free(ai: &AccountInfo<'_>, idx: u32) -> Result<()> {
-    let mut h = load_header(ai)?;
-    let mut n = read_node(ai, idx)?;
-    n.next = h.free_head;
+fn free(ai: &AccountInfo<'_>, h: &mut SlabHeader, idx: u32) -> Result<()> {
+    let n = SlabNode { next: h.free_head, ..SlabNode::default() };
     write_node(ai, idx, &n)?;
     h.free_head = idx;
     h.used = h.used.saturating_sub(1);
-    store_header(ai, &h)
+    Ok(())
 }
 
 fn extreme(ai: &AccountInfo<'_>, mut idx: u32, is_bid_book: bool) -> Result<u32> {
@@ -171,54 +185,78 @@ fn extreme(ai: &AccountInfo<'_>, mut idx: u32, is_bid_book: bool) -> Result<u32>
     }
 }
 
-fn recompute_best(ai: &AccountInfo<'_>, is_bid_book: bool) -> Result<u32> {
-    let h = load_header(ai)?;
-    if h.root == IDX_NULL { return Ok(IDX_NULL); }
-    extreme(ai, h.root, is_bid_book)
+fn find_level(ai: &AccountInfo<'_>, h: &SlabHeader, price_ticks: u64) -> Result<u32> {
+    let mut cur = h.root;
+    while cur != IDX_NULL {
+        let n = read_node(ai, cur)?;
+        if price_ticks == n.price_ticks { return Ok(cur); }
+        cur = if price_ticks < n.price_ticks { n.left } else { n.right };
+    }
+    Ok(IDX_NULL)
 }
 
-fn bst_insert(ai: &AccountInfo<'_>, idx: u32, is_bid_book: bool) -> Result<()> {
-    let mut h = load_header(ai)?;
-    let ni = read_node(ai, idx)?;
+fn locate_order(ai: &AccountInfo<'_>, h: &SlabHeader, price_ticks: u64, oo: Pubkey, order_id: u64) -> Result<u32> {
+    let mut idx = find_level(ai, h, price_ticks)?;
+    while idx != IDX_NULL {
+        let n = read_node(ai, idx)?;
+        if n.oo == oo && n.order_id == order_id {
+            return Ok(idx);
+        }
+        idx = n.next;
+    }
+    Ok(IDX_NULL)
+}
+
+fn link_order(ai: &AccountInfo<'_>, h: &mut SlabHeader, idx: u32, is_bid_book: bool) -> Result<()> {
+    let mut ni = read_node(ai, idx)?;
+    ni.parent = IDX_NULL;
+    ni.left = IDX_NULL;
+    ni.right = IDX_NULL;
+    ni.next = IDX_NULL;
+    ni.prev = IDX_NULL;
     if h.root == IDX_NULL {
-        let mut ni2 = ni;
-        ni2.parent = IDX_NULL;
-        write_node(ai, idx, &ni2)?;
+        write_node(ai, idx, &ni)?;
         h.root = idx;
         h.best = idx;
-        store_header(ai, &h)?;
         return Ok(());
     }
     let mut cur = h.root;
     loop {
-        let cn = read_node(ai, cur)?;
-        if ni.price_ticks < cn.price_ticks {
-            if cn.left == IDX_NULL {
-                let mut cn2 = cn; cn2.left = idx;
-                write_node(ai, cur, &cn2)?;
-                let mut ni2 = ni; ni2.parent = cur;
-                write_node(ai, idx, &ni2)?;
-                break;
-            } else { cur = cn.left; }
-        } else {
-            if cn.right == IDX_NULL {
-                let mut cn2 = cn; cn2.right = idx;
-                write_node(ai, cur, &cn2)?;
-                let mut ni2 = ni; ni2.parent = cur;
-                write_node(ai, idx, &ni2)?;
-                break;
-            } else { cur = cn.right; }
+        let mut cn = read_node(ai, cur)?;
+        if ni.price_ticks == cn.price_ticks {
+            // Existing level: join the back of its queue.
+            let tail = if cn.prev == IDX_NULL { cur } else { cn.prev };
+            if tail == cur {
+                cn.next = idx;
+            } else {
+                let mut tn = read_node(ai, tail)?;
+                tn.next = idx;
+                write_node(ai, tail, &tn)?;
+            }
+            cn.prev = idx;
+            write_node(ai, cur, &cn)?;
+            ni.prev = tail;
+            return write_node(ai, idx, &ni);
+        }
+        let go_left = ni.price_ticks < cn.price_ticks;
+        let child = if go_left { cn.left } else { cn.right };
+        if child == IDX_NULL {
+            if go_left { cn.left = idx; } else { cn.right = idx; }
+            write_node(ai, cur, &cn)?;
+            ni.parent = cur;
+            write_node(ai, idx, &ni)?;
+            break;
         }
+        cur = child;
     }
-    let best_idx = h.best;
-    if best_idx == IDX_NULL {
+    if h.best == IDX_NULL {
         h.best = idx;
     } else {
-        let bn = read_node(ai, best_idx)?;
+        let bn = read_node(ai, h.best)?;
         let better = if is_bid_book { ni.price_ticks > bn.price_ticks } else { ni.price_ticks < bn.price_ticks };
         if better { h.best = idx; }
     }
-    store_header(ai, &h)
+    Ok(())
 }
 
 fn replace_parent_link(ai: &AccountInfo<'_>, parent: u32, old_child: u32, new_child: u32) -> Result<()> {
@@ -229,61 +267,104 @@ fn replace_parent_link(ai: &AccountInfo<'_>, parent: u32, old_child: u32, new_ch
     write_node(ai, parent, &p)
 }
 
-fn bst_delete(ai: &AccountInfo<'_>, idx: u32, is_bid_book: bool) -> Result<u32> {
-    let mut h = load_header(ai)?;
+fn set_parent(ai: &AccountInfo<'_>, idx: u32, parent: u32) -> Result<()> {
+    if idx == IDX_NULL { return Ok(()); }
     let mut n = read_node(ai, idx)?;
-    let mut to_free = IDX_NULL;
-    if n.left == IDX_NULL || n.right == IDX_NULL {
-        let child = if n.left != IDX_NULL { n.left } else { n.right };
-        if n.parent == IDX_NULL {
-            h.root = child;
-        } else {
-            replace_parent_link(ai, n.parent, idx, child)?;
-        }
-        if child != IDX_NULL {
-            let mut c = read_node(ai, child)?;
-            c.parent = n.parent;
-            write_node(ai, child, &c)?;
-        }
-        to_free = idx;
+    n.parent = parent;
+    write_node(ai, idx, &n)
+}
+
+/// Puts the subtree rooted at `v` where `u` (whose parent is `u_parent`) used to hang.
+fn transplant(ai: &AccountInfo<'_>, h: &mut SlabHeader, u: u32, u_parent: u32, v: u32) -> Result<()> {
+    if u_parent == IDX_NULL {
+        h.root = v;
     } else {
-        let succ_idx = {
-            let mut t = n.right;
-            loop {
-                let tnode = read_node(ai, t)?;
-                if tnode.left == IDX_NULL { break t; }
-                t = tnode.left;
-            }
-        };
-        let succ = read_node(ai, succ_idx)?;
-        n.price_ticks = succ.price_ticks;
-        n.base_qty = succ.base_qty;
-        n.oo = succ.oo;
-        n.ts = succ.ts;
-        write_node(ai, idx, &n)?;
-        let s_child = succ.right;
-        if succ.parent == IDX_NULL {
-            h.root = s_child;
-        } else {
-            replace_parent_link(ai, succ.parent, succ_idx, s_child)?;
-        }
-        if s_child != IDX_NULL {
-            let mut sc = read_node(ai, s_child)?;
-            sc.parent = succ.parent;
-            write_node(ai, s_child, &sc)?;
+        replace_parent_link(ai, u_parent, u, v)?;
+    }
+    set_parent(ai, v, u_parent)
+}
+
+/// Unlinks a level head from the price tree. Nodes keep their slab index, so
+/// queues hanging off other levels are never disturbed.
+fn tree_delete(ai: &AccountInfo<'_>, h: &mut SlabHeader, zi: u32) -> Result<()> {
+    let z = read_node(ai, zi)?;
+    if z.left == IDX_NULL {
+        transplant(ai, h, zi, z.parent, z.right)?;
+    } else if z.right == IDX_NULL {
+        transplant(ai, h, zi, z.parent, z.left)?;
+    } else {
+        let yi = extreme(ai, z.right, false)?;
+        let y = read_node(ai, yi)?;
+        if y.parent != zi {
+            transplant(ai, h, yi, y.parent, y.right)?;
+            let mut y2 = read_node(ai, yi)?;
+            y2.right = z.right;
+            write_node(ai, yi, &y2)?;
+            set_parent(ai, z.right, yi)?;
         }
-        to_free = succ_idx;
+        transplant(ai, h, zi, z.parent, yi)?;
+        let mut y3 = read_node(ai, yi)?;
+        y3.left = z.left;
+        write_node(ai, yi, &y3)?;
+        set_parent(ai, z.left, yi)?;
     }
-    if h.root == IDX_NULL {
-        h.best = IDX_NULL;
+    Ok(())
+}
+
+/// Hands the tree position of level head `idx` to the next order in its queue.
+fn promote_next(ai: &AccountInfo<'_>, h: &mut SlabHeader, idx: u32, n: &SlabNode) -> Result<()> {
+    let si = n.next;
+    let mut s = read_node(ai, si)?;
+    s.parent = n.parent;
+    s.left = n.left;
+    s.right = n.right;
+    s.prev = if n.prev == si { IDX_NULL } else { n.prev };
+    write_node(ai, si, &s)?;
+    if n.parent == IDX_NULL {
+        h.root = si;
     } else {
-        h.best = recompute_best(ai, is_bid_book)?;
+        replace_parent_link(ai, n.parent, idx, si)?;
     }
-    store_header(ai, &h)?;
-    if to_free != IDX_NULL {
-        free(ai, to_free)?;
+    set_parent(ai, n.left, si)?;
+    set_parent(ai, n.right, si)?;
+    if h.best == idx { h.best = si; }
+    Ok(())
+}
+
+/// Removes one order from the book, wherever it sits in its level queue, and frees its node.
+fn unlink_order(ai: &AccountInfo<'_>, h: &mut SlabHeader, idx: u32, is_bid_book: bool) -> Result<()> {
+    detach_order(ai, h, idx, is_bid_book)?;
+    free(ai, h, idx)
+}
+
+/// Takes one order out of the tree and its level queue but keeps its node allocated.
+fn detach_order(ai: &AccountInfo<'_>, h: &mut SlabHeader, idx: u32, is_bid_book: bool) -> Result<()> {
+    let n = read_node(ai, idx)?;
+    let head = find_level(ai, h, n.price_ticks)?;
+    require!(head != IDX_NULL, SlabError::LevelNotFound);
+    if head != idx {
+        let p = n.prev;
+        let mut pn = read_node(ai, p)?;
+        pn.next = n.next;
+        if n.next != IDX_NULL {
+            let mut nx = read_node(ai, n.next)?;
+            nx.prev = p;
+            write_node(ai, n.next, &nx)?;
+        } else if p == head {
+            pn.prev = IDX_NULL;
+        } else {
+            let mut hn = read_node(ai, head)?;
+            hn.prev = p;
+            write_node(ai, head, &hn)?;
+        }
+        write_node(ai, p, &pn)?;
+    } else if n.next != IDX_NULL {
+        promote_next(ai, h, idx, &n)?;
+    } else {
+        tree_delete(ai, h, idx)?;
+        h.best = extreme(ai, h.root, is_bid_book)?;
     }
-    Ok(h.best)
+    Ok(())
 }
 
 pub fn ensure_free_nodes_grow<F>(ai: &AccountInfo<'_>, max_cap: u32, need_free: u32, bytes_grow: F) -> Result<()>
@@ -314,31 +395,37 @@ where
     grow(ai, new_cap)
 }
 
-fn rebuild_freelist(ai: &AccountInfo<'_>, mut h: SlabHeader) -> Result<SlabHeader> {
-    let mut used: Vec<bool> = vec![false; h.capacity as usize];
+/// Marks every node reachable from the tree, including orders queued behind level heads.
+fn mark_live(ai: &AccountInfo<'_>, capacity: u32, root: u32) -> Result<Vec<bool>> {
+    let mut live: Vec<bool> = vec![false; capacity as usize];
     let mut stack: Vec<u32> = vec![];
-    if h.root != IDX_NULL {
-        stack.push(h.root);
+    if root != IDX_NULL {
+        stack.push(root);
     }
     while let Some(idx) = stack.pop() {
-        if idx == IDX_NULL || idx as usize >= used.len() {
+        if idx == IDX_NULL || idx as usize >= live.len() {
             continue;
         }
-        if used[idx as usize] {
+        if live[idx as usize] {
             continue;
         }
-        used[idx as usize] = true;
+        live[idx as usize] = true;
         let n = read_node(ai, idx)?;
         if n.left != IDX_NULL { stack.push(n.left); }
         if n.right != IDX_NULL { stack.push(n.right); }
+        if n.next != IDX_NULL { stack.push(n.next); }
     }
+    Ok(live)
+}
+
+fn rebuild_freelist(ai: &AccountInfo<'_>, mut h: SlabHeader) -> Result<SlabHeader> {
+    let used = mark_live(ai, h.capacity, h.root)?;
 
     let mut free_head = IDX_NULL;
     for i in (0..h.capacity).rev() {
         if used[i as usize] { continue; }
         // Reset free nodes to a clean default and push onto the freelist.
-        let mut n = SlabNode::default();
-        n.next = free_head;
+        let n = SlabNode { next: free_head, ..SlabNode::default() };
         write_node(ai, i, &n)?;
         free_head = i;
     }
@@ -350,89 +437,164 @@ fn rebuild_freelist(ai: &AccountInfo<'_>, mut h: SlabHeader) -> Result<SlabHeade
 }
 
 fn find_unused_index(ai: &AccountInfo<'_>, capacity: u32, root: u32) -> Result<Option<u32>> {
-    let mut used: Vec<bool> = vec![false; capacity as usize];
-    let mut stack: Vec<u32> = vec![];
-    if root != IDX_NULL { stack.push(root); }
-    while let Some(idx) = stack.pop() {
-        if idx == IDX_NULL || idx as usize >= used.len() { continue; }
-        if used[idx as usize] { continue; }
-        used[idx as usize] = true;
+    let used = mark_live(ai, capacity, root)?;
+    Ok(used.iter().position(|&b| !b).map(|i| i as u32))
+}
+
+/// Rests `order` at the back of its price level. Tree and queue links are assigned here.
+pub fn insert_order(ai: &AccountInfo<'_>, is_bid_book: bool, order: SlabNode) -> Result<u32> {
+    let idx = alloc(ai)?;
+    write_node(ai, idx, &order)?;
+    let mut h = load_header(ai)?;
+    link_order(ai, &mut h, idx, is_bid_book)?;
+    store_header(ai, &h)?;
+    Ok(idx)
+}
+
+/// Oldest order at the best price.
+pub fn peek_best(ai: &AccountInfo<'_>) -> Result<Option<SlabNode>> {
+    let h = load_header(ai)?;
+    if h.best == IDX_NULL { return Ok(None); }
+    Ok(Some(read_node(ai, h.best)?))
+}
+
+/// Up to `max` orders at the best price, oldest first.
+pub fn best_level(ai: &AccountInfo<'_>, max: usize) -> Result<Vec<SlabNode>> {
+    let h = load_header(ai)?;
+    let mut out = Vec::new();
+    let mut idx = h.best;
+    while idx != IDX_NULL && out.len() < max {
         let n = read_node(ai, idx)?;
-        if n.left != IDX_NULL { stack.push(n.left); }
-        if n.right != IDX_NULL { stack.push(n.right); }
+        idx = n.next;
+        out.push(n);
     }
-    for i in 0..capacity {
-        if !used[i as usize] {
-            return Ok(Some(i));
+    Ok(out)
+}
+
+/// Up to `max` orders in match priority: level by level from the best price, oldest
+/// first within a level.
+pub fn best_orders(ai: &AccountInfo<'_>, is_bid_book: bool, max: usize) -> Result<Vec<SlabNode>> {
+    let h = load_header(ai)?;
+    let mut out = Vec::new();
+    let mut level = h.best;
+    while level != IDX_NULL && out.len() < max {
+        let head = read_node(ai, level)?;
+        out.push(head);
+        let mut idx = head.next;
+        while idx != IDX_NULL && out.len() < max {
+            let n = read_node(ai, idx)?;
+            idx = n.next;
+            out.push(n);
         }
+        level = next_level(ai, level, &head, is_bid_book)?;
     }
-    Ok(None)
+    Ok(out)
 }
 
-pub fn insert_order(
+/// The level after `idx` in match priority: the in-order successor for asks, the
+/// predecessor for bids.
+fn next_level(ai: &AccountInfo<'_>, mut idx: u32, n: &SlabNode, is_bid_book: bool) -> Result<u32> {
+    let down = if is_bid_book { n.left } else { n.right };
+    if down != IDX_NULL {
+        return extreme(ai, down, is_bid_book);
+    }
+    let mut parent = n.parent;
+    while parent != IDX_NULL {
+        let p = read_node(ai, parent)?;
+        let came_from = if is_bid_book { p.left } else { p.right };
+        if came_from != idx {
+            return Ok(parent);
+        }
+        idx = parent;
+        parent = p.parent;
+    }
+    Ok(IDX_NULL)
+}
+
+pub fn consume_best_by(ai: &AccountInfo<'_>, is_bid_book: bool, qty: u64) -> Result<()> {
+    let mut h = load_header(ai)?;
+    let idx = h.best;
+    consume_at(ai, &mut h, idx, is_bid_book, qty)
+}
+
+/// Takes `qty` off the order `order_id` of `oo` resting at `price_ticks`, wherever it
+/// sits in its level.
+pub fn consume_order_by(
     ai: &AccountInfo<'_>,
     is_bid_book: bool,
     price_ticks: u64,
-    base_qty: u64,
     oo: Pubkey,
-    ts: u64,
+    order_id: u64,
+    qty: u64,
 ) -> Result<()> {
-    let idx = alloc(ai)?;
-    let n = SlabNode {
-        price_ticks,
-        base_qty,
-        oo,
-        ts,
-        parent: IDX_NULL,
-        left: IDX_NULL,
-        right: IDX_NULL,
-        next: IDX_NULL,
-        prev: IDX_NULL,
-        flags: 0,
-        pad: [0; 3],
-    };
-    write_node(ai, idx, &n)?;
-    bst_insert(ai, idx, is_bid_book)
-}
-
-pub fn peek_best(ai: &AccountInfo<'_>) -> Result<Option<(Pubkey, u64, u64)>> {
-    let h = load_header(ai)?;
-    if h.best == IDX_NULL { return Ok(None); }
-    let n = read_node(ai, h.best)?;
-    Ok(Some((n.oo, n.price_ticks, n.base_qty)))
+    let mut h = load_header(ai)?;
+    let idx = locate_order(ai, &h, price_ticks, oo, order_id)?;
+    consume_at(ai, &mut h, idx, is_bid_book, qty)
 }
 
-pub fn consume_best_by(ai: &AccountInfo<'_>, is_bid_book: bool, qty: u64) -> Result<()> {
-    let h = load_header(ai)?;
-    if h.best == IDX_NULL { return Ok(()); }
-    let mut n = read_node(ai, h.best)?;
+fn consume_at(ai: &AccountInfo<'_>, h: &mut SlabHeader, idx: u32, is_bid_book: bool, qty: u64) -> Result<()> {
+    if idx == IDX_NULL { return Ok(()); }
+    let mut n = read_node(ai, idx)?;
     if qty >= n.base_qty {
-        let idx = h.best;
-        let _ = bst_delete(ai, idx, is_bid_book)?;
+        if n.flags & FLAG_ICEBERG != 0 && n.hidden_qty > 0 {
+            // The refilled slice loses time priority and rejoins its level at the back,
+            // keeping its node.
+            detach_order(ai, h, idx, is_bid_book)?;
+            let shown = n.hidden_qty.min(n.display_qty);
+            write_node(ai, idx, &SlabNode { base_qty: shown, hidden_qty: n.hidden_qty - shown, ..n })?;
+            link_order(ai, h, idx, is_bid_book)?;
+        } else {
+            unlink_order(ai, h, idx, is_bid_book)?;
+        }
+        store_header(ai, h)?;
     } else {
         n.base_qty = n.base_qty.saturating_sub(qty);
-        write_node(ai, h.best, &n)?;
+        write_node(ai, idx, &n)?;
     }
     Ok(())
 }
 
-pub fn remove_by_oo(ai: &AccountInfo<'_>, is_bid_book: bool, target: Pubkey) -> Result<bool> {
-    let h = load_header(ai)?;
-    if h.root == IDX_NULL {
+/// Removes the order `order_id` of `oo` resting at `price_ticks`. Only that price level is walked.
+pub fn remove_order(
+    ai: &AccountInfo<'_>,
+    is_bid_book: bool,
+    price_ticks: u64,
+    oo: Pubkey,
+    order_id: u64,
+) -> Result<bool> {
+    let mut h = load_header(ai)?;
+    let idx = locate_order(ai, &h, price_ticks, oo, order_id)?;
+    if idx == IDX_NULL {
         return Ok(false);
     }
-    let mut stack = vec![h.root];
-    while let Some(idx) = stack.pop() {
-        if idx == IDX_NULL { continue; }
-        let n = read_node(ai, idx)?;
-        if n.oo == target {
-            let _ = bst_delete(ai, idx, is_bid_book)?;
-            return Ok(true);
-        }
-        if n.left != IDX_NULL { stack.push(n.left); }
-        if n.right != IDX_NULL { stack.push(n.right); }
+    unlink_order(ai, &mut h, idx, is_bid_book)?;
+    store_header(ai, &h)?;
+    Ok(true)
+}
+
+pub fn find_order(ai: &AccountInfo<'_>, price_ticks: u64, oo: Pubkey, order_id: u64) -> Result<Option<SlabNode>> {
+    let h = load_header(ai)?;
+    let idx = locate_order(ai, &h, price_ticks, oo, order_id)?;
+    if idx == IDX_NULL {
+        return Ok(None);
     }
-    Ok(false)
+    Ok(Some(read_node(ai, idx)?))
+}
+
+/// Shrinks a resting order in place to `qty` in total, keeping its queue position.
+/// An iceberg gives up hidden size first.
+pub fn reduce_order(ai: &AccountInfo<'_>, price_ticks: u64, oo: Pubkey, order_id: u64, qty: u64) -> Result<bool> {
+    let h = load_header(ai)?;
+    let idx = locate_order(ai, &h, price_ticks, oo, order_id)?;
+    if idx == IDX_NULL {
+        return Ok(false);
+    }
+    let mut n = read_node(ai, idx)?;
+    require!(qty > 0 && qty <= n.base_qty + n.hidden_qty, SlabError::InvalidQty);
+    n.base_qty = n.base_qty.min(qty);
+    n.hidden_qty = qty - n.base_qty;
+    write_node(ai, idx, &n)?;
+    Ok(true)
 }
 
 pub fn used_nodes(ai: &AccountInfo<'_>) -> Result<u32> {
@@ -444,3 +606,267 @@ pub fn capacity_nodes(ai: &AccountInfo<'_>) -> Result<u32> {
     let h = load_header(ai)?;
     Ok(h.capacity)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_book<F: FnOnce(&AccountInfo<'_>)>(cap: u32, f: F) {
+        let key = Pubkey::new_unique();
+        let owner = crate::id();
+        let mut lamports = 0u64;
+        let mut data = vec![0u8; Blob::LEN + region_len_for(cap)];
+        let ai = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        init(&ai, cap).unwrap();
+        f(&ai);
+    }
+
+    fn order(price_ticks: u64, base_qty: u64, oo: Pubkey, order_id: u64) -> SlabNode {
+        SlabNode { price_ticks, base_qty, oo, order_id, ts: order_id, ..SlabNode::default() }
+    }
+
+    fn place(ai: &AccountInfo<'_>, is_bid_book: bool, price_ticks: u64, oo: Pubkey, order_id: u64) {
+        insert_order(ai, is_bid_book, order(price_ticks, 10, oo, order_id)).unwrap();
+    }
+
+    fn drain(ai: &AccountInfo<'_>, is_bid_book: bool) -> Vec<(Pubkey, u64)> {
+        let mut out = vec![];
+        while let Some(n) = peek_best(ai).unwrap() {
+            out.push((n.oo, n.price_ticks));
+            consume_best_by(ai, is_bid_book, n.base_qty).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn fifo_at_equal_bid_price() {
+        with_book(16, |ai| {
+            let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+            place(ai, true, 100, a, 1);
+            place(ai, true, 100, b, 2);
+            place(ai, true, 100, c, 3);
+            assert_eq!(drain(ai, true), vec![(a, 100), (b, 100), (c, 100)]);
+            assert_eq!(used_nodes(ai).unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn fifo_at_equal_ask_price() {
+        with_book(16, |ai| {
+            let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+            place(ai, false, 100, a, 1);
+            place(ai, false, 100, b, 2);
+            place(ai, false, 100, c, 3);
+            assert_eq!(drain(ai, false), vec![(a, 100), (b, 100), (c, 100)]);
+        });
+    }
+
+    #[test]
+    fn price_then_time_across_levels() {
+        with_book(32, |ai| {
+            let k: Vec<Pubkey> = (0..8).map(|_| Pubkey::new_unique()).collect();
+            let prices = [105u64, 100, 110, 100, 105, 110, 90, 105];
+            for (i, p) in prices.iter().enumerate() {
+                place(ai, true, *p, k[i], i as u64);
+            }
+            let want = vec![
+                (k[2], 110), (k[5], 110),
+                (k[0], 105), (k[4], 105), (k[7], 105),
+                (k[1], 100), (k[3], 100),
+                (k[6], 90),
+            ];
+            assert_eq!(drain(ai, true), want);
+        });
+        with_book(32, |ai| {
+            let k: Vec<Pubkey> = (0..6).map(|_| Pubkey::new_unique()).collect();
+            let prices = [105u64, 100, 110, 100, 105, 100];
+            for (i, p) in prices.iter().enumerate() {
+                place(ai, false, *p, k[i], i as u64);
+            }
+            let want = vec![
+                (k[1], 100), (k[3], 100), (k[5], 100),
+                (k[0], 105), (k[4], 105),
+                (k[2], 110),
+            ];
+            assert_eq!(drain(ai, false), want);
+        });
+    }
+
+    #[test]
+    fn partial_fill_keeps_head_of_queue() {
+        with_book(16, |ai| {
+            let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+            place(ai, false, 100, a, 1);
+            place(ai, false, 100, b, 2);
+            consume_best_by(ai, false, 4).unwrap();
+            let head = peek_best(ai).unwrap().unwrap();
+            assert_eq!((head.oo, head.base_qty), (a, 6));
+            consume_best_by(ai, false, 6).unwrap();
+            let head = peek_best(ai).unwrap().unwrap();
+            assert_eq!((head.oo, head.base_qty), (b, 10));
+        });
+    }
+
+    #[test]
+    fn cancel_inside_queue_preserves_order() {
+        with_book(16, |ai| {
+            let k: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+            for (i, key) in k.iter().enumerate() {
+                place(ai, true, 100, *key, i as u64);
+            }
+            assert!(remove_order(ai, true, 100, k[2], 2).unwrap());
+            assert!(remove_order(ai, true, 100, k[4], 4).unwrap());
+            assert!(remove_order(ai, true, 100, k[0], 0).unwrap());
+            let late = Pubkey::new_unique();
+            place(ai, true, 100, late, 9);
+            assert_eq!(drain(ai, true), vec![(k[1], 100), (k[3], 100), (late, 100)]);
+        });
+    }
+
+    #[test]
+    fn removing_inner_levels_keeps_other_queues() {
+        with_book(32, |ai| {
+            let k: Vec<Pubkey> = (0..9).map(|_| Pubkey::new_unique()).collect();
+            // Levels 100 (root), 90 and 110 each hold three orders.
+            let prices = [100u64, 90, 110, 100, 90, 110, 100, 90, 110];
+            for (i, p) in prices.iter().enumerate() {
+                place(ai, false, *p, k[i], i as u64);
+            }
+            // Empty the root level so the tree has to be re-linked around it.
+            for i in [0usize, 3, 6] {
+                assert!(remove_order(ai, false, 100, k[i], i as u64).unwrap());
+            }
+            assert_eq!(used_nodes(ai).unwrap(), 6);
+            assert_eq!(drain(ai, false), vec![(k[1], 90), (k[4], 90), (k[7], 90), (k[2], 110), (k[5], 110), (k[8], 110)]);
+        });
+    }
+
+    #[test]
+    fn remove_targets_one_order_of_an_owner() {
+        with_book(16, |ai| {
+            let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+            place(ai, true, 100, a, 1);
+            place(ai, true, 100, b, 2);
+            place(ai, true, 100, a, 3);
+            place(ai, true, 90, a, 4);
+            assert!(!remove_order(ai, true, 90, a, 3).unwrap());
+            assert!(!remove_order(ai, true, 100, b, 3).unwrap());
+            assert!(remove_order(ai, true, 100, a, 3).unwrap());
+            let head = peek_best(ai).unwrap().unwrap();
+            assert_eq!((head.oo, head.order_id), (a, 1));
+            assert_eq!(drain(ai, true), vec![(a, 100), (b, 100), (a, 90)]);
+        });
+    }
+
+    #[test]
+    fn iceberg_refills_at_the_back_of_its_level() {
+        with_book(16, |ai| {
+            let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+            let iceberg = SlabNode {
+                hidden_qty: 15,
+                display_qty: 10,
+                flags: FLAG_ICEBERG,
+                ..order(100, 10, a, 1)
+            };
+            let idx = insert_order(ai, false, iceberg).unwrap();
+            place(ai, false, 100, b, 2);
+
+            consume_best_by(ai, false, 4).unwrap();
+            let head = peek_best(ai).unwrap().unwrap();
+            assert_eq!((head.oo, head.base_qty, head.hidden_qty), (a, 6, 15));
+
+            consume_best_by(ai, false, 6).unwrap();
+            assert_eq!(peek_best(ai).unwrap().unwrap().oo, b);
+            consume_best_by(ai, false, 10).unwrap();
+            let head = peek_best(ai).unwrap().unwrap();
+            assert_eq!((head.oo, head.order_id, head.base_qty, head.hidden_qty), (a, 1, 10, 5));
+            // Refilled in its own node, without allocating.
+            assert_eq!(read_node(ai, idx).unwrap().order_id, 1);
+            assert_eq!(used_nodes(ai).unwrap(), 1);
+
+            consume_best_by(ai, false, 10).unwrap();
+            let head = peek_best(ai).unwrap().unwrap();
+            assert_eq!((head.base_qty, head.hidden_qty), (5, 0));
+            consume_best_by(ai, false, 5).unwrap();
+            assert!(peek_best(ai).unwrap().is_none());
+            assert_eq!(used_nodes(ai).unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn iceberg_refill_keeps_the_tree_intact() {
+        with_book(16, |ai| {
+            let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+            place(ai, true, 90, b, 1);
+            place(ai, true, 110, b, 2);
+            let iceberg = SlabNode { hidden_qty: 10, display_qty: 10, flags: FLAG_ICEBERG, ..order(100, 10, a, 3) };
+            insert_order(ai, true, iceberg).unwrap();
+            place(ai, true, 100, b, 4);
+
+            consume_order_by(ai, true, 100, a, 3, 10).unwrap();
+            assert_eq!(drain(ai, true), vec![(b, 110), (b, 100), (a, 100), (b, 90)]);
+            assert_eq!(used_nodes(ai).unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn removing_an_iceberg_drops_its_reserve() {
+        with_book(16, |ai| {
+            let a = Pubkey::new_unique();
+            let iceberg = SlabNode { hidden_qty: 90, display_qty: 10, flags: FLAG_ICEBERG, ..order(100, 10, a, 1) };
+            insert_order(ai, true, iceberg).unwrap();
+            assert!(remove_order(ai, true, 100, a, 1).unwrap());
+            assert!(peek_best(ai).unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn consume_inside_a_level_keeps_the_queue() {
+        with_book(16, |ai| {
+            let k: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+            for (i, key) in k.iter().enumerate() {
+                place(ai, false, 100, *key, i as u64);
+            }
+            place(ai, false, 110, k[0], 9);
+            consume_order_by(ai, false, 100, k[1], 1, 4).unwrap();
+            consume_order_by(ai, false, 100, k[2], 2, 10).unwrap();
+            let level: Vec<(Pubkey, u64)> = best_level(ai, 8).unwrap().iter().map(|n| (n.oo, n.base_qty)).collect();
+            assert_eq!(level, vec![(k[0], 10), (k[1], 6)]);
+            assert_eq!(best_level(ai, 1).unwrap().len(), 1);
+            assert_eq!(drain(ai, false), vec![(k[0], 100), (k[1], 100), (k[0], 110)]);
+        });
+    }
+
+    #[test]
+    fn best_orders_walks_levels_in_priority() {
+        let prices = [105u64, 100, 110, 100, 105, 110, 90, 120];
+        let walk = |is_bid: bool, max: usize| -> Vec<(u64, u64)> {
+            let mut out = vec![];
+            with_book(32, |ai| {
+                for (i, p) in prices.iter().enumerate() {
+                    place(ai, is_bid, *p, Pubkey::new_unique(), i as u64);
+                }
+                out = best_orders(ai, is_bid, max).unwrap().iter().map(|n| (n.price_ticks, n.order_id)).collect();
+            });
+            out
+        };
+        assert_eq!(walk(true, 16), vec![(120, 7), (110, 2), (110, 5), (105, 0), (105, 4), (100, 1), (100, 3), (90, 6)]);
+        assert_eq!(walk(false, 16), vec![(90, 6), (100, 1), (100, 3), (105, 0), (105, 4), (110, 2), (110, 5), (120, 7)]);
+        assert_eq!(walk(false, 3), vec![(90, 6), (100, 1), (100, 3)]);
+    }
+
+    #[test]
+    fn reduce_keeps_queue_position() {
+        with_book(16, |ai| {
+            let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+            place(ai, true, 100, a, 1);
+            place(ai, true, 100, b, 2);
+            assert!(reduce_order(ai, 100, a, 1, 4).unwrap());
+            assert!(reduce_order(ai, 100, a, 1, 11).is_err());
+            let head = peek_best(ai).unwrap().unwrap();
+            assert_eq!((head.oo, head.base_qty), (a, 4));
+            assert_eq!(find_order(ai, 100, b, 2).unwrap().unwrap().base_qty, 10);
+            assert!(!reduce_order(ai, 100, b, 1, 4).unwrap());
+        });
+    }
+}
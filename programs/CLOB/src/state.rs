@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use crate::domain::EngineKind;
 
 pub const BLOB_MAGIC: u32 = 0x4B_45_52_44;
 
@@ -12,8 +13,20 @@ pub struct Market {
     pub event_queue: Pubkey,
     pub tick_size: u64,
     pub min_base_qty: u64,
-    pub fees_bps: u16,
+    /// Native base units per lot; order `base_qty` counts lots.
+    pub base_lot_size: u64,
+    /// Native quote units per lot; `price_ticks` counts quote lots per base lot.
+    pub quote_lot_size: u64,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    /// Negative values are a rebate paid out of the taker fee.
+    pub maker_fee_bps: i16,
+    pub taker_fee_bps: u16,
     pub paused: bool,
+    pub engine_kind: EngineKind,
+    pub auction_interval_slots: u64,
+    /// Slot of the last auction that cleared the book, or of `init_market`.
+    pub last_auction_slot: u64,
     pub bump_market: u8,
     pub bump_bids: u8,
     pub bump_asks: u8,
@@ -21,15 +34,30 @@ pub struct Market {
     pub bids_capacity: u32,
     pub asks_capacity: u32,
     pub eventq_capacity: u32,
+    /// Fees the market keeps: taker and maker fees less maker rebates.
     pub fees_accrued: u64,
+    /// Total maker rebates paid out.
+    pub rebates_paid: u64,
+    /// Signs `sweep_fees`; starts as `authority`.
+    pub fee_authority: Pubkey,
+    /// Quote token account `sweep_fees` pays into; unset until `set_fee_config`.
+    pub treasury: Pubkey,
+    /// Quote paid out of `fees_accrued` to whoever settles an event; 0 pays nothing.
+    pub crank_reward: u64,
+    /// Total crank rewards paid out.
+    pub crank_rewards_paid: u64,
+    pub next_order_id: u64,
+    /// Sequence number of the next logged Anchor event (see `events`).
+    pub event_seq: u64,
 }
 
 impl Market {
     pub const LEN: usize = 8
         + 32 + 32 + 32 + 32 + 32 + 32
-        + 8 + 8 + 2 + 1
+        + 8 + 8 + 8 + 8 + 1 + 1 + 2 + 2 + 1 + 1 + 8 + 8
         + 1 + 1 + 1 + 1
         + 4 + 4 + 4
+        + 8 + 8 + 32 + 32 + 8 + 8 + 8
         + 8;
 }
 
@@ -62,33 +90,146 @@ impl OrderEntry {
 pub struct FillEvent {
     pub maker_oo: Pubkey,
     pub taker_oo: Pubkey,
+    /// Wallets behind the two orders, whose user balances settle the fill.
+    pub maker_owner: Pubkey,
+    pub taker_owner: Pubkey,
     pub base_qty: u64,
-    pub price_ticks: u32,
+    pub maker_client_order_id: u64,
+    pub taker_client_order_id: u64,
+    pub price_ticks: u64,
+    /// Collateral each side reserved for this fill, in the currency it pays with.
+    pub maker_locked: u64,
+    pub taker_locked: u64,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
     pub taker_side: u8,
-    pub pad: [u8; 3],
+    /// Open orders slots of the two orders.
+    pub maker_slot: u8,
+    pub taker_slot: u8,
+    /// The taker was resting too (auction fills), so settlement updates its slot as
+    /// well; otherwise placement already did and settling needs only its balance.
+    pub taker_resting: bool,
+    pub pad: [u8; 4],
 }
 impl FillEvent {
-    pub const LEN: usize = 32 + 32 + 8 + 4 + 1 + 3;
+    pub const LEN: usize = 32 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 1 + 1 + 1 + 4;
 }
 
-#[account]
-pub struct OpenOrdersLite {
-    pub user: Pubkey,
-    pub market: Pubkey,
-    pub locked_lamports: u64,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OutReason {
+    /// Pruned after `expires_at`; its collateral is returned on settlement.
+    Expired,
+    /// Fully traded; the fills carry its collateral.
+    Filled,
+    /// Cancelled by its owner, who got the collateral back right away.
+    Cancelled,
+    /// Cancelled by the owner's own incoming order, likewise released right away.
+    /// These last two are records only and are dropped when the queue is full.
+    SelfTrade,
+}
+
+/// A resting order left the book. Settlement frees its owner's slot and returns `locked`.
+#[repr(C)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct OutEvent {
+    pub owner_oo: Pubkey,
+    /// Wallet behind `owner_oo`, whose user balance gets `locked` back.
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub client_order_id: u64,
+    /// Size that left the book unfilled.
+    pub base_qty: u64,
+    /// Collateral the order still held, returned to its owner on settlement.
+    pub locked: u64,
+    pub side: u8,
+    pub slot: u8,
+    pub reason: OutReason,
+    pub pad: [u8; 5],
+}
+impl OutEvent {
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 8 + 8 + 1 + 1 + 1 + 5;
+}
+
+/// Event queue entry. Every entry occupies `Event::LEN` bytes regardless of variant.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub enum Event {
+    Fill(FillEvent),
+    Out(OutEvent),
+}
+impl Event {
+    pub const LEN: usize = 1 + FillEvent::LEN;
+
+    /// Whether settling it moves balances or frees a slot. Cancel and self-trade records
+    /// do neither, so they earn no crank reward.
+    pub fn pays_crank_reward(&self) -> bool {
+        match self {
+            Event::Fill(_) => true,
+            Event::Out(o) => matches!(o.reason, OutReason::Expired | OutReason::Filled),
+        }
+    }
+}
+
+pub const MAX_OPEN_ORDERS: usize = 16;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct OrderSlot {
+    pub order_id: u64,
+    pub client_order_id: u64,
     pub price_ticks: u64,
+    /// Unfilled size. A fill against the resting order comes off when it settles, so
+    /// until then this still counts size that has traded on the book.
     pub base_qty: u64,
+    /// Size filled so far, likewise as of settlement.
+    pub filled_qty: u64,
     pub side: u8,
     pub active: bool,
+    pub pad: [u8; 6],
+}
+impl OrderSlot {
+    pub const LEN: usize = 8 + 8 + 8 + 8 + 8 + 1 + 1 + 6;
+
+    /// Applies a settled fill of `qty` if the slot still holds `order_id`.
+    pub fn apply_fill(&mut self, order_id: u64, qty: u64) {
+        if self.active && self.order_id == order_id {
+            self.base_qty = self.base_qty.saturating_sub(qty);
+            self.filled_qty = self.filled_qty.saturating_add(qty);
+        }
+    }
+}
+
+#[account]
+pub struct OpenOrdersLite {
+    pub user: Pubkey,
+    pub market: Pubkey,
     pub bump: u8,
-    pub pad: [u8; 5],
+    pub pad: [u8; 7],
+    pub slots: [OrderSlot; MAX_OPEN_ORDERS],
 }
 impl OpenOrdersLite {
     pub const LEN: usize = 8
         + 32 + 32
-        + 8 + 8 + 8
-        + 1 + 1 + 1
-        + 5;
+        + 1 + 7
+        + OrderSlot::LEN * MAX_OPEN_ORDERS;
+
+    pub fn free_slot(&self) -> Option<usize> {
+        self.slots.iter().position(|s| !s.active)
+    }
+
+    pub fn find_order(&self, order_id: u64) -> Option<usize> {
+        self.slots.iter().position(|s| s.active && s.order_id == order_id)
+    }
+
+    /// Client ids are optional; 0 never matches.
+    pub fn find_client_order(&self, client_order_id: u64) -> Option<usize> {
+        if client_order_id == 0 {
+            return None;
+        }
+        self.slots.iter().position(|s| s.active && s.client_order_id == client_order_id)
+    }
+
+    pub fn has_active(&self) -> bool {
+        self.slots.iter().any(|s| s.active)
+    }
 }
 
 #[account]
@@ -97,6 +238,9 @@ pub struct UserBalance {
     pub user: Pubkey,
     pub base_free: u64,
     pub quote_free: u64,
+    /// Held as collateral by open orders and unsettled fills; not withdrawable.
+    pub base_locked: u64,
+    pub quote_locked: u64,
     pub bump: u8,
     pub pad: [u8; 7],
 }
@@ -104,6 +248,51 @@ impl UserBalance {
     pub const LEN: usize = 8
         + 32 + 32
         + 8 + 8
+        + 8 + 8
         + 1
         + 7;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settled_fills_only_reach_the_order_they_name() {
+        let mut s = OrderSlot { order_id: 7, base_qty: 300, side: 1, active: true, ..OrderSlot::default() };
+        s.apply_fill(7, 100);
+        assert_eq!((s.base_qty, s.filled_qty), (200, 100));
+
+        // The slot was cancelled and reused since the fill was queued.
+        s.apply_fill(6, 100);
+        assert_eq!((s.base_qty, s.filled_qty), (200, 100));
+
+        s.apply_fill(7, 200);
+        assert_eq!((s.base_qty, s.filled_qty), (0, 300));
+
+        let mut free = OrderSlot::default();
+        free.apply_fill(0, 100);
+        assert_eq!((free.base_qty, free.filled_qty), (0, 0));
+    }
+
+    #[test]
+    fn only_settling_work_earns_crank_rewards() {
+        let out = |reason| Event::Out(OutEvent {
+            owner_oo: Pubkey::default(),
+            owner: Pubkey::default(),
+            order_id: 0,
+            client_order_id: 0,
+            base_qty: 0,
+            locked: 0,
+            side: 0,
+            slot: 0,
+            reason,
+            pad: [0; 5],
+        });
+        assert!(Event::Fill(FillEvent::default()).pays_crank_reward());
+        assert!(out(OutReason::Expired).pays_crank_reward());
+        assert!(out(OutReason::Filled).pays_crank_reward());
+        assert!(!out(OutReason::Cancelled).pays_crank_reward());
+        assert!(!out(OutReason::SelfTrade).pays_crank_reward());
+    }
+}
@@ -0,0 +1,237 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund, readFills } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+async function setupMarket() {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+async function place(
+  pdas: ReturnType<typeof derivePDAs>,
+  user: Keypair | null,
+  priceTicks: number,
+  baseQty: number,
+  side: 0 | 1
+) {
+  const payer = user ? user.publicKey : provider.wallet.publicKey;
+  const ub = await fund(program, pdas.market, user);
+  const builder = program.methods
+    .placeOrder({
+      priceTicks: new anchor.BN(priceTicks),
+      baseQty: new anchor.BN(baseQty),
+      side,
+      maxSlippageTicks: new anchor.BN(0),
+      clientOrderId: new anchor.BN(0),
+      orderType: { limit: {} },
+      quoteLimit: new anchor.BN(0),
+      selfTradeBehavior: { decrementBoth: {} },
+      expiresAt: new anchor.BN(0),
+      displayQty: new anchor.BN(0),
+      matchLimit: 0,
+    })
+    .accounts({
+      payer,
+      ...pdas,
+      oo: ooOf(pdas.market, payer),
+      ub,
+      systemProgram: SYS_PROG,
+    });
+  return user ? builder.signers([user]).rpc() : builder.rpc();
+}
+
+async function amend(pdas: ReturnType<typeof derivePDAs>, orderId: anchor.BN, priceTicks: number, baseQty: number) {
+  return program.methods
+    .amendOrder({
+      orderId,
+      priceTicks: new anchor.BN(priceTicks),
+      baseQty: new anchor.BN(baseQty),
+    })
+    .accounts({
+      payer: provider.wallet.publicKey,
+      market: pdas.market,
+      bids: pdas.bids,
+      asks: pdas.asks,
+      oo: ooOf(pdas.market, provider.wallet.publicKey),
+      ub: await fund(program, pdas.market, null),
+    })
+    .rpc();
+}
+
+async function settle(pdas: ReturnType<typeof derivePDAs>, users: (Keypair | null)[]) {
+  const accounts: PublicKey[] = [];
+  for (const user of users) {
+    const owner = user ? user.publicKey : provider.wallet.publicKey;
+    accounts.push(ooOf(pdas.market, owner), await fund(program, pdas.market, user));
+  }
+  return program.methods
+    .settleEvents(64)
+    .accounts({ cranker: provider.wallet.publicKey, market: pdas.market, eventQueue: pdas.eventQueue })
+    .remainingAccounts(accounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })))
+    .rpc();
+}
+
+async function activeSlots(oo: PublicKey) {
+  const acc = await (program.account as any).openOrdersLite.fetch(oo);
+  return acc.slots.filter((s: any) => s.active);
+}
+
+function fills(pdas: ReturnType<typeof derivePDAs>) {
+  return readFills(provider.connection, pdas.eventQueue);
+}
+
+describe("amend_order", () => {
+  it("keeps priority on a size reduction and re-queues on an increase", async () => {
+    const pdas = await setupMarket();
+    const other = Keypair.generate();
+    const taker = Keypair.generate();
+    await airdropSOL(other.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const mineOo = ooOf(pdas.market, provider.wallet.publicKey);
+    const otherOo = ooOf(pdas.market, other.publicKey);
+
+    await place(pdas, null, 50_000, 300, 1);
+    await place(pdas, other, 50_000, 100, 1);
+    const [mine] = await activeSlots(mineOo);
+
+    await amend(pdas, mine.orderId, 50_000, 200);
+    await place(pdas, taker, 50_000, 100, 0);
+    expect((await fills(pdas)).map((f) => f.makerOo.toBase58())).toEqual([mineOo.toBase58()]);
+
+    await amend(pdas, mine.orderId, 50_000, 300);
+    await place(pdas, taker, 50_000, 100, 0);
+    expect((await fills(pdas)).map((f) => f.makerOo.toBase58())).toEqual([
+      mineOo.toBase58(),
+      otherOo.toBase58(),
+    ]);
+
+    // The first fill is still owed to the slot: 300 on the book plus 100 not yet settled.
+    expect((await activeSlots(mineOo))[0].baseQty.toNumber()).toBe(400);
+    await settle(pdas, [null, other, taker]);
+    const [after] = await activeSlots(mineOo);
+    expect(after.orderId.toString()).toBe(mine.orderId.toString());
+    expect(after.baseQty.toNumber()).toBe(300);
+    expect(after.filledQty.toNumber()).toBe(100);
+    expect(await activeSlots(otherOo)).toHaveLength(0);
+  });
+
+  it("moves the price, adjusts the locked collateral and refuses to cross", async () => {
+    const pdas = await setupMarket();
+    const other = Keypair.generate();
+    await airdropSOL(other.publicKey, 2 * LAMPORTS_PER_SOL);
+    const mineOo = ooOf(pdas.market, provider.wallet.publicKey);
+
+    await place(pdas, null, 50_000, 100, 1);
+    await place(pdas, other, 30_000, 100, 0);
+    const [mine] = await activeSlots(mineOo);
+
+    // Asks lock base, so a price move keeps the lock and a size increase adds to it.
+    const ub = await fund(program, pdas.market, null);
+    await amend(pdas, mine.orderId, 60_000, 100);
+    const [moved] = await activeSlots(mineOo);
+    expect(moved.priceTicks.toNumber()).toBe(60_000);
+    expect((await (program.account as any).userBalance.fetch(ub)).baseLocked.toNumber()).toBe(100);
+    await amend(pdas, mine.orderId, 60_000, 250);
+    expect((await (program.account as any).userBalance.fetch(ub)).baseLocked.toNumber()).toBe(250);
+
+    await expect(amend(pdas, mine.orderId, 30_000, 100)).rejects.toThrow(/WouldCross/);
+  });
+
+  it("logs the old and new terms of an amendment", async () => {
+    const pdas = await setupMarket();
+    const mineOo = ooOf(pdas.market, provider.wallet.publicKey);
+    await place(pdas, null, 50_000, 300, 1);
+    const [mine] = await activeSlots(mineOo);
+
+    const amendedBy = async (sig: string) => {
+      await provider.connection.confirmTransaction(sig, "confirmed");
+      const tx = await provider.connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const parser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+      return [...parser.parseLogs(tx!.meta!.logMessages!)].find((e) => e.name === "orderAmended")!.data;
+    };
+
+    const shrunk = await amendedBy(await amend(pdas, mine.orderId, 50_000, 200));
+    expect(shrunk.owner.equals(provider.wallet.publicKey)).toBe(true);
+    expect(shrunk.ownerOo.equals(mineOo)).toBe(true);
+    expect(shrunk.orderId.eq(mine.orderId)).toBe(true);
+    expect([shrunk.oldPriceTicks.toNumber(), shrunk.newPriceTicks.toNumber()]).toEqual([50_000, 50_000]);
+    expect([shrunk.oldBaseQty.toNumber(), shrunk.newBaseQty.toNumber()]).toEqual([300, 200]);
+    expect(shrunk.requeued).toBe(false);
+
+    const moved = await amendedBy(await amend(pdas, mine.orderId, 60_000, 200));
+    expect([moved.oldPriceTicks.toNumber(), moved.newPriceTicks.toNumber()]).toEqual([50_000, 60_000]);
+    expect(moved.requeued).toBe(true);
+    expect(moved.seqNum.toNumber()).toBe(shrunk.seqNum.toNumber() + 1);
+  });
+});
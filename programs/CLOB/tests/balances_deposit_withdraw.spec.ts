@@ -94,7 +94,12 @@ describe("balances deposit/withdraw", () => {
       eventQueueCapacity: 512,
       tickSize: new anchor.BN(10_000),
       minBaseQty: new anchor.BN(100),
-      feesBps: 10,
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
     };
 
     const cuIx = computeLimitIx(1_200_000);
@@ -0,0 +1,144 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund, readFills } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+async function setupMarket(auctionIntervalSlots = 0) {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(10),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { batchAuction: {} },
+      auctionIntervalSlots: new anchor.BN(auctionIntervalSlots),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+async function place(pdas: ReturnType<typeof derivePDAs>, user: Keypair, side: 0 | 1, priceTicks: number) {
+  return program.methods
+    .placeOrder({
+      priceTicks: new anchor.BN(priceTicks),
+      baseQty: new anchor.BN(100),
+      side,
+      maxSlippageTicks: new anchor.BN(0),
+      clientOrderId: new anchor.BN(0),
+      orderType: { limit: {} },
+      quoteLimit: new anchor.BN(0),
+      selfTradeBehavior: { decrementBoth: {} },
+      expiresAt: new anchor.BN(0),
+      displayQty: new anchor.BN(0),
+      matchLimit: 0,
+    })
+    .accounts({ payer: user.publicKey, ...pdas, oo: ooOf(pdas.market, user.publicKey), ub: await fund(program, pdas.market, user), systemProgram: SYS_PROG })
+    .signers([user])
+    .rpc();
+}
+
+async function runAuction(pdas: ReturnType<typeof derivePDAs>) {
+  return program.methods
+    .runAuction(0)
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({ payer: provider.wallet.publicKey, ...pdas, systemProgram: SYS_PROG })
+    .rpc();
+}
+
+// Fill events in queue order as (maker OO, base qty, price ticks).
+async function fills(pdas: ReturnType<typeof derivePDAs>) {
+  return (await readFills(provider.connection, pdas.eventQueue)).map((f) => [f.makerOo.toBase58(), f.baseQty, f.priceTicks]);
+}
+
+describe("batch auction engine", () => {
+  it("rests crossing orders and clears them at one price when the auction runs", async () => {
+    const pdas = await setupMarket();
+    const seller = Keypair.generate();
+    const buyer = Keypair.generate();
+    for (const k of [seller, buyer]) await airdropSOL(k.publicKey, LAMPORTS_PER_SOL);
+
+    await place(pdas, seller, 1, 40_000);
+    await place(pdas, seller, 1, 50_000);
+    await place(pdas, buyer, 0, 60_000);
+    await place(pdas, buyer, 0, 45_000);
+    expect(await fills(pdas)).toEqual([]);
+
+    // Every price from 40k to 60k trades 100; the middle of that range clears.
+    await runAuction(pdas);
+    expect(await fills(pdas)).toEqual([[ooOf(pdas.market, seller.publicKey).toBase58(), 100, 50_000]]);
+  });
+
+  it("never crosses an account's bid with its own ask", async () => {
+    const pdas = await setupMarket();
+    const trader = Keypair.generate();
+    await airdropSOL(trader.publicKey, LAMPORTS_PER_SOL);
+
+    await place(pdas, trader, 1, 40_000);
+    await place(pdas, trader, 0, 60_000);
+    await runAuction(pdas);
+    expect(await fills(pdas)).toEqual([]);
+  });
+
+  it("refuses to run before the interval has passed", async () => {
+    const pdas = await setupMarket(1_000_000);
+    await expect(runAuction(pdas)).rejects.toThrow(/AuctionNotDue/);
+  });
+});
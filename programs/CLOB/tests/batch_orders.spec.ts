@@ -0,0 +1,149 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+async function setupMarket() {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+function params(priceTicks: number, side: 0 | 1, clientOrderId: number) {
+  return {
+    priceTicks: new anchor.BN(priceTicks),
+    baseQty: new anchor.BN(100),
+    side,
+    maxSlippageTicks: new anchor.BN(0),
+    clientOrderId: new anchor.BN(clientOrderId),
+    orderType: { limit: {} },
+    quoteLimit: new anchor.BN(0),
+    selfTradeBehavior: { decrementBoth: {} },
+    expiresAt: new anchor.BN(0),
+    displayQty: new anchor.BN(0),
+    matchLimit: 0,
+  };
+}
+
+async function batch(pdas: ReturnType<typeof derivePDAs>, items: object[]) {
+  return program.methods
+    .batchOrders(items)
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      ...pdas,
+      oo: ooOf(pdas.market, provider.wallet.publicKey),
+      ub: await fund(program, pdas.market, null),
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+}
+
+async function activeSlots(pdas: ReturnType<typeof derivePDAs>) {
+  const acc = await (program.account as any).openOrdersLite.fetch(ooOf(pdas.market, provider.wallet.publicKey));
+  return acc.slots.filter((s: any) => s.active);
+}
+
+describe("batch_orders", () => {
+  it("quotes a ladder in one transaction and replaces part of it in the next", async () => {
+    const pdas = await setupMarket();
+
+    await batch(pdas, [
+      { place: { params: params(10_000, 0, 1) } },
+      { place: { params: params(20_000, 0, 2) } },
+      { place: { params: params(40_000, 1, 3) } },
+      { place: { params: params(50_000, 1, 4) } },
+    ]);
+    const placed = await activeSlots(pdas);
+    expect(placed).toHaveLength(4);
+
+    const inner = placed.find((s: any) => s.clientOrderId.toNumber() === 2);
+    await batch(pdas, [
+      { cancel: { orderId: inner.orderId } },
+      { place: { params: params(30_000, 0, 2) } },
+    ]);
+    const prices = (await activeSlots(pdas)).map((s: any) => s.priceTicks.toNumber()).sort();
+    expect(prices).toEqual([10_000, 30_000, 40_000, 50_000]);
+  });
+
+  it("reverts every item when one fails and reports its index", async () => {
+    const pdas = await setupMarket();
+
+    const failed = batch(pdas, [
+      { place: { params: params(10_000, 0, 7) } },
+      { place: { params: params(20_000, 0, 7) } },
+    ]);
+    await expect(failed).rejects.toThrow(/DuplicateClientOrderId/);
+    const logs: string[] = await failed.catch((e: any) => e.logs ?? []);
+    expect(logs.some((l) => l.includes("batch_orders: item 1 failed"))).toBe(true);
+
+    const oo = ooOf(pdas.market, provider.wallet.publicKey);
+    expect(await provider.connection.getAccountInfo(oo)).toBeNull();
+  });
+});
@@ -1,6 +1,8 @@
 import { describe, it, expect } from "vitest";
 import * as anchor from "@coral-xyz/anchor";
-import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair } from "@solana/web3.js";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund } from "./helpers";
 
 const provider = anchor.AnchorProvider.env();
 anchor.setProvider(provider);
@@ -8,6 +10,11 @@ const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
 
 const SYS_PROG = anchor.web3.SystemProgram.programId;
 
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
 function computeLimitIx(units: number) {
   return ComputeBudgetProgram.setComputeUnitLimit({ units });
 }
@@ -45,12 +52,12 @@ async function airdropSOL(pubkey: anchor.web3.PublicKey, lamports: number) {
   await provider.connection.confirmTransaction(sig);
 }
 
-describe("clear_eventq + close_oo", () => {
-  it("resets eventq used and closes OO to payer", async () => {
+describe("close_oo", () => {
+  it("closes OO to payer once its orders are gone", async () => {
     await airdropSOL(provider.wallet.publicKey, 2 * LAMPORTS_PER_SOL);
 
-    const baseMint = Keypair.generate().publicKey;
-    const quoteMint = Keypair.generate().publicKey;
+    const baseMint = await newMint();
+    const quoteMint = await newMint();
     const { market, bids, asks, eventQueue, oo } = derivePDAs(
       program.programId,
       baseMint,
@@ -66,7 +73,12 @@ describe("clear_eventq + close_oo", () => {
         eventQueueCapacity: 512,
         tickSize: new anchor.BN(10_000),
         minBaseQty: new anchor.BN(100),
-        feesBps: 10,
+        makerFeeBps: 0,
+        takerFeeBps: 10,
+        baseLotSize: new anchor.BN(1),
+        quoteLotSize: new anchor.BN(1),
+        engineKind: { priceTime: {} },
+        auctionIntervalSlots: new anchor.BN(0),
       })
       .preInstructions([computeLimitIx(1_400_000)])
       .accounts({
@@ -82,14 +94,20 @@ describe("clear_eventq + close_oo", () => {
       })
       .rpc();
 
-    const lock = Math.floor(0.02 * LAMPORTS_PER_SOL);
+    const ub = await fund(program, market, null);
     await program.methods
       .placeOrder({
         priceTicks: new anchor.BN(10_000),
         baseQty: new anchor.BN(100),
         side: 0,
-        lockLamports: new anchor.BN(lock),
         maxSlippageTicks: new anchor.BN(0),
+        clientOrderId: new anchor.BN(0),
+        orderType: { limit: {} },
+        quoteLimit: new anchor.BN(0),
+        selfTradeBehavior: { decrementBoth: {} },
+        expiresAt: new anchor.BN(0),
+        displayQty: new anchor.BN(0),
+        matchLimit: 0,
       })
       .accounts({
         payer: provider.wallet.publicKey,
@@ -98,12 +116,14 @@ describe("clear_eventq + close_oo", () => {
         asks,
         eventQueue,
         oo,
+        ub,
         systemProgram: SYS_PROG,
       })
       .rpc();
 
+    const ooAcc = await (program.account as any).openOrdersLite.fetch(oo);
     await program.methods
-      .cancelOrder()
+      .cancelOrder(ooAcc.slots[0].orderId)
       .accounts({
         payer: provider.wallet.publicKey,
         market,
@@ -122,14 +142,5 @@ describe("clear_eventq + close_oo", () => {
 
     const ooBalAfter = await provider.connection.getBalance(oo);
     expect(ooBalAfter).toBe(0);
-
-    await program.methods
-      .clearEventq()
-      .accounts({
-        authority: provider.wallet.publicKey,
-        market,
-        eventQueue,
-      })
-      .rpc();
   });
 });
@@ -0,0 +1,200 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+async function setupMarket() {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+async function place(
+  pdas: ReturnType<typeof derivePDAs>,
+  user: Keypair | null,
+  priceTicks: number,
+  side: 0 | 1,
+  expiresAt: number
+) {
+  const payer = user ? user.publicKey : provider.wallet.publicKey;
+  const ub = await fund(program, pdas.market, user);
+  const builder = program.methods
+    .placeOrder({
+      priceTicks: new anchor.BN(priceTicks),
+      baseQty: new anchor.BN(100),
+      side,
+      maxSlippageTicks: new anchor.BN(1_000_000),
+      clientOrderId: new anchor.BN(0),
+      orderType: { limit: {} },
+      quoteLimit: new anchor.BN(0),
+      selfTradeBehavior: { decrementBoth: {} },
+      expiresAt: new anchor.BN(expiresAt),
+      displayQty: new anchor.BN(0),
+      matchLimit: 0,
+    })
+    .accounts({
+      payer,
+      ...pdas,
+      oo: ooOf(pdas.market, payer),
+      ub,
+      systemProgram: SYS_PROG,
+    });
+  return user ? builder.signers([user]).rpc() : builder.rpc();
+}
+
+async function chainTime() {
+  const slot = await provider.connection.getSlot();
+  return (await provider.connection.getBlockTime(slot))!;
+}
+
+async function activeSlots(oo: PublicKey) {
+  const acc = await (program.account as any).openOrdersLite.fetch(oo);
+  return acc.slots.filter((s: any) => s.active);
+}
+
+describe("good-til-time orders", () => {
+  it("rejects an expiry that has already passed", async () => {
+    const pdas = await setupMarket();
+    const now = await chainTime();
+    await expect(place(pdas, null, 50_000, 1, now - 10)).rejects.toThrow(/AlreadyExpired/);
+  });
+
+  it("prunes an expired maker instead of filling it and frees its slot on settle", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const makerOo = ooOf(pdas.market, provider.wallet.publicKey);
+    const takerOo = ooOf(pdas.market, taker.publicKey);
+
+    const expiresAt = (await chainTime()) + 2;
+    await place(pdas, null, 50_000, 1, expiresAt);
+    while ((await chainTime()) < expiresAt) {
+      await new Promise((r) => setTimeout(r, 500));
+    }
+
+    // The only ask is stale: the bid rests instead of trading against it.
+    await place(pdas, taker, 50_000, 0, 0);
+    const takerSlots = await activeSlots(takerOo);
+    expect(takerSlots).toHaveLength(1);
+    expect(takerSlots[0].baseQty.toNumber()).toBe(100);
+    expect(await activeSlots(makerOo)).toHaveLength(1);
+
+    await program.methods
+      .settleEvents(16)
+      .accounts({
+        cranker: provider.wallet.publicKey,
+        market: pdas.market,
+        eventQueue: pdas.eventQueue,
+      })
+      .remainingAccounts([
+        { pubkey: makerOo, isSigner: false, isWritable: true },
+        { pubkey: await fund(program, pdas.market, null), isSigner: false, isWritable: true },
+      ])
+      .rpc();
+
+    expect(await activeSlots(makerOo)).toHaveLength(0);
+  });
+
+  it("returns an expired maker's collateral after it cancelled and closed its account", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const makerOo = ooOf(pdas.market, provider.wallet.publicKey);
+    const makerUb = await fund(program, pdas.market, null);
+
+    const expiresAt = (await chainTime()) + 2;
+    await place(pdas, null, 50_000, 1, expiresAt);
+    const [order] = await activeSlots(makerOo);
+    while ((await chainTime()) < expiresAt) {
+      await new Promise((r) => setTimeout(r, 500));
+    }
+    await place(pdas, taker, 50_000, 0, 0);
+
+    // Pruned already, so the cancel only frees the slot; the collateral waits in the out event.
+    await program.methods
+      .cancelOrder(order.orderId)
+      .accounts({ payer: provider.wallet.publicKey, market: pdas.market, oo: makerOo, ub: makerUb })
+      .rpc();
+    await program.methods
+      .closeOo()
+      .accounts({ payer: provider.wallet.publicKey, market: pdas.market, oo: makerOo })
+      .rpc();
+    expect((await (program.account as any).userBalance.fetch(makerUb)).baseLocked.toNumber()).toBe(100);
+
+    await program.methods
+      .settleEvents(16)
+      .accounts({ cranker: provider.wallet.publicKey, market: pdas.market, eventQueue: pdas.eventQueue })
+      .remainingAccounts([
+        { pubkey: makerOo, isSigner: false, isWritable: true },
+        { pubkey: makerUb, isSigner: false, isWritable: true },
+      ])
+      .rpc();
+    expect((await (program.account as any).userBalance.fetch(makerUb)).baseLocked.toNumber()).toBe(0);
+  });
+});
@@ -0,0 +1,415 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import {
+  LAMPORTS_PER_SOL,
+  ComputeBudgetProgram,
+  Keypair,
+  PublicKey,
+} from "@solana/web3.js";
+import {
+  createMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  getAccount,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const workspaceProgram = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function pdas(
+  programId: PublicKey,
+  baseMint: PublicKey,
+  quoteMint: PublicKey,
+  user: PublicKey
+) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_bids"), market.toBuffer()],
+    programId
+  );
+  const [asks] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_asks"), market.toBuffer()],
+    programId
+  );
+  const [eventQueue] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_eventq"), market.toBuffer()],
+    programId
+  );
+  const [oo] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    programId
+  );
+  const [vaultAuth] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_vault_auth"), market.toBuffer()],
+    programId
+  );
+  const [baseVault] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_vault_base"), market.toBuffer()],
+    programId
+  );
+  const [quoteVault] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_vault_quote"), market.toBuffer()],
+    programId
+  );
+  const [ub] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_user"), market.toBuffer(), user.toBuffer()],
+    programId
+  );
+  return { market, bids, asks, eventQueue, oo, vaultAuth, baseVault, quoteVault, ub };
+}
+
+async function airdrop(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+// One 100 @ 10_000 fill left in the event queue: 1_000_000 quote, so settling it
+// accrues a 10 bps taker fee of 1_000.
+async function marketWithUnsettledFill() {
+  const maker = Keypair.generate();
+  const taker = Keypair.generate();
+  await airdrop(maker.publicKey, 2 * LAMPORTS_PER_SOL);
+  await airdrop(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+
+  const mintAuthority = (provider.wallet as any).payer as Keypair;
+  const baseMint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+  const quoteMint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+  const makerBase = await getOrCreateAssociatedTokenAccount(provider.connection, mintAuthority, baseMint, maker.publicKey);
+  const takerQuote = await getOrCreateAssociatedTokenAccount(provider.connection, mintAuthority, quoteMint, taker.publicKey);
+  await mintTo(provider.connection, mintAuthority, baseMint, makerBase.address, mintAuthority, 1_000_000n);
+  await mintTo(provider.connection, mintAuthority, quoteMint, takerQuote.address, mintAuthority, 5_000_000n);
+
+  const m = pdas(workspaceProgram.programId, baseMint, quoteMint, maker.publicKey);
+  const t = pdas(workspaceProgram.programId, baseMint, quoteMint, taker.publicKey);
+
+  await workspaceProgram.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_200_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      market: m.market,
+      bids: m.bids,
+      asks: m.asks,
+      eventQueue: m.eventQueue,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+
+  await workspaceProgram.methods
+    .initVaults()
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      market: m.market,
+      baseMint,
+      quoteMint,
+      vaultAuth: m.vaultAuth,
+      baseVault: m.baseVault,
+      quoteVault: m.quoteVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+
+  for (const [user, ub] of [[maker, m.ub], [taker, t.ub]] as const) {
+    await workspaceProgram.methods
+      .initUserBalance()
+      .accounts({ payer: provider.wallet.publicKey, user: user.publicKey, market: m.market, ub, systemProgram: SYS_PROG })
+      .signers([user])
+      .rpc();
+  }
+
+  await workspaceProgram.methods
+    .depositBase(new anchor.BN(500_000))
+    .accounts({
+      user: maker.publicKey,
+      market: m.market,
+      ub: m.ub,
+      userBaseAta: makerBase.address,
+      baseVault: m.baseVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    .signers([maker])
+    .rpc();
+  await workspaceProgram.methods
+    .depositQuote(new anchor.BN(2_000_000))
+    .accounts({
+      user: taker.publicKey,
+      market: m.market,
+      ub: t.ub,
+      userQuoteAta: takerQuote.address,
+      quoteVault: m.quoteVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    .signers([taker])
+    .rpc();
+
+  for (const [user, { oo, ub }, side] of [[maker, m, 1], [taker, t, 0]] as const) {
+    await workspaceProgram.methods
+      .placeOrder({
+        priceTicks: new anchor.BN(10_000),
+        baseQty: new anchor.BN(100),
+        side,
+        maxSlippageTicks: new anchor.BN(0),
+        clientOrderId: new anchor.BN(0),
+        orderType: { limit: {} },
+        quoteLimit: new anchor.BN(0),
+        selfTradeBehavior: { decrementBoth: {} },
+        expiresAt: new anchor.BN(0),
+        displayQty: new anchor.BN(0),
+        matchLimit: 0,
+      })
+      .accounts({
+        payer: user.publicKey,
+        market: m.market,
+        bids: m.bids,
+        asks: m.asks,
+        eventQueue: m.eventQueue,
+        oo,
+        ub,
+        systemProgram: SYS_PROG,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  return { baseMint, quoteMint, maker, ...m, takerOo: t.oo, takerUb: t.ub };
+}
+
+async function settle(mkt: Awaited<ReturnType<typeof marketWithUnsettledFill>>, cranker: Keypair, extra: PublicKey[] = []) {
+  return workspaceProgram.methods
+    .settleEvents(1000)
+    .accounts({ cranker: cranker.publicKey, market: mkt.market, eventQueue: mkt.eventQueue })
+    .remainingAccounts([
+      { pubkey: mkt.oo, isSigner: false, isWritable: true },
+      { pubkey: mkt.takerOo, isSigner: false, isWritable: false },
+      { pubkey: mkt.ub, isSigner: false, isWritable: true },
+      { pubkey: mkt.takerUb, isSigner: false, isWritable: true },
+      ...extra.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })),
+    ])
+    .signers([cranker])
+    .rpc();
+}
+
+async function marketWithOneFill() {
+  const mkt = await marketWithUnsettledFill();
+  await settle(mkt, (provider.wallet as any).payer as Keypair);
+  return mkt;
+}
+
+describe("fee sweep", () => {
+  it(
+    "moves accrued fees to the configured treasury and resets the counter",
+    async () => {
+      const mkt = await marketWithOneFill();
+      const payer = (provider.wallet as any).payer as Keypair;
+      const treasury = await getOrCreateAssociatedTokenAccount(provider.connection, payer, mkt.quoteMint, payer.publicKey);
+
+      const sweep = (feeAuthority: Keypair) =>
+        workspaceProgram.methods
+          .sweepFees()
+          .accounts({
+            feeAuthority: feeAuthority.publicKey,
+            market: mkt.market,
+            vaultAuth: mkt.vaultAuth,
+            quoteVault: mkt.quoteVault,
+            treasury: treasury.address,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([feeAuthority])
+          .rpc();
+
+      // No treasury configured yet.
+      await expect(sweep(payer)).rejects.toThrow(/InvalidTreasury/);
+
+      await workspaceProgram.methods
+        .setFeeConfig()
+        .accounts({
+          authority: provider.wallet.publicKey,
+          market: mkt.market,
+          feeAuthority: payer.publicKey,
+          treasury: treasury.address,
+        })
+        .rpc();
+
+      const claimable = await workspaceProgram.methods.claimableFees().accounts({ market: mkt.market }).view();
+      expect(claimable.toNumber()).toBe(1_000);
+
+      const stranger = Keypair.generate();
+      await airdrop(stranger.publicKey, LAMPORTS_PER_SOL);
+      await expect(sweep(stranger)).rejects.toThrow(/Unauthorized/);
+
+      await sweep(payer);
+      expect((await getAccount(provider.connection, treasury.address)).amount).toBe(1_000n);
+      const market = await (workspaceProgram.account as any).market.fetch(mkt.market);
+      expect(market.feesAccrued.toNumber()).toBe(0);
+    },
+    60_000
+  );
+});
+
+describe("settlement crank", () => {
+  it(
+    "lets anyone settle and pays the cranker its reward out of the fees",
+    async () => {
+      const mkt = await marketWithUnsettledFill();
+      const cranker = Keypair.generate();
+      await airdrop(cranker.publicKey, LAMPORTS_PER_SOL);
+      const crankerUb = pdas(workspaceProgram.programId, mkt.baseMint, mkt.quoteMint, cranker.publicKey).ub;
+      await workspaceProgram.methods
+        .initUserBalance()
+        .accounts({ payer: provider.wallet.publicKey, user: cranker.publicKey, market: mkt.market, ub: crankerUb, systemProgram: SYS_PROG })
+        .signers([cranker])
+        .rpc();
+
+      const stranger = Keypair.generate();
+      await airdrop(stranger.publicKey, LAMPORTS_PER_SOL);
+      await expect(
+        workspaceProgram.methods
+          .setCrankReward(new anchor.BN(100))
+          .accounts({ authority: stranger.publicKey, market: mkt.market })
+          .signers([stranger])
+          .rpc()
+      ).rejects.toThrow(/ConstraintHasOne|has.one/i);
+      await workspaceProgram.methods
+        .setCrankReward(new anchor.BN(100))
+        .accounts({ authority: provider.wallet.publicKey, market: mkt.market })
+        .rpc();
+
+      await settle(mkt, cranker, [crankerUb]);
+
+      // Two events: the fill and the maker's out.
+      const ub = await (workspaceProgram.account as any).userBalance.fetch(crankerUb);
+      expect(ub.quoteFree.toNumber()).toBe(200);
+      const market = await (workspaceProgram.account as any).market.fetch(mkt.market);
+      expect(market.feesAccrued.toNumber()).toBe(800);
+      expect(market.crankRewardsPaid.toNumber()).toBe(200);
+    },
+    60_000
+  );
+
+  it(
+    "keeps settling after a filled maker closes its open orders account",
+    async () => {
+      const mkt = await marketWithUnsettledFill();
+      const [order] = (await (workspaceProgram.account as any).openOrdersLite.fetch(mkt.oo)).slots.filter((s: any) => s.active);
+      await workspaceProgram.methods
+        .cancelOrder(order.orderId)
+        .accounts({ payer: mkt.maker.publicKey, market: mkt.market, oo: mkt.oo, ub: mkt.ub })
+        .signers([mkt.maker])
+        .rpc();
+      await workspaceProgram.methods
+        .closeOo()
+        .accounts({ payer: mkt.maker.publicKey, market: mkt.market, oo: mkt.oo })
+        .signers([mkt.maker])
+        .rpc();
+
+      // A stranger cranks past the fill and the out of the closed account.
+      const cranker = Keypair.generate();
+      await airdrop(cranker.publicKey, LAMPORTS_PER_SOL);
+      await settle(mkt, cranker);
+      const market = await (workspaceProgram.account as any).market.fetch(mkt.market);
+      expect(market.feesAccrued.toNumber()).toBe(1_000);
+      const makerUb = await (workspaceProgram.account as any).userBalance.fetch(mkt.ub);
+      expect(makerUb.baseLocked.toNumber()).toBe(0);
+      expect(makerUb.quoteFree.toNumber()).toBe(1_000_000);
+    },
+    60_000
+  );
+
+  it(
+    "pays nothing for cancel records",
+    async () => {
+      const mkt = await marketWithUnsettledFill();
+      const payer = (provider.wallet as any).payer as Keypair;
+      await settle(mkt, payer);
+
+      const cranker = Keypair.generate();
+      await airdrop(cranker.publicKey, LAMPORTS_PER_SOL);
+      const crankerUb = pdas(workspaceProgram.programId, mkt.baseMint, mkt.quoteMint, cranker.publicKey).ub;
+      await workspaceProgram.methods
+        .initUserBalance()
+        .accounts({ payer: provider.wallet.publicKey, user: cranker.publicKey, market: mkt.market, ub: crankerUb, systemProgram: SYS_PROG })
+        .signers([cranker])
+        .rpc();
+      await workspaceProgram.methods
+        .setCrankReward(new anchor.BN(100))
+        .accounts({ authority: provider.wallet.publicKey, market: mkt.market })
+        .rpc();
+
+      // Rest and cancel three asks: three Out Cancelled records and nothing else.
+      for (let i = 0; i < 3; i++) {
+        await workspaceProgram.methods
+          .placeOrder({
+            priceTicks: new anchor.BN(20_000),
+            baseQty: new anchor.BN(100),
+            side: 1,
+            maxSlippageTicks: new anchor.BN(0),
+            clientOrderId: new anchor.BN(i),
+            orderType: { limit: {} },
+            quoteLimit: new anchor.BN(0),
+            selfTradeBehavior: { decrementBoth: {} },
+            expiresAt: new anchor.BN(0),
+            displayQty: new anchor.BN(0),
+            matchLimit: 0,
+          })
+          .accounts({
+            payer: mkt.maker.publicKey,
+            market: mkt.market,
+            bids: mkt.bids,
+            asks: mkt.asks,
+            eventQueue: mkt.eventQueue,
+            oo: mkt.oo,
+            ub: mkt.ub,
+            systemProgram: SYS_PROG,
+          })
+          .signers([mkt.maker])
+          .rpc();
+        const oo = await (workspaceProgram.account as any).openOrdersLite.fetch(mkt.oo);
+        const slot = oo.slots.find((s: any) => s.active);
+        await workspaceProgram.methods
+          .cancelOrder(slot.orderId)
+          .accounts({ payer: mkt.maker.publicKey, market: mkt.market, oo: mkt.oo, ub: mkt.ub })
+          .signers([mkt.maker])
+          .rpc();
+      }
+
+      await settle(mkt, cranker, [crankerUb]);
+
+      const ub = await (workspaceProgram.account as any).userBalance.fetch(crankerUb);
+      expect(ub.quoteFree.toNumber()).toBe(0);
+      const market = await (workspaceProgram.account as any).market.fetch(mkt.market);
+      expect(market.feesAccrued.toNumber()).toBe(1_000);
+      expect(market.crankRewardsPaid.toNumber()).toBe(0);
+    },
+    60_000
+  );
+});
@@ -6,7 +6,9 @@ import {
   LAMPORTS_PER_SOL,
   ComputeBudgetProgram,
   ConfirmOptions,
+  Connection,
 } from "@solana/web3.js";
+import { createMint, getOrCreateAssociatedTokenAccount, mintTo, TOKEN_PROGRAM_ID } from "@solana/spl-token";
 
 const anchor = pkg as typeof import("@coral-xyz/anchor");
 export default anchor;
@@ -29,8 +31,10 @@ export async function airdropSOL(provider: import("@coral-xyz/anchor").AnchorPro
   );
 }
 
-export function randomMints() {
-  return { baseMint: Keypair.generate().publicKey, quoteMint: Keypair.generate().publicKey };
+export async function randomMints(provider: import("@coral-xyz/anchor").AnchorProvider, decimals = 6) {
+  const payer = (provider.wallet as any).payer as Keypair;
+  const mint = () => createMint(provider.connection, payer, payer.publicKey, null, decimals);
+  return { baseMint: await mint(), quoteMint: await mint() };
 }
 
 export function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
@@ -72,7 +76,8 @@ export const LITE_PROFILE = {
   event_queue_capacity: 512,
   tick_size: new BN(10_000),
   min_base_qty: new BN(100),
-  fees_bps: 10,
+  maker_fee_bps: 0,
+  taker_fee_bps: 10,
 };
 
 export const ComputeLimitIx = (units: number) => ComputeBudgetProgram.setComputeUnitLimit({ units });
@@ -107,3 +112,150 @@ export const Sizing = {
 };
 
 export const LAMPORTS_PER_SOL_CONST = LAMPORTS_PER_SOL;
+
+export const FUNDING = 1_000_000_000_000_000n;
+
+// Orders lock collateral from the owner's balance, so every trader gets vaults,
+// a user balance and a deposit of both tokens the first time they use a market.
+export async function fund(program: import("@coral-xyz/anchor").Program, market: PublicKey, user: Keypair | null) {
+  const provider = program.provider as import("@coral-xyz/anchor").AnchorProvider;
+  const payer = (provider.wallet as any).payer as Keypair;
+  const owner = user ?? payer;
+  const seeds = (tag: string, ...keys: PublicKey[]) =>
+    PublicKey.findProgramAddressSync([Buffer.from(tag), ...keys.map((k) => k.toBuffer())], program.programId)[0];
+  const ub = seeds("kerdos_user", market, owner.publicKey);
+  if (await provider.connection.getAccountInfo(ub)) return ub;
+
+  const { baseMint, quoteMint } = await (program.account as any).market.fetch(market);
+  const vaultAuth = seeds("kerdos_vault_auth", market);
+  const baseVault = seeds("kerdos_vault_base", market);
+  const quoteVault = seeds("kerdos_vault_quote", market);
+  if (!(await provider.connection.getAccountInfo(baseVault))) {
+    await program.methods
+      .initVaults()
+      .accounts({
+        payer: payer.publicKey,
+        authority: payer.publicKey,
+        market,
+        baseMint,
+        quoteMint,
+        vaultAuth,
+        baseVault,
+        quoteVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SYS_PROG,
+      })
+      .rpc();
+  }
+
+  const signers = user ? [user] : [];
+  const baseAta = await getOrCreateAssociatedTokenAccount(provider.connection, payer, baseMint, owner.publicKey);
+  const quoteAta = await getOrCreateAssociatedTokenAccount(provider.connection, payer, quoteMint, owner.publicKey);
+  await mintTo(provider.connection, payer, baseMint, baseAta.address, payer, FUNDING);
+  await mintTo(provider.connection, payer, quoteMint, quoteAta.address, payer, FUNDING);
+  await program.methods
+    .initUserBalance()
+    .accounts({ payer: payer.publicKey, user: owner.publicKey, market, ub, systemProgram: SYS_PROG })
+    .signers(signers)
+    .rpc();
+  await program.methods
+    .depositBase(new BN(FUNDING.toString()))
+    .accounts({ user: owner.publicKey, market, ub, userBaseAta: baseAta.address, baseVault, tokenProgram: TOKEN_PROGRAM_ID })
+    .signers(signers)
+    .rpc();
+  await program.methods
+    .depositQuote(new BN(FUNDING.toString()))
+    .accounts({ user: owner.publicKey, market, ub, userQuoteAta: quoteAta.address, quoteVault, tokenProgram: TOKEN_PROGRAM_ID })
+    .signers(signers)
+    .rpc();
+  return ub;
+}
+
+// Event queue layout, mirroring eventq.rs and the `Event` structs in state.rs: the blob
+// header, a (head, count, seq_num) ring header, then fixed slots of (seq_num, tag, event)
+// wrapping at the blob capacity. Specs read the queue only through `readEventQueue`.
+type FieldKind = "pubkey" | "u64" | "u8" | "bool" | "pad4" | "pad5";
+const FIELD_LEN: Record<FieldKind, number> = { pubkey: 32, u64: 8, u8: 1, bool: 1, pad4: 4, pad5: 5 };
+
+const FILL_FIELDS: [string, FieldKind][] = [
+  ["makerOo", "pubkey"],
+  ["takerOo", "pubkey"],
+  ["makerOwner", "pubkey"],
+  ["takerOwner", "pubkey"],
+  ["baseQty", "u64"],
+  ["makerClientOrderId", "u64"],
+  ["takerClientOrderId", "u64"],
+  ["priceTicks", "u64"],
+  ["makerLocked", "u64"],
+  ["takerLocked", "u64"],
+  ["makerOrderId", "u64"],
+  ["takerOrderId", "u64"],
+  ["takerSide", "u8"],
+  ["makerSlot", "u8"],
+  ["takerSlot", "u8"],
+  ["takerResting", "bool"],
+  ["pad", "pad4"],
+];
+
+const OUT_FIELDS: [string, FieldKind][] = [
+  ["ownerOo", "pubkey"],
+  ["owner", "pubkey"],
+  ["orderId", "u64"],
+  ["clientOrderId", "u64"],
+  ["baseQty", "u64"],
+  ["locked", "u64"],
+  ["side", "u8"],
+  ["slot", "u8"],
+  ["reason", "u8"],
+  ["pad", "pad5"],
+];
+
+export const OUT_REASONS = ["expired", "filled", "cancelled", "selfTrade"] as const;
+
+const BLOB_HEADER_LEN = 4 + 1 + 4 + 4;
+const RING_HEADER_LEN = 4 + 4 + 8;
+const fieldsLen = (fields: [string, FieldKind][]) => fields.reduce((n, [, k]) => n + FIELD_LEN[k], 0);
+const EVENT_LEN = 1 + Math.max(fieldsLen(FILL_FIELDS), fieldsLen(OUT_FIELDS));
+const SLOT_LEN = 8 + EVENT_LEN;
+
+function decodeFields(d: Buffer, off: number, fields: [string, FieldKind][]) {
+  const out: Record<string, any> = {};
+  for (const [name, kind] of fields) {
+    if (kind === "pubkey") out[name] = new PublicKey(d.subarray(off, off + 32));
+    else if (kind === "u64") out[name] = Number(d.readBigUInt64LE(off));
+    else if (kind === "u8") out[name] = d.readUInt8(off);
+    else if (kind === "bool") out[name] = d.readUInt8(off) !== 0;
+    off += FIELD_LEN[kind];
+  }
+  return out;
+}
+
+export type QueuedEvent =
+  | ({ seqNum: number; kind: "fill" } & Record<string, any>)
+  | ({ seqNum: number; kind: "out"; reason: (typeof OUT_REASONS)[number] } & Record<string, any>);
+
+/** Queued events, oldest first, plus the ring header. u64 fields decode as numbers. */
+export async function readEventQueue(connection: Connection, eventQueue: PublicKey) {
+  const d = (await connection.getAccountInfo(eventQueue))!.data;
+  const cap = d.readUInt32LE(5);
+  const head = d.readUInt32LE(BLOB_HEADER_LEN);
+  const count = d.readUInt32LE(BLOB_HEADER_LEN + 4);
+  const nextSeqNum = Number(d.readBigUInt64LE(BLOB_HEADER_LEN + 8));
+  const events: QueuedEvent[] = [];
+  for (let i = 0; i < count; i++) {
+    const off = BLOB_HEADER_LEN + RING_HEADER_LEN + ((head + i) % cap) * SLOT_LEN;
+    const seqNum = Number(d.readBigUInt64LE(off));
+    if (d.readUInt8(off + 8) === 0) {
+      events.push({ seqNum, kind: "fill", ...decodeFields(d, off + 9, FILL_FIELDS) });
+    } else {
+      const ev = decodeFields(d, off + 9, OUT_FIELDS);
+      events.push({ seqNum, kind: "out", ...ev, reason: OUT_REASONS[ev.reason] });
+    }
+  }
+  return { head, count, nextSeqNum, events };
+}
+
+/** Just the queued fills, oldest first. */
+export async function readFills(connection: Connection, eventQueue: PublicKey) {
+  return (await readEventQueue(connection, eventQueue)).events.filter((e) => e.kind === "fill");
+}
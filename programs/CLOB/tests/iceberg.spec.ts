@@ -0,0 +1,153 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund, readFills } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+async function setupMarket() {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+async function place(
+  pdas: ReturnType<typeof derivePDAs>,
+  user: Keypair | null,
+  baseQty: number,
+  side: 0 | 1,
+  displayQty = 0
+) {
+  const payer = user ? user.publicKey : provider.wallet.publicKey;
+  const ub = await fund(program, pdas.market, user);
+  const builder = program.methods
+    .placeOrder({
+      priceTicks: new anchor.BN(50_000),
+      baseQty: new anchor.BN(baseQty),
+      side,
+      maxSlippageTicks: new anchor.BN(0),
+      clientOrderId: new anchor.BN(0),
+      orderType: { limit: {} },
+      quoteLimit: new anchor.BN(0),
+      selfTradeBehavior: { decrementBoth: {} },
+      expiresAt: new anchor.BN(0),
+      displayQty: new anchor.BN(displayQty),
+      matchLimit: 0,
+    })
+    .accounts({
+      payer,
+      ...pdas,
+      oo: ooOf(pdas.market, payer),
+      ub,
+      systemProgram: SYS_PROG,
+    });
+  return user ? builder.signers([user]).rpc() : builder.rpc();
+}
+
+function fills(pdas: ReturnType<typeof derivePDAs>) {
+  return readFills(provider.connection, pdas.eventQueue);
+}
+
+describe("iceberg orders", () => {
+  it("shows only the display size and refills behind the rest of the level", async () => {
+    const pdas = await setupMarket();
+    const other = Keypair.generate();
+    const taker = Keypair.generate();
+    await airdropSOL(other.publicKey, 2 * LAMPORTS_PER_SOL);
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const icebergOo = ooOf(pdas.market, provider.wallet.publicKey);
+    const otherOo = ooOf(pdas.market, other.publicKey);
+
+    await place(pdas, null, 300, 1, 100);
+    await place(pdas, other, 100, 1);
+
+    // 200 takes the visible 100, then the plain order that is now ahead of the refill.
+    await place(pdas, taker, 200, 0);
+    let f = await fills(pdas);
+    expect(f.map((e) => [e.makerOo.toBase58(), e.baseQty])).toEqual([
+      [icebergOo.toBase58(), 100],
+      [otherOo.toBase58(), 100],
+    ]);
+
+    // The reserve keeps refilling until the full 300 has traded.
+    await place(pdas, taker, 300, 0);
+    f = await fills(pdas);
+    expect(f.slice(2).map((e) => [e.makerOo.toBase58(), e.baseQty])).toEqual([
+      [icebergOo.toBase58(), 100],
+      [icebergOo.toBase58(), 100],
+    ]);
+    const takerOo = await (program.account as any).openOrdersLite.fetch(ooOf(pdas.market, taker.publicKey));
+    const resting = takerOo.slots.filter((s: any) => s.active);
+    expect(resting).toHaveLength(1);
+    expect(resting[0].baseQty.toNumber()).toBe(100);
+  });
+
+  it("rejects a display size off the lot grid", async () => {
+    const pdas = await setupMarket();
+    await expect(place(pdas, null, 300, 1, 150)).rejects.toThrow(/InvalidDisplayQty/);
+  });
+});
@@ -24,7 +24,7 @@ describe("kerdos_markets — init_market Lite", () => {
   it("crea mercado Lite y reporta space/rent/compute", async () => {
     await airdropSOL(provider, 2 * LAMPORTS_PER_SOL);
 
-    const { baseMint, quoteMint } = randomMints();
+    const { baseMint, quoteMint } = await randomMints(provider);
     const { market, bids, asks, eventQueue } = derivePDAs(program.programId, baseMint, quoteMint);
 
     const params = {
@@ -35,7 +35,12 @@ describe("kerdos_markets — init_market Lite", () => {
       eventQueueCapacity: LITE_PROFILE.event_queue_capacity,
       tickSize: LITE_PROFILE.tick_size,
       minBaseQty: LITE_PROFILE.min_base_qty,
-      feesBps: LITE_PROFILE.fees_bps,
+      makerFeeBps: LITE_PROFILE.maker_fee_bps,
+      takerFeeBps: LITE_PROFILE.taker_fee_bps,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
     };
 
     const cuIx = ComputeLimitIx(envNum("KERDOS_INIT_CU_LIMIT", 1_400_000));
@@ -81,4 +86,45 @@ describe("kerdos_markets — init_market Lite", () => {
     const maxRentSOL = envNum("KERDOS_LITE_RENT_MAX_SOL", 0.06);
     expect(totalSOL).toBeLessThanOrEqual(maxRentSOL);
   });
+
+  it("rechaza un rebate de maker mayor que el fee de taker", async () => {
+    const { baseMint, quoteMint } = await randomMints(provider);
+    const { market, bids, asks, eventQueue } = derivePDAs(program.programId, baseMint, quoteMint);
+
+    const init = (makerFeeBps: number, takerFeeBps: number) =>
+      program.methods
+        .initMarket({
+          baseMint,
+          quoteMint,
+          bidsCapacity: LITE_PROFILE.bids_capacity,
+          asksCapacity: LITE_PROFILE.asks_capacity,
+          eventQueueCapacity: LITE_PROFILE.event_queue_capacity,
+          tickSize: LITE_PROFILE.tick_size,
+          minBaseQty: LITE_PROFILE.min_base_qty,
+          makerFeeBps,
+          takerFeeBps,
+          baseLotSize: new anchor.BN(1),
+          quoteLotSize: new anchor.BN(1),
+          engineKind: { priceTime: {} },
+          auctionIntervalSlots: new anchor.BN(0),
+        })
+        .preInstructions([ComputeLimitIx(1_400_000)])
+        .accounts({
+          payer: provider.wallet.publicKey,
+          authority: provider.wallet.publicKey,
+          baseMint,
+          quoteMint,
+          market,
+          bids,
+          asks,
+          eventQueue,
+          systemProgram: SYS_PROG,
+        })
+        .rpc();
+
+    await expect(init(-11, 10)).rejects.toThrow(/InvalidFees/);
+    await init(-10, 10);
+    const m = await (program.account as any).market.fetch(market);
+    expect([m.makerFeeBps, m.takerFeeBps]).toEqual([-10, 10]);
+  });
 });
@@ -0,0 +1,256 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund, FUNDING, readEventQueue } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+async function setupMarket() {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+async function place(
+  pdas: ReturnType<typeof derivePDAs>,
+  user: Keypair | null,
+  priceTicks: number,
+  baseQty: number,
+  side: 0 | 1
+) {
+  const payer = user ? user.publicKey : provider.wallet.publicKey;
+  const ub = await fund(program, pdas.market, user);
+  const builder = program.methods
+    .placeOrder({
+      priceTicks: new anchor.BN(priceTicks),
+      baseQty: new anchor.BN(baseQty),
+      side,
+      maxSlippageTicks: new anchor.BN(0),
+      clientOrderId: new anchor.BN(0),
+      orderType: { limit: {} },
+      quoteLimit: new anchor.BN(0),
+      selfTradeBehavior: { decrementBoth: {} },
+      expiresAt: new anchor.BN(0),
+      displayQty: new anchor.BN(0),
+      matchLimit: 0,
+    })
+    .accounts({
+      payer,
+      ...pdas,
+      oo: ooOf(pdas.market, payer),
+      ub,
+      systemProgram: SYS_PROG,
+    });
+  return user ? builder.signers([user]).rpc() : builder.rpc();
+}
+
+async function settle(pdas: ReturnType<typeof derivePDAs>, users: (Keypair | null)[]) {
+  const accounts: PublicKey[] = [];
+  for (const user of users) {
+    const owner = user ? user.publicKey : provider.wallet.publicKey;
+    accounts.push(ooOf(pdas.market, owner), await fund(program, pdas.market, user));
+  }
+  return program.methods
+    .settleEvents(64)
+    .accounts({ cranker: provider.wallet.publicKey, market: pdas.market, eventQueue: pdas.eventQueue })
+    .remainingAccounts(accounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })))
+    .rpc();
+}
+
+async function activeSlots(oo: PublicKey) {
+  const acc = await (program.account as any).openOrdersLite.fetch(oo);
+  return acc.slots.filter((s: any) => s.active);
+}
+
+async function cancel(pdas: ReturnType<typeof derivePDAs>, orderId: anchor.BN) {
+  return program.methods
+    .cancelOrder(orderId)
+    .accounts({
+      payer: provider.wallet.publicKey,
+      market: pdas.market,
+      oo: ooOf(pdas.market, provider.wallet.publicKey),
+      ub: await fund(program, pdas.market, null),
+    })
+    .rpc();
+}
+
+// Slab nodes follow the 13-byte blob header and the 24-byte slab header, 152 bytes each:
+// price u64, base_qty u64, oo, owner, order_id u64, client id, ts, expires_at, hidden_qty
+// u64, ... Freed nodes are zeroed, so a scan only finds live orders.
+async function bookSize(book: PublicKey, oo: PublicKey, orderId: anchor.BN) {
+  const d = (await provider.connection.getAccountInfo(book))!.data;
+  for (let off = 37; off + 152 <= d.length; off += 152) {
+    if (!new PublicKey(d.subarray(off + 16, off + 48)).equals(oo)) continue;
+    if (d.readBigUInt64LE(off + 80) !== BigInt(orderId.toString())) continue;
+    return Number(d.readBigUInt64LE(off + 8)) + Number(d.readBigUInt64LE(off + 112));
+  }
+  return 0;
+}
+
+describe("maker open orders", () => {
+  it("catch up with the book on settlement and drop fully filled orders", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const makerOo = ooOf(pdas.market, provider.wallet.publicKey);
+
+    await place(pdas, null, 50_000, 100, 1);
+    await place(pdas, null, 60_000, 200, 1);
+    const [first, second] = await activeSlots(makerOo);
+
+    // Takes all of the first ask and half of the second.
+    await place(pdas, taker, 60_000, 200, 0);
+    expect((await activeSlots(makerOo)).map((s: any) => s.baseQty.toNumber())).toEqual([100, 200]);
+    expect(await bookSize(pdas.asks, makerOo, first.orderId)).toBe(0);
+    expect(await bookSize(pdas.asks, makerOo, second.orderId)).toBe(100);
+
+    await settle(pdas, [null, taker]);
+    const slots = await activeSlots(makerOo);
+    expect(slots).toHaveLength(1);
+    expect(slots[0].orderId.toString()).toBe(second.orderId.toString());
+    expect(slots[0].baseQty.toNumber()).toBe(await bookSize(pdas.asks, makerOo, second.orderId));
+    expect(slots[0].filledQty.toNumber()).toBe(100);
+  });
+
+  it("settle fills of a taker that has since closed its open orders account", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const takerOo = ooOf(pdas.market, taker.publicKey);
+
+    await place(pdas, null, 50_000, 100, 1);
+    await place(pdas, taker, 50_000, 100, 0);
+    await program.methods
+      .closeOo()
+      .accounts({ payer: taker.publicKey, market: pdas.market, oo: takerOo })
+      .signers([taker])
+      .rpc();
+    expect(await provider.connection.getAccountInfo(takerOo)).toBeNull();
+
+    const takerUb = await fund(program, pdas.market, taker);
+    await program.methods
+      .settleEvents(64)
+      .accounts({ cranker: provider.wallet.publicKey, market: pdas.market, eventQueue: pdas.eventQueue })
+      .remainingAccounts(
+        [ooOf(pdas.market, provider.wallet.publicKey), await fund(program, pdas.market, null), takerUb].map((pubkey) => ({
+          pubkey,
+          isSigner: false,
+          isWritable: true,
+        }))
+      )
+      .rpc();
+
+    const ub = await (program.account as any).userBalance.fetch(takerUb);
+    expect(ub.quoteLocked.toNumber()).toBe(0);
+    expect(BigInt(ub.baseFree.toString())).toBe(FUNDING + 100n);
+  });
+
+  it("settle fills of a maker that cancelled and closed its open orders account", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const makerOo = ooOf(pdas.market, provider.wallet.publicKey);
+
+    await place(pdas, null, 50_000, 100, 1);
+    const [order] = await activeSlots(makerOo);
+    await place(pdas, taker, 50_000, 100, 0);
+
+    // Off the book already: the cancel only frees the slot, which lets the account close.
+    await cancel(pdas, order.orderId);
+    await program.methods
+      .closeOo()
+      .accounts({ payer: provider.wallet.publicKey, market: pdas.market, oo: makerOo })
+      .rpc();
+    expect(await provider.connection.getAccountInfo(makerOo)).toBeNull();
+
+    await settle(pdas, [null, taker]);
+    expect((await readEventQueue(provider.connection, pdas.eventQueue)).count).toBe(0);
+    const ub = await (program.account as any).userBalance.fetch(await fund(program, pdas.market, null));
+    expect(ub.baseLocked.toNumber()).toBe(0);
+    expect(BigInt(ub.baseFree.toString())).toBe(FUNDING - 100n);
+  });
+
+  it("cancel a partly filled order for what is left on the book", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+    const makerOo = ooOf(pdas.market, provider.wallet.publicKey);
+
+    await place(pdas, null, 50_000, 300, 1);
+    await place(pdas, taker, 50_000, 100, 0);
+    await settle(pdas, [null, taker]);
+    const [order] = await activeSlots(makerOo);
+    expect(order.baseQty.toNumber()).toBe(await bookSize(pdas.asks, makerOo, order.orderId));
+
+    // The fill paid out its 100 when it settled; the cancel returns the other 200.
+    await cancel(pdas, order.orderId);
+    const ub = await (program.account as any).userBalance.fetch(await fund(program, pdas.market, null));
+    expect(ub.baseLocked.toNumber()).toBe(0);
+    expect(BigInt(ub.baseFree.toString())).toBe(FUNDING - 100n);
+    expect(await activeSlots(makerOo)).toHaveLength(0);
+  });
+});
@@ -1,6 +1,8 @@
 import { describe, it, expect } from "vitest";
 import * as anchor from "@coral-xyz/anchor";
-import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair } from "@solana/web3.js";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund } from "./helpers";
 
 const provider = anchor.AnchorProvider.env();
 anchor.setProvider(provider);
@@ -8,6 +10,11 @@ const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
 
 const SYS_PROG = anchor.web3.SystemProgram.programId;
 
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
 function computeLimitIx(units: number) {
   return ComputeBudgetProgram.setComputeUnitLimit({ units });
 }
@@ -43,8 +50,8 @@ async function airdropSOL(pubkey: anchor.web3.PublicKey, lamports: number) {
 
 describe("match then maker cancel", () => {
   it("crosses best ask with taker bid; taker OO ends inactive", async () => {
-    const baseMint = Keypair.generate().publicKey;
-    const quoteMint = Keypair.generate().publicKey;
+    const baseMint = await newMint();
+    const quoteMint = await newMint();
 
     const { market, bids, asks, eventQueue } = derivePDAs(
       program.programId,
@@ -62,9 +69,14 @@ describe("match then maker cancel", () => {
       bidsCapacity: 1024,
       asksCapacity: 1024,
       eventQueueCapacity: 512,
-      tickSize: new anchor.BN(10_000),
+      tickSize: new anchor.BN(1_000),
       minBaseQty: new anchor.BN(100),
-      feesBps: 10,
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
     };
 
     await program.methods
@@ -88,15 +100,22 @@ describe("match then maker cancel", () => {
       [Buffer.from("kerdos_oo"), market.toBuffer(), provider.wallet.publicKey.toBuffer()],
       program.programId
     );
-    const lock = Math.floor(0.05 * LAMPORTS_PER_SOL);
+    const ubMaker = await fund(program, market, null);
+    const ubTaker = await fund(program, market, taker);
 
     await program.methods
       .placeOrder({
         priceTicks: new anchor.BN(10_000), // ask 10k
         baseQty: new anchor.BN(100),
         side: 1, // ask
-        lockLamports: new anchor.BN(lock),
         maxSlippageTicks: new anchor.BN(0),
+        clientOrderId: new anchor.BN(0),
+        orderType: { limit: {} },
+        quoteLimit: new anchor.BN(0),
+        selfTradeBehavior: { decrementBoth: {} },
+        expiresAt: new anchor.BN(0),
+        displayQty: new anchor.BN(0),
+        matchLimit: 0,
       })
       .accounts({
         payer: provider.wallet.publicKey,
@@ -104,7 +123,8 @@ describe("match then maker cancel", () => {
         bids,
         asks,
         eventQueue,
-        ooMaker,
+        oo: ooMaker,
+        ub: ubMaker,
         systemProgram: SYS_PROG,
       } as any) 
       .rpc();
@@ -119,8 +139,14 @@ describe("match then maker cancel", () => {
         priceTicks: new anchor.BN(12_000),
         baseQty: new anchor.BN(100),
         side: 0, // bid
-        lockLamports: new anchor.BN(lock),
         maxSlippageTicks: new anchor.BN(10_000),
+        clientOrderId: new anchor.BN(0),
+        orderType: { limit: {} },
+        quoteLimit: new anchor.BN(0),
+        selfTradeBehavior: { decrementBoth: {} },
+        expiresAt: new anchor.BN(0),
+        displayQty: new anchor.BN(0),
+        matchLimit: 0,
       })
       .accounts({
         payer: taker.publicKey,
@@ -129,19 +155,24 @@ describe("match then maker cancel", () => {
         asks,
         eventQueue,
         oo: ooTaker,
+        ub: ubTaker,
         systemProgram: SYS_PROG,
       })
       .signers([taker])
       .rpc();
 
     const takerOo = await (program.account as any).openOrdersLite.fetch(ooTaker);
-    expect(takerOo.active).toBe(false);
+    expect(takerOo.slots[0].active).toBe(false);
+
+    // Locked at the 12k limit, then trued up to what the fill at 10k costs: 1M plus the 10 bps fee.
+    const takerUb = await (program.account as any).userBalance.fetch(ubTaker);
+    expect(takerUb.quoteLocked.toNumber()).toBe(1_001_000);
 
    
     const makerOo = await (program.account as any).openOrdersLite.fetch(ooMaker);
-    if (makerOo.active) {
+    if (makerOo.slots[0].active) {
       await program.methods
-        .cancelOrder()
+        .cancelOrder(makerOo.slots[0].orderId)
         .accounts({
           payer: provider.wallet.publicKey,
           market,
@@ -149,7 +180,7 @@ describe("match then maker cancel", () => {
         })
         .rpc();
       const makerOoAfter = await (program.account as any).openOrdersLite.fetch(ooMaker);
-      expect(makerOoAfter.active).toBe(false);
+      expect(makerOoAfter.slots[0].active).toBe(false);
     }
   });
 });
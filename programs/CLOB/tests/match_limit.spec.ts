@@ -0,0 +1,184 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund, readEventQueue } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+async function setupMarket() {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+function params(side: 0 | 1, baseQty: number, orderType: object, matchLimit: number) {
+  return {
+    priceTicks: new anchor.BN(50_000),
+    baseQty: new anchor.BN(baseQty),
+    side,
+    maxSlippageTicks: new anchor.BN(0),
+    clientOrderId: new anchor.BN(0),
+    orderType,
+    quoteLimit: new anchor.BN(0),
+    selfTradeBehavior: { decrementBoth: {} },
+    expiresAt: new anchor.BN(0),
+    displayQty: new anchor.BN(0),
+    matchLimit,
+  };
+}
+
+async function accountsFor(pdas: ReturnType<typeof derivePDAs>, user: Keypair) {
+  const payer = user.publicKey;
+  return { payer, ...pdas, oo: ooOf(pdas.market, payer), ub: await fund(program, pdas.market, user), systemProgram: SYS_PROG };
+}
+
+// 48 resting asks of one lot each, 16 per maker (a full open orders account).
+async function fillBookWithTinyAsks(pdas: ReturnType<typeof derivePDAs>) {
+  for (let m = 0; m < 3; m++) {
+    const maker = Keypair.generate();
+    await airdropSOL(maker.publicKey, LAMPORTS_PER_SOL);
+    for (let half = 0; half < 2; half++) {
+      const items = Array.from({ length: 8 }, () => ({ place: { params: params(1, 100, { limit: {} }, 0) } }));
+      await program.methods
+        .batchOrders(items)
+        .preInstructions([computeLimitIx(1_400_000)])
+        .accounts(await accountsFor(pdas, maker))
+        .signers([maker])
+        .rpc();
+    }
+  }
+}
+
+// Compute units a taker at the default match limit stays under (see DEFAULT_MATCH_LIMIT).
+const TAKER_COMPUTE_CEILING = 600_000;
+
+async function takeBid(
+  pdas: ReturnType<typeof derivePDAs>,
+  taker: Keypair,
+  orderType: object,
+  matchLimit: number,
+  computeUnits = 1_400_000
+) {
+  return program.methods
+    .placeOrder(params(0, 4_800, orderType, matchLimit))
+    .preInstructions([computeLimitIx(computeUnits)])
+    .accounts(await accountsFor(pdas, taker))
+    .signers([taker])
+    .rpc();
+}
+
+async function eventCount(pdas: ReturnType<typeof derivePDAs>) {
+  return (await readEventQueue(provider.connection, pdas.eventQueue)).count;
+}
+
+async function activeSlots(oo: PublicKey) {
+  const acc = await (program.account as any).openOrdersLite.fetch(oo);
+  return acc.slots.filter((s: any) => s.active);
+}
+
+describe("match limit", () => {
+  it("stops after the given number of makers and drops a still-crossing remainder", async () => {
+    const pdas = await setupMarket();
+    await fillBookWithTinyAsks(pdas);
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, LAMPORTS_PER_SOL);
+
+    // Every maker is taken whole: a fill and an out event each.
+    await takeBid(pdas, taker, { immediateOrCancel: {} }, 5);
+    expect(await eventCount(pdas)).toBe(10);
+
+    // A limit remainder would rest on top of the asks it could not reach; it is dropped.
+    const sig = await takeBid(pdas, taker, { limit: {} }, 3);
+    expect(await eventCount(pdas)).toBe(16);
+    const tx = await provider.connection.getTransaction(sig, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+    const placed = [...parser.parseLogs(tx!.meta!.logMessages!)].find((e) => e.name === "orderPlaced")!;
+    expect(placed.data.matchLimitReached).toBe(true);
+    expect(placed.data.restingQty.toNumber()).toBe(0);
+    expect(await activeSlots(ooOf(pdas.market, taker.publicKey))).toHaveLength(0);
+  });
+
+  it("keeps the default worst case inside the compute budget", async () => {
+    const pdas = await setupMarket();
+    await fillBookWithTinyAsks(pdas);
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, LAMPORTS_PER_SOL);
+
+    // Requested at the ceiling, so going over it fails the transaction outright.
+    const sig = await takeBid(pdas, taker, { immediateOrCancel: {} }, 0, TAKER_COMPUTE_CEILING);
+    expect(await eventCount(pdas)).toBe(64);
+
+    const tx = await provider.connection.getTransaction(sig, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    expect(tx!.meta!.computeUnitsConsumed!).toBeLessThanOrEqual(TAKER_COMPUTE_CEILING);
+  });
+});
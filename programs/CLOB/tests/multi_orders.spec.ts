@@ -0,0 +1,310 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(
+  programId: anchor.web3.PublicKey,
+  baseMint: anchor.web3.PublicKey,
+  quoteMint: anchor.web3.PublicKey
+) {
+  const [market] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_bids"), market.toBuffer()],
+    programId
+  );
+  const [asks] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_asks"), market.toBuffer()],
+    programId
+  );
+  const [eventQueue] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_eventq"), market.toBuffer()],
+    programId
+  );
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: anchor.web3.PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+describe("multiple resting orders per user", () => {
+  it("quotes both sides and a ladder, then cancels one order by id", async () => {
+    const baseMint = await newMint();
+    const quoteMint = await newMint();
+    const { market, bids, asks, eventQueue } = derivePDAs(program.programId, baseMint, quoteMint);
+
+    await airdropSOL(provider.wallet.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    await program.methods
+      .initMarket({
+        baseMint,
+        quoteMint,
+        bidsCapacity: 1024,
+        asksCapacity: 1024,
+        eventQueueCapacity: 512,
+        tickSize: new anchor.BN(10_000),
+        minBaseQty: new anchor.BN(100),
+        makerFeeBps: 0,
+        takerFeeBps: 10,
+        baseLotSize: new anchor.BN(1),
+        quoteLotSize: new anchor.BN(1),
+        engineKind: { priceTime: {} },
+        auctionIntervalSlots: new anchor.BN(0),
+      })
+      .preInstructions([computeLimitIx(1_400_000)])
+      .accounts({
+        payer: provider.wallet.publicKey,
+        authority: provider.wallet.publicKey,
+        baseMint,
+        quoteMint,
+        market,
+        bids,
+        asks,
+        eventQueue,
+        systemProgram: SYS_PROG,
+      })
+      .rpc();
+
+    const [oo] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("kerdos_oo"), market.toBuffer(), provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+    const ub = await fund(program, market, null);
+    const quotes = [
+      { priceTicks: 10_000, side: 0, clientOrderId: 7001 },
+      { priceTicks: 20_000, side: 0, clientOrderId: 7002 },
+      { priceTicks: 40_000, side: 1, clientOrderId: 7003 },
+      { priceTicks: 50_000, side: 1, clientOrderId: 7004 },
+    ];
+    for (const q of quotes) {
+      await program.methods
+        .placeOrder({
+          priceTicks: new anchor.BN(q.priceTicks),
+          baseQty: new anchor.BN(100),
+          side: q.side,
+          maxSlippageTicks: new anchor.BN(0),
+          clientOrderId: new anchor.BN(q.clientOrderId),
+          orderType: { limit: {} },
+          quoteLimit: new anchor.BN(0),
+          selfTradeBehavior: { decrementBoth: {} },
+          expiresAt: new anchor.BN(0),
+          displayQty: new anchor.BN(0),
+          matchLimit: 0,
+        })
+        .accounts({
+          payer: provider.wallet.publicKey,
+          market,
+          bids,
+          asks,
+          eventQueue,
+          oo,
+          ub,
+          systemProgram: SYS_PROG,
+        })
+        .rpc();
+    }
+
+    const ooAcc = await (program.account as any).openOrdersLite.fetch(oo);
+    const active = ooAcc.slots.filter((s: any) => s.active);
+    expect(active.length).toBe(4);
+    expect(new Set(active.map((s: any) => s.orderId.toString())).size).toBe(4);
+
+    const target = active.find((s: any) => s.priceTicks.toNumber() === 20_000);
+    await program.methods
+      .cancelOrder(target.orderId)
+      .accounts({
+        payer: provider.wallet.publicKey,
+        market,
+        oo,
+        ub,
+      })
+      .rpc();
+
+    const after = await (program.account as any).openOrdersLite.fetch(oo);
+    const remaining = after.slots.filter((s: any) => s.active).map((s: any) => s.priceTicks.toNumber());
+    expect(remaining.sort()).toEqual([10_000, 40_000, 50_000]);
+  });
+
+  it("cancels by client order id and rejects duplicate client ids", async () => {
+    const baseMint = await newMint();
+    const quoteMint = await newMint();
+    const { market, bids, asks, eventQueue } = derivePDAs(program.programId, baseMint, quoteMint);
+
+    await program.methods
+      .initMarket({
+        baseMint,
+        quoteMint,
+        bidsCapacity: 1024,
+        asksCapacity: 1024,
+        eventQueueCapacity: 512,
+        tickSize: new anchor.BN(10_000),
+        minBaseQty: new anchor.BN(100),
+        makerFeeBps: 0,
+        takerFeeBps: 10,
+        baseLotSize: new anchor.BN(1),
+        quoteLotSize: new anchor.BN(1),
+        engineKind: { priceTime: {} },
+        auctionIntervalSlots: new anchor.BN(0),
+      })
+      .preInstructions([computeLimitIx(1_400_000)])
+      .accounts({
+        payer: provider.wallet.publicKey,
+        authority: provider.wallet.publicKey,
+        baseMint,
+        quoteMint,
+        market,
+        bids,
+        asks,
+        eventQueue,
+        systemProgram: SYS_PROG,
+      })
+      .rpc();
+
+    const [oo] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("kerdos_oo"), market.toBuffer(), provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+    const ub = await fund(program, market, null);
+    const place = (clientOrderId: number) =>
+      program.methods
+        .placeOrder({
+          priceTicks: new anchor.BN(10_000),
+          baseQty: new anchor.BN(100),
+          side: 0,
+          maxSlippageTicks: new anchor.BN(0),
+          clientOrderId: new anchor.BN(clientOrderId),
+          orderType: { limit: {} },
+          quoteLimit: new anchor.BN(0),
+          selfTradeBehavior: { decrementBoth: {} },
+          expiresAt: new anchor.BN(0),
+          displayQty: new anchor.BN(0),
+          matchLimit: 0,
+        })
+        .accounts({
+          payer: provider.wallet.publicKey,
+          market,
+          bids,
+          asks,
+          eventQueue,
+          oo,
+          ub,
+          systemProgram: SYS_PROG,
+        })
+        .rpc();
+
+    await place(42);
+    await expect(place(42)).rejects.toThrow(/DuplicateClientOrderId/);
+
+    await program.methods
+      .cancelOrderByClientId(new anchor.BN(42))
+      .accounts({
+        payer: provider.wallet.publicKey,
+        market,
+        oo,
+        ub,
+      })
+      .rpc();
+
+    const after = await (program.account as any).openOrdersLite.fetch(oo);
+    expect(after.slots.some((s: any) => s.active)).toBe(false);
+  });
+
+  it("cancels all orders on one side, then up to a limit", async () => {
+    const baseMint = await newMint();
+    const quoteMint = await newMint();
+    const { market, bids, asks, eventQueue } = derivePDAs(program.programId, baseMint, quoteMint);
+
+    await program.methods
+      .initMarket({
+        baseMint,
+        quoteMint,
+        bidsCapacity: 1024,
+        asksCapacity: 1024,
+        eventQueueCapacity: 512,
+        tickSize: new anchor.BN(10_000),
+        minBaseQty: new anchor.BN(100),
+        makerFeeBps: 0,
+        takerFeeBps: 10,
+        baseLotSize: new anchor.BN(1),
+        quoteLotSize: new anchor.BN(1),
+        engineKind: { priceTime: {} },
+        auctionIntervalSlots: new anchor.BN(0),
+      })
+      .preInstructions([computeLimitIx(1_400_000)])
+      .accounts({
+        payer: provider.wallet.publicKey,
+        authority: provider.wallet.publicKey,
+        baseMint,
+        quoteMint,
+        market,
+        bids,
+        asks,
+        eventQueue,
+        systemProgram: SYS_PROG,
+      })
+      .rpc();
+
+    const [oo] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("kerdos_oo"), market.toBuffer(), provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+    const ub = await fund(program, market, null);
+    for (const [priceTicks, side] of [[10_000, 0], [20_000, 0], [40_000, 1], [50_000, 1]]) {
+      await program.methods
+        .placeOrder({
+          priceTicks: new anchor.BN(priceTicks),
+          baseQty: new anchor.BN(100),
+          side,
+          maxSlippageTicks: new anchor.BN(0),
+          clientOrderId: new anchor.BN(0),
+          orderType: { limit: {} },
+          quoteLimit: new anchor.BN(0),
+          selfTradeBehavior: { decrementBoth: {} },
+          expiresAt: new anchor.BN(0),
+          displayQty: new anchor.BN(0),
+          matchLimit: 0,
+        })
+        .accounts({ payer: provider.wallet.publicKey, market, bids, asks, eventQueue, oo, ub, systemProgram: SYS_PROG })
+        .rpc();
+    }
+
+    const cancelAll = (side: number | null, limit: number) =>
+      program.methods
+        .cancelAllOrders(side, limit)
+        .accounts({ payer: provider.wallet.publicKey, market, bids, asks, oo, ub })
+        .rpc();
+    const active = async () =>
+      ((await (program.account as any).openOrdersLite.fetch(oo)).slots as any[]).filter((s) => s.active);
+
+    await cancelAll(1, 255);
+    expect((await active()).map((s) => s.side)).toEqual([0, 0]);
+
+    await cancelAll(null, 1);
+    expect(await active()).toHaveLength(1);
+
+    // 0 is no limit.
+    await cancelAll(null, 0);
+    expect(await active()).toHaveLength(0);
+  });
+});
@@ -0,0 +1,195 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund, readEventQueue } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+async function setupMarket() {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+async function place(
+  pdas: ReturnType<typeof derivePDAs>,
+  user: Keypair | null,
+  priceTicks: number,
+  baseQty: number,
+  side: 0 | 1,
+  orderType: object,
+  quoteLimit = 0
+) {
+  const payer = user ? user.publicKey : provider.wallet.publicKey;
+  const ub = await fund(program, pdas.market, user);
+  const builder = program.methods
+    .placeOrder({
+      priceTicks: new anchor.BN(priceTicks),
+      baseQty: new anchor.BN(baseQty),
+      side,
+      maxSlippageTicks: new anchor.BN(1_000_000),
+      clientOrderId: new anchor.BN(0),
+      orderType,
+      quoteLimit: new anchor.BN(quoteLimit),
+      selfTradeBehavior: { decrementBoth: {} },
+      expiresAt: new anchor.BN(0),
+      displayQty: new anchor.BN(0),
+      matchLimit: 0,
+    })
+    .accounts({
+      payer,
+      ...pdas,
+      oo: ooOf(pdas.market, payer),
+      ub,
+      systemProgram: SYS_PROG,
+    });
+  return user ? builder.signers([user]).rpc() : builder.rpc();
+}
+
+async function activeSlots(oo: PublicKey) {
+  const acc = await (program.account as any).openOrdersLite.fetch(oo);
+  return acc.slots.filter((s: any) => s.active);
+}
+
+describe("order types", () => {
+  it("post-only rejects a crossing order and slide re-prices it behind the best ask", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    await place(pdas, null, 50_000, 100, 1, { limit: {} });
+
+    await expect(place(pdas, taker, 60_000, 100, 0, { postOnly: {} })).rejects.toThrow(/WouldCross/);
+
+    await place(pdas, taker, 60_000, 100, 0, { postOnlySlide: {} });
+    const [resting] = await activeSlots(ooOf(pdas.market, taker.publicKey));
+    expect(resting.priceTicks.toNumber()).toBe(40_000);
+    expect(resting.baseQty.toNumber()).toBe(100);
+  });
+
+  it("immediate-or-cancel never rests its remainder", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    await place(pdas, null, 50_000, 100, 1, { limit: {} });
+    await place(pdas, taker, 50_000, 300, 0, { immediateOrCancel: {} });
+
+    expect(await activeSlots(ooOf(pdas.market, taker.publicKey))).toHaveLength(0);
+  });
+
+  it("fill-or-kill reverts unless the whole quantity fills", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    await place(pdas, null, 50_000, 100, 1, { limit: {} });
+    await place(pdas, null, 60_000, 100, 1, { limit: {} });
+
+    await expect(place(pdas, taker, 60_000, 300, 0, { fillOrKill: {} })).rejects.toThrow(/FillOrKillNotFilled/);
+    await place(pdas, taker, 60_000, 200, 0, { fillOrKill: {} });
+
+    expect(await activeSlots(ooOf(pdas.market, taker.publicKey))).toHaveLength(0);
+  });
+
+  it("market buy stops at its quote budget and a market sell enforces its minimum", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    await place(pdas, null, 50_000, 100, 1, { limit: {} });
+    await place(pdas, null, 60_000, 100, 1, { limit: {} });
+
+    // 8M quote buys the 100 @ 50k level (5M plus a 5k fee); the rest cannot pay for a
+    // whole lot at 60k.
+    await place(pdas, taker, 0, 1_000_000, 0, { market: {} }, 8_000_000);
+    expect(await activeSlots(ooOf(pdas.market, taker.publicKey))).toHaveLength(0);
+
+    // Sell 100 into a 50k bid: 5M received, so a 5M+1 minimum reverts.
+    await place(pdas, null, 50_000, 100, 0, { limit: {} });
+    await expect(place(pdas, taker, 0, 100, 1, { market: {} }, 5_000_001)).rejects.toThrow(/QuoteLimitNotMet/);
+    await place(pdas, taker, 0, 100, 1, { market: {} }, 5_000_000);
+    expect(await activeSlots(ooOf(pdas.market, taker.publicKey))).toHaveLength(0);
+  });
+
+  it("rejects prices off the tick grid and fills above the old u32 range at full precision", async () => {
+    const pdas = await setupMarket();
+    const taker = Keypair.generate();
+    await airdropSOL(taker.publicKey, 2 * LAMPORTS_PER_SOL);
+
+    await expect(place(pdas, null, 55_000, 100, 1, { limit: {} })).rejects.toThrow(/OffTickGrid/);
+
+    const high = 5_000_000_000; // > u32::MAX, on the 10k grid
+    await place(pdas, null, high, 100, 1, { limit: {} });
+    await place(pdas, taker, high, 100, 0, { limit: {} });
+
+    const [fill] = (await readEventQueue(provider.connection, pdas.eventQueue)).events;
+    expect(fill.seqNum).toBe(0);
+    expect(fill.kind).toBe("fill");
+    expect(fill.priceTicks).toBe(high);
+  });
+});
@@ -6,6 +6,13 @@ import {
   Keypair,
 } from "@solana/web3.js";
 import type { Commitment } from "@solana/web3.js";
+import { readEventQueue } from "./helpers";
+import {
+  createMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
 
 const baseProvider = anchor.AnchorProvider.env();
 
@@ -41,7 +48,23 @@ function derivePDAs(
     [Buffer.from("kerdos_oo"), market.toBuffer(), ooOwner.toBuffer()],
     programId
   );
-  return { market, bids, asks, eventQueue, oo };
+  const [vaultAuth] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_vault_auth"), market.toBuffer()],
+    programId
+  );
+  const [baseVault] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_vault_base"), market.toBuffer()],
+    programId
+  );
+  const [quoteVault] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_vault_quote"), market.toBuffer()],
+    programId
+  );
+  const [ub] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_user"), market.toBuffer(), ooOwner.toBuffer()],
+    programId
+  );
+  return { market, bids, asks, eventQueue, oo, vaultAuth, baseVault, quoteVault, ub };
 }
 
 async function confirmTx(
@@ -53,155 +76,242 @@ async function confirmTx(
   await conn.confirmTransaction({ signature: sig, ...latest }, commitment);
 }
 
+// Program events decoded from a confirmed transaction's logs.
+async function eventsOf(program: anchor.Program, sig: string) {
+  await confirmTx(program.provider.connection, sig, "confirmed");
+  const tx = await program.provider.connection.getTransaction(sig, {
+    commitment: "confirmed",
+    maxSupportedTransactionVersion: 0,
+  });
+  const parser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+  return [...parser.parseLogs(tx?.meta?.logMessages ?? [])];
+}
+
 async function airdropSOL(conn: anchor.web3.Connection, pubkey: anchor.web3.PublicKey, lamports: number) {
   const sig = await conn.requestAirdrop(pubkey, lamports);
   await confirmTx(conn, sig, "confirmed");
 }
 
-const getBal = async (
-  conn: anchor.web3.Connection,
-  pk: anchor.web3.PublicKey
-) => BigInt(await conn.getBalance(pk, "confirmed"));
+const QUOTE_DEPOSIT = 2_000_000;
 
-describe("place/cancel without matching", () => {
-  it("locks lamports then refunds on cancel", { timeout: 30000 }, async () => {
-    const user = Keypair.generate();
+// A fresh user with its own market, vaults and a quote-only balance.
+async function setup(quoteDeposit: number, eventQueueCapacity = 512) {
+  const user = Keypair.generate();
   const userWallet = new anchor.Wallet(user);
   const userProvider = new anchor.AnchorProvider(
     baseProvider.connection,
     userWallet,
     { ...baseProvider.opts, commitment: "confirmed", preflightCommitment: "confirmed" }
   );
+  const program = new anchor.Program(
+    ((anchor.workspace as any).kerdos_markets as anchor.Program).idl,
+    userProvider
+  );
+  const conn = userProvider.connection;
 
-    const prev = anchor.getProvider();
-    anchor.setProvider(userProvider);
-    try {
-      const program = (anchor.workspace as any)
-        .kerdos_markets as anchor.Program;
-
-      await airdropSOL(userProvider.connection, user.publicKey, 2 * LAMPORTS_PER_SOL);
-      console.log("airdrop ok");
-
-      const baseMint = Keypair.generate().publicKey;
-      const quoteMint = Keypair.generate().publicKey;
-
-      const { market, bids, asks, eventQueue, oo } = derivePDAs(
-        program.programId,
-        baseMint,
-        quoteMint,
-        user.publicKey
-      );
-
-      const initParams = {
-        baseMint,
-        quoteMint,
-        bidsCapacity: 1024,
-        asksCapacity: 1024,
-        eventQueueCapacity: 512,
-        tickSize: new anchor.BN(10_000),
-        minBaseQty: new anchor.BN(100),
-        feesBps: 10,
-      };
-
-      const cuIx = computeLimitIx(1_400_000);
-
-      const initSig = await program.methods
-        .initMarket(initParams)
-        .preInstructions([cuIx])
-        .accounts({
-          payer: user.publicKey,
-          authority: user.publicKey,
-          baseMint,
-          quoteMint,
-          market,
-          bids,
-          asks,
-          eventQueue,
-          systemProgram: SYS_PROG,
-        })
-        .rpc();
-      await confirmTx(userProvider.connection, initSig, "confirmed");
-      console.log("initMarket ok", initSig);
-
-      const preUser = await getBal(userProvider.connection, user.publicKey);
-
-      const bnLamportsPerSol = BigInt(LAMPORTS_PER_SOL);
-      const lock = (bnLamportsPerSol * 5n) / 100n; // 0.05 SOL
-
-      const placeSig = await program.methods
-        .placeOrder({
-          priceTicks: new anchor.BN(10_000),
-          baseQty: new anchor.BN(100),
-          side: 0, // bid
-          lockLamports: new anchor.BN(Number(lock)),
-          maxSlippageTicks: new anchor.BN(0),
-        })
-        .accounts({
-          payer: user.publicKey,
-          market,
-          bids,
-          asks,
-          eventQueue,
-          oo,
-          systemProgram: SYS_PROG,
-        })
-        .rpc();
-      await confirmTx(userProvider.connection, placeSig, "confirmed");
-      console.log("placeOrder ok", placeSig);
+  await airdropSOL(conn, user.publicKey, 2 * LAMPORTS_PER_SOL);
+
+  const baseMint = await createMint(conn, user, user.publicKey, null, 6);
+  const quoteMint = await createMint(conn, user, user.publicKey, null, 6);
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint, user.publicKey);
+  const { market, bids, asks, eventQueue, vaultAuth, baseVault, quoteVault, ub } = pdas;
 
-      const midUser = await getBal(userProvider.connection, user.publicKey);
+  const initSig = await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: user.publicKey,
+      authority: user.publicKey,
+      baseMint,
+      quoteMint,
+      market,
+      bids,
+      asks,
+      eventQueue,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  await confirmTx(conn, initSig, "confirmed");
 
-      const SPACE_OO = 104;
-      const rentOO = BigInt(
-        await userProvider.connection.getMinimumBalanceForRentExemption(SPACE_OO)
-      );
-      const ooBalBefore = await getBal(userProvider.connection, oo);
+  await program.methods
+    .initVaults()
+    .accounts({
+      payer: user.publicKey,
+      authority: user.publicKey,
+      market,
+      baseMint,
+      quoteMint,
+      vaultAuth,
+      baseVault,
+      quoteVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  await program.methods
+    .initUserBalance()
+    .accounts({ payer: user.publicKey, user: user.publicKey, market, ub, systemProgram: SYS_PROG })
+    .rpc();
 
-      expect(ooBalBefore).toBe(rentOO + lock);
-      expect(preUser > midUser).toBe(true);
+  const quoteAta = await getOrCreateAssociatedTokenAccount(conn, user, quoteMint, user.publicKey);
+  await mintTo(conn, user, quoteMint, quoteAta.address, user, quoteDeposit);
+  await program.methods
+    .depositQuote(new anchor.BN(quoteDeposit))
+    .accounts({
+      user: user.publicKey,
+      market,
+      ub,
+      userQuoteAta: quoteAta.address,
+      quoteVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    .rpc();
 
-      const ooAccAfterPlace = await (program.account as any).openOrdersLite.fetch(oo);
-      expect(ooAccAfterPlace.active).toBe(true);
-      expect(BigInt(ooAccAfterPlace.lockedLamports.toString())).toBe(lock);
+  return { user, program, ...pdas };
+}
+
+function placeBid(ctx: Awaited<ReturnType<typeof setup>>) {
+  const { user, program, market, bids, asks, eventQueue, oo, ub } = ctx;
+  return program.methods
+    .placeOrder({
+      priceTicks: new anchor.BN(10_000),
+      baseQty: new anchor.BN(100),
+      side: 0, // bid
+      maxSlippageTicks: new anchor.BN(0),
+      clientOrderId: new anchor.BN(0),
+      orderType: { limit: {} },
+      quoteLimit: new anchor.BN(0),
+      selfTradeBehavior: { decrementBoth: {} },
+      expiresAt: new anchor.BN(0),
+      displayQty: new anchor.BN(0),
+      matchLimit: 0,
+    })
+    .accounts({
+      payer: user.publicKey,
+      market,
+      bids,
+      asks,
+      eventQueue,
+      oo,
+      ub,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+}
 
+// 100 lots at 10_000 is 1_000_000 quote, plus a 10 bps fee allowance of 10 per lot.
+const BID_COLLATERAL = 1_001_000;
+
+describe("place/cancel without matching", () => {
+  it("locks quote collateral then releases it on cancel", { timeout: 30000 }, async () => {
+    const ctx = await setup(QUOTE_DEPOSIT);
+    const { user, program, market, eventQueue, oo, ub } = ctx;
+
+    const placeSig = await placeBid(ctx);
+    await confirmTx(program.provider.connection, placeSig, "confirmed");
+
+    const ooAccAfterPlace = await (program.account as any).openOrdersLite.fetch(oo);
+    const placed = ooAccAfterPlace.slots[0];
+    expect(placed.active).toBe(true);
+
+    const ubAfterPlace = await (program.account as any).userBalance.fetch(ub);
+    expect(ubAfterPlace.quoteLocked.toNumber()).toBe(BID_COLLATERAL);
+    expect(ubAfterPlace.quoteFree.toNumber()).toBe(QUOTE_DEPOSIT - BID_COLLATERAL);
+
+    const cancelSig = await program.methods
+      .cancelOrder(placed.orderId)
+      .accounts({
+        payer: user.publicKey,
+        market,
+        oo,
+        ub,
+      })
+      .rpc();
+    await confirmTx(program.provider.connection, cancelSig, "confirmed");
+
+    const ooAccAfterCancel = await (program.account as any).openOrdersLite.fetch(oo);
+    expect(ooAccAfterCancel.slots[0].active).toBe(false);
+
+    const ubAfterCancel = await (program.account as any).userBalance.fetch(ub);
+    expect(ubAfterCancel.quoteLocked.toNumber()).toBe(0);
+    expect(ubAfterCancel.quoteFree.toNumber()).toBe(QUOTE_DEPOSIT);
+
+    // The cancel is recorded as an out event.
+    const { events } = await readEventQueue(program.provider.connection, eventQueue);
+    expect(events).toHaveLength(1);
+    expect(events[0].kind).toBe("out");
+    expect(events[0].reason).toBe("cancelled");
+  });
+
+  it("logs placement and cancellation as numbered events", { timeout: 30000 }, async () => {
+    const ctx = await setup(QUOTE_DEPOSIT);
+    const { user, program, market, oo, ub } = ctx;
+
+    const [placed] = await eventsOf(program, await placeBid(ctx));
+    expect(placed.name).toBe("orderPlaced");
+    expect(placed.data.market.equals(market)).toBe(true);
+    expect(placed.data.owner.equals(user.publicKey)).toBe(true);
+    expect(placed.data.side).toBe(0);
+    expect(placed.data.priceTicks.toNumber()).toBe(10_000);
+    expect(placed.data.restingQty.toNumber()).toBe(100);
+    // init_market and the deposit took 0 and 1.
+    expect(placed.data.seqNum.toNumber()).toBe(2);
+
+    const cancelSig = await program.methods
+      .cancelOrder(placed.data.orderId)
+      .accounts({ payer: user.publicKey, market, oo, ub })
+      .rpc();
+    const [cancelled] = await eventsOf(program, cancelSig);
+    expect(cancelled.name).toBe("orderCancelled");
+    expect(cancelled.data.owner.equals(user.publicKey)).toBe(true);
+    expect(cancelled.data.ownerOo.equals(oo)).toBe(true);
+    expect(cancelled.data.orderId.eq(placed.data.orderId)).toBe(true);
+    expect(cancelled.data.baseQty.toNumber()).toBe(100);
+    expect(cancelled.data.reason).toEqual({ cancelled: {} });
+    expect(cancelled.data.seqNum.toNumber()).toBe(3);
+  });
+
+  it("still cancels when the event queue is full", { timeout: 60000 }, async () => {
+    const ctx = await setup(QUOTE_DEPOSIT, 2);
+    const { user, program, market, eventQueue, oo, ub } = ctx;
+
+    // The third cancel finds both slots taken by the first two records.
+    for (let i = 0; i < 3; i++) {
+      await confirmTx(program.provider.connection, await placeBid(ctx), "confirmed");
+      const slot = (await (program.account as any).openOrdersLite.fetch(oo)).slots[0];
       const cancelSig = await program.methods
-        .cancelOrder()
-        .accounts({
-          payer: user.publicKey,
-          market,
-          oo,
-        })
+        .cancelOrder(slot.orderId)
+        .accounts({ payer: user.publicKey, market, oo, ub })
         .rpc();
-      await confirmTx(userProvider.connection, cancelSig, "confirmed");
-      console.log("cancelOrder ok", cancelSig);
-
-      const postUser = await getBal(userProvider.connection, user.publicKey);
-      const ooBalAfter = await getBal(userProvider.connection, oo);
-      const ooAccAfterCancel = await (program.account as any).openOrdersLite.fetch(oo);
-
-      expect(ooBalAfter).toBe(rentOO);
-      expect(ooAccAfterCancel.active).toBe(false);
-      expect(BigInt(ooAccAfterCancel.lockedLamports.toString())).toBe(0n);
-
-      const refunded = ooBalBefore - ooBalAfter;
-      expect(refunded).toBe(lock);
-
-      const actualFee = (midUser + refunded) - postUser;
-      expect(actualFee >= 0n).toBe(true);
-
-      const cancelTx = await userProvider.connection.getTransaction(cancelSig, {
-        maxSupportedTransactionVersion: 0,
-        commitment: "finalized",
-      });
-      if (cancelTx?.meta?.fee !== undefined) {
-        const rpcBase = BigInt(cancelTx.meta.fee);
-        const rpcPrior = BigInt((cancelTx.meta as any)?.prioritizationFee ?? 0);
-        expect(actualFee >= rpcBase).toBe(true);
-        const DIFF_CAP = 10_000_000n; 
-        expect(actualFee - (rpcBase + rpcPrior) <= DIFF_CAP).toBe(true);
-      }
-    } finally {
-      anchor.setProvider(prev);
+      await confirmTx(program.provider.connection, cancelSig, "confirmed");
     }
+
+    const ubAcc = await (program.account as any).userBalance.fetch(ub);
+    expect(ubAcc.quoteLocked.toNumber()).toBe(0);
+    expect(ubAcc.quoteFree.toNumber()).toBe(QUOTE_DEPOSIT);
+    const { events } = await readEventQueue(program.provider.connection, eventQueue);
+    expect(events).toHaveLength(2);
+  });
+
+  it("rejects an order the free balance cannot cover", { timeout: 30000 }, async () => {
+    const ctx = await setup(BID_COLLATERAL - 1);
+    await expect(placeBid(ctx)).rejects.toThrow(/InsufficientFunds/);
+
+    const ub = await (ctx.program.account as any).userBalance.fetch(ctx.ub);
+    expect(ub.quoteLocked.toNumber()).toBe(0);
   });
 });
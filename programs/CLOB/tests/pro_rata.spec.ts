@@ -0,0 +1,124 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund, readFills } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function airdropSOL(pubkey: PublicKey, lamports: number) {
+  const sig = await provider.connection.requestAirdrop(pubkey, lamports);
+  await provider.connection.confirmTransaction(sig);
+}
+
+async function setupMarket() {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(10),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { proRata: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+async function place(pdas: ReturnType<typeof derivePDAs>, user: Keypair, side: 0 | 1, baseQty: number) {
+  return program.methods
+    .placeOrder({
+      priceTicks: new anchor.BN(50_000),
+      baseQty: new anchor.BN(baseQty),
+      side,
+      maxSlippageTicks: new anchor.BN(0),
+      clientOrderId: new anchor.BN(0),
+      orderType: { limit: {} },
+      quoteLimit: new anchor.BN(0),
+      selfTradeBehavior: { decrementBoth: {} },
+      expiresAt: new anchor.BN(0),
+      displayQty: new anchor.BN(0),
+      matchLimit: 0,
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({ payer: user.publicKey, ...pdas, oo: ooOf(pdas.market, user.publicKey), ub: await fund(program, pdas.market, user), systemProgram: SYS_PROG })
+    .signers([user])
+    .rpc();
+}
+
+// Fill events in queue order as (maker OO, base qty).
+async function fills(pdas: ReturnType<typeof derivePDAs>) {
+  return (await readFills(provider.connection, pdas.eventQueue)).map((f) => [f.makerOo.toBase58(), f.baseQty]);
+}
+
+describe("pro-rata engine", () => {
+  it("splits a level by maker size and hands rounding leftovers to the oldest maker", async () => {
+    const pdas = await setupMarket();
+    const makers = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    const taker = Keypair.generate();
+    for (const k of [...makers, taker]) await airdropSOL(k.publicKey, LAMPORTS_PER_SOL);
+
+    await place(pdas, makers[0], 1, 100);
+    await place(pdas, makers[1], 1, 100);
+    await place(pdas, makers[2], 1, 200);
+
+    // 25 steps of 10 over 10/10/20 steps: 6.25, 6.25, 12.5 -> 6, 6, 12, plus one to the oldest.
+    await place(pdas, taker, 0, 250);
+    const oo = makers.map((m) => ooOf(pdas.market, m.publicKey).toBase58());
+    expect(await fills(pdas)).toEqual([
+      [oo[0], 70],
+      [oo[1], 60],
+      [oo[2], 120],
+    ]);
+  });
+});
@@ -0,0 +1,156 @@
+import { describe, it, expect } from "vitest";
+import * as anchor from "@coral-xyz/anchor";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund, readEventQueue } from "./helpers";
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
+
+const SYS_PROG = anchor.web3.SystemProgram.programId;
+
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
+function computeLimitIx(units: number) {
+  return ComputeBudgetProgram.setComputeUnitLimit({ units });
+}
+
+function derivePDAs(programId: PublicKey, baseMint: PublicKey, quoteMint: PublicKey) {
+  const [market] = PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_market"), baseMint.toBuffer(), quoteMint.toBuffer()],
+    programId
+  );
+  const [bids] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_bids"), market.toBuffer()], programId);
+  const [asks] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_asks"), market.toBuffer()], programId);
+  const [eventQueue] = PublicKey.findProgramAddressSync([Buffer.from("kerdos_eventq"), market.toBuffer()], programId);
+  return { market, bids, asks, eventQueue };
+}
+
+async function setupMarket() {
+  const baseMint = await newMint();
+  const quoteMint = await newMint();
+  const pdas = derivePDAs(program.programId, baseMint, quoteMint);
+  await program.methods
+    .initMarket({
+      baseMint,
+      quoteMint,
+      bidsCapacity: 1024,
+      asksCapacity: 1024,
+      eventQueueCapacity: 512,
+      tickSize: new anchor.BN(10_000),
+      minBaseQty: new anchor.BN(100),
+      makerFeeBps: 0,
+      takerFeeBps: 10,
+      baseLotSize: new anchor.BN(1),
+      quoteLotSize: new anchor.BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new anchor.BN(0),
+    })
+    .preInstructions([computeLimitIx(1_400_000)])
+    .accounts({
+      payer: provider.wallet.publicKey,
+      authority: provider.wallet.publicKey,
+      baseMint,
+      quoteMint,
+      ...pdas,
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+  return pdas;
+}
+
+function ooOf(market: PublicKey, user: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("kerdos_oo"), market.toBuffer(), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+async function place(
+  pdas: ReturnType<typeof derivePDAs>,
+  priceTicks: number,
+  baseQty: number,
+  side: 0 | 1,
+  selfTradeBehavior: object
+) {
+  return program.methods
+    .placeOrder({
+      priceTicks: new anchor.BN(priceTicks),
+      baseQty: new anchor.BN(baseQty),
+      side,
+      maxSlippageTicks: new anchor.BN(1_000_000),
+      clientOrderId: new anchor.BN(0),
+      orderType: { limit: {} },
+      quoteLimit: new anchor.BN(0),
+      selfTradeBehavior,
+      expiresAt: new anchor.BN(0),
+      displayQty: new anchor.BN(0),
+      matchLimit: 0,
+    })
+    .accounts({
+      payer: provider.wallet.publicKey,
+      ...pdas,
+      oo: ooOf(pdas.market, provider.wallet.publicKey),
+      ub: await fund(program, pdas.market, null),
+      systemProgram: SYS_PROG,
+    })
+    .rpc();
+}
+
+async function activeSlots(pdas: ReturnType<typeof derivePDAs>) {
+  const oo = ooOf(pdas.market, provider.wallet.publicKey);
+  const acc = await (program.account as any).openOrdersLite.fetch(oo);
+  return acc.slots.filter((s: any) => s.active);
+}
+
+async function eventCount(pdas: ReturnType<typeof derivePDAs>) {
+  return (await readEventQueue(provider.connection, pdas.eventQueue)).count;
+}
+
+describe("self-trade prevention", () => {
+  it("cancel maker removes the resting order and rests the taker", async () => {
+    const pdas = await setupMarket();
+    await place(pdas, 50_000, 100, 1, { cancelMaker: {} });
+    await place(pdas, 50_000, 100, 0, { cancelMaker: {} });
+
+    const active = await activeSlots(pdas);
+    expect(active).toHaveLength(1);
+    expect(active[0].side).toBe(0);
+    // Only the out event recording the cancelled maker.
+    expect(await eventCount(pdas)).toBe(1);
+  });
+
+  it("cancel taker keeps the resting order and drops the incoming one", async () => {
+    const pdas = await setupMarket();
+    await place(pdas, 50_000, 100, 1, { cancelTaker: {} });
+    await place(pdas, 50_000, 100, 0, { cancelTaker: {} });
+
+    const active = await activeSlots(pdas);
+    expect(active).toHaveLength(1);
+    expect(active[0].side).toBe(1);
+    expect(await eventCount(pdas)).toBe(0);
+  });
+
+  it("decrement both shrinks the two orders without a fill", async () => {
+    const pdas = await setupMarket();
+    await place(pdas, 50_000, 300, 1, { decrementBoth: {} });
+    await place(pdas, 50_000, 100, 0, { decrementBoth: {} });
+
+    const active = await activeSlots(pdas);
+    expect(active).toHaveLength(1);
+    expect(active[0].side).toBe(1);
+    expect(active[0].baseQty.toNumber()).toBe(200);
+    expect(await eventCount(pdas)).toBe(0);
+  });
+
+  it("abort fails the transaction", async () => {
+    const pdas = await setupMarket();
+    await place(pdas, 50_000, 100, 1, { abortTransaction: {} });
+    await expect(place(pdas, 50_000, 100, 0, { abortTransaction: {} })).rejects.toThrow(/SelfTrade/);
+    expect(await activeSlots(pdas)).toHaveLength(1);
+  });
+});
@@ -167,7 +167,12 @@ describe("settle_events", () => {
         eventQueueCapacity: 512,
         tickSize: new anchor.BN(10_000),
         minBaseQty: new anchor.BN(100),
-        feesBps: 0,
+        makerFeeBps: 0,
+        takerFeeBps: 0,
+        baseLotSize: new anchor.BN(1),
+        quoteLotSize: new anchor.BN(1),
+        engineKind: { priceTime: {} },
+        auctionIntervalSlots: new anchor.BN(0),
       };
 
       const cuIx = computeLimitIx(1_200_000);
@@ -262,8 +267,14 @@ describe("settle_events", () => {
           priceTicks: new anchor.BN(10_000),
           baseQty: new anchor.BN(100),
           side: 1,
-          lockLamports: new anchor.BN(1000),
           maxSlippageTicks: new anchor.BN(0),
+          clientOrderId: new anchor.BN(0),
+          orderType: { limit: {} },
+          quoteLimit: new anchor.BN(0),
+          selfTradeBehavior: { decrementBoth: {} },
+          expiresAt: new anchor.BN(0),
+          displayQty: new anchor.BN(0),
+          matchLimit: 0,
         })
         .accounts({
           payer: maker.publicKey,
@@ -272,6 +283,7 @@ describe("settle_events", () => {
           asks,
           eventQueue,
           oo: makerOo,
+          ub: makerUb,
           systemProgram: SYS_PROG,
         })
         .signers([maker])
@@ -282,8 +294,14 @@ describe("settle_events", () => {
           priceTicks: new anchor.BN(10_000),
           baseQty: new anchor.BN(100),
           side: 0,
-          lockLamports: new anchor.BN(1000),
           maxSlippageTicks: new anchor.BN(0),
+          clientOrderId: new anchor.BN(0),
+          orderType: { limit: {} },
+          quoteLimit: new anchor.BN(0),
+          selfTradeBehavior: { decrementBoth: {} },
+          expiresAt: new anchor.BN(0),
+          displayQty: new anchor.BN(0),
+          matchLimit: 0,
         })
         .accounts({
           payer: taker.publicKey,
@@ -292,20 +310,26 @@ describe("settle_events", () => {
           asks,
           eventQueue,
           oo: takerOo,
+          ub: takerUb,
           systemProgram: SYS_PROG,
         })
         .signers([taker])
         .rpc();
 
+      // The maker was filled in full: its slot stays active until the out event settles.
+      const makerSlots = async () =>
+        ((await (workspaceProgram.account as any).openOrdersLite.fetch(makerOo)).slots as any[]).filter((s) => s.active);
+      expect(await makerSlots()).toHaveLength(1);
+
       await workspaceProgram.methods
         .settleEvents(1000)
         .accounts({
-          authority: provider.wallet.publicKey,
+          cranker: provider.wallet.publicKey,
           market,
           eventQueue,
         })
         .remainingAccounts([
-          { pubkey: makerOo, isSigner: false, isWritable: false },
+          { pubkey: makerOo, isSigner: false, isWritable: true },
           { pubkey: takerOo, isSigner: false, isWritable: false },
           { pubkey: makerUb, isSigner: false, isWritable: true },
           { pubkey: takerUb, isSigner: false, isWritable: true },
@@ -327,6 +351,9 @@ describe("settle_events", () => {
       expect(BigInt(takerUbAfter.quoteFree.toString())).toBe(
         BigInt(takerUbBefore.quoteFree.toString()) - 1_000_000n
       );
+      expect(makerUbAfter.baseLocked.toNumber()).toBe(0);
+      expect(takerUbAfter.quoteLocked.toNumber()).toBe(0);
+      expect(await makerSlots()).toHaveLength(0);
     },
     60_000
   );
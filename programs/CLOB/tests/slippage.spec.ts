@@ -1,6 +1,8 @@
 import { describe, it, expect } from "vitest";
 import * as anchor from "@coral-xyz/anchor";
-import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair } from "@solana/web3.js";
+import { createMint } from "@solana/spl-token";
+import { LAMPORTS_PER_SOL, ComputeBudgetProgram, Keypair, PublicKey } from "@solana/web3.js";
+import { fund } from "./helpers";
 
 const provider = anchor.AnchorProvider.env();
 anchor.setProvider(provider);
@@ -8,6 +10,11 @@ const program = (anchor.workspace as any).kerdos_markets as anchor.Program;
 
 const SYS_PROG = anchor.web3.SystemProgram.programId;
 
+async function newMint() {
+  const payer = (provider.wallet as any).payer as Keypair;
+  return createMint(provider.connection, payer, payer.publicKey, null, 6);
+}
+
 function computeLimitIx(units: number) {
   return ComputeBudgetProgram.setComputeUnitLimit({ units });
 }
@@ -43,8 +50,8 @@ async function airdropSOL(pubkey: anchor.web3.PublicKey, lamports: number) {
 
 describe("slippage bound", () => {
   it("with 0 slippage, taker does not cross and becomes maker; with tolerance, it crosses", async () => {
-    const baseMint = Keypair.generate().publicKey;
-    const quoteMint = Keypair.generate().publicKey;
+    const baseMint = await newMint();
+    const quoteMint = await newMint();
     const { market, bids, asks, eventQueue } = derivePDAs(
       program.programId,
       baseMint,
@@ -62,9 +69,14 @@ describe("slippage bound", () => {
         bidsCapacity: 1024,
         asksCapacity: 1024,
         eventQueueCapacity: 512,
-        tickSize: new anchor.BN(10_000),
+        tickSize: new anchor.BN(1_000),
         minBaseQty: new anchor.BN(100),
-        feesBps: 10,
+        makerFeeBps: 0,
+        takerFeeBps: 10,
+        baseLotSize: new anchor.BN(1),
+        quoteLotSize: new anchor.BN(1),
+        engineKind: { priceTime: {} },
+        auctionIntervalSlots: new anchor.BN(0),
       })
       .preInstructions([computeLimitIx(1_400_000)])
       .accounts({
@@ -85,14 +97,21 @@ describe("slippage bound", () => {
       [Buffer.from("kerdos_oo"), market.toBuffer(), provider.wallet.publicKey.toBuffer()],
       program.programId
     );
-    const lock = Math.floor(0.03 * LAMPORTS_PER_SOL);
+    const ubMaker = await fund(program, market, null);
+    const ubTaker = await fund(program, market, taker);
     await program.methods
       .placeOrder({
         priceTicks: new anchor.BN(10_000), // ask 10k
         baseQty: new anchor.BN(100),
         side: 1,
-        lockLamports: new anchor.BN(lock),
         maxSlippageTicks: new anchor.BN(0),
+        clientOrderId: new anchor.BN(0),
+        orderType: { limit: {} },
+        quoteLimit: new anchor.BN(0),
+        selfTradeBehavior: { decrementBoth: {} },
+        expiresAt: new anchor.BN(0),
+        displayQty: new anchor.BN(0),
+        matchLimit: 0,
       })
       .accounts({
         payer: provider.wallet.publicKey,
@@ -101,6 +120,7 @@ describe("slippage bound", () => {
         asks,
         eventQueue,
         oo: ooMaker,
+        ub: ubMaker,
         systemProgram: SYS_PROG,
       })
       .rpc();
@@ -115,8 +135,14 @@ describe("slippage bound", () => {
         priceTicks: new anchor.BN(12_000),
         baseQty: new anchor.BN(100),
         side: 0,
-        lockLamports: new anchor.BN(lock),
         maxSlippageTicks: new anchor.BN(0),
+        clientOrderId: new anchor.BN(0),
+        orderType: { limit: {} },
+        quoteLimit: new anchor.BN(0),
+        selfTradeBehavior: { decrementBoth: {} },
+        expiresAt: new anchor.BN(0),
+        displayQty: new anchor.BN(0),
+        matchLimit: 0,
       })
       .accounts({
         payer: taker.publicKey,
@@ -125,17 +151,18 @@ describe("slippage bound", () => {
         asks,
         eventQueue,
         oo: ooTaker,
+        ub: ubTaker,
         systemProgram: SYS_PROG,
       })
       .signers([taker])
       .rpc();
 
     let takerOo = await (program.account as any).openOrdersLite.fetch(ooTaker);
-    expect(takerOo.active).toBe(true); // became maker
+    expect(takerOo.slots[0].active).toBe(true); // became maker
 
-    // cancel to reuse the same OO PDA
+    // cancel so the resting bid does not stay on the book
     await program.methods
-      .cancelOrder()
+      .cancelOrder(takerOo.slots[0].orderId)
       .accounts({
         payer: taker.publicKey,
         market,
@@ -150,8 +177,14 @@ describe("slippage bound", () => {
         priceTicks: new anchor.BN(12_000),
         baseQty: new anchor.BN(100),
         side: 0,
-        lockLamports: new anchor.BN(lock),
         maxSlippageTicks: new anchor.BN(2_000),
+        clientOrderId: new anchor.BN(0),
+        orderType: { limit: {} },
+        quoteLimit: new anchor.BN(0),
+        selfTradeBehavior: { decrementBoth: {} },
+        expiresAt: new anchor.BN(0),
+        displayQty: new anchor.BN(0),
+        matchLimit: 0,
       })
       .accounts({
         payer: taker.publicKey,
@@ -160,12 +193,13 @@ describe("slippage bound", () => {
         asks,
         eventQueue,
         oo: ooTaker,
+        ub: ubTaker,
         systemProgram: SYS_PROG,
       })
       .signers([taker])
       .rpc();
 
     takerOo = await (program.account as any).openOrdersLite.fetch(ooTaker);
-    expect(takerOo.active).toBe(false);
+    expect(takerOo.slots.some((s: any) => s.active)).toBe(false);
   });
 });
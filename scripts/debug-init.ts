@@ -35,7 +35,12 @@ async function main() {
       eventQueueCapacity: evqCapacity,
       tickSize: new BN(10_000),
       minBaseQty: new BN(100),
-      feesBps: 0,
+      makerFeeBps: 0,
+      takerFeeBps: 0,
+      baseLotSize: new BN(1),
+      quoteLotSize: new BN(1),
+      engineKind: { priceTime: {} },
+      auctionIntervalSlots: new BN(0),
     })
     .accounts({
       payer: provider.wallet.publicKey,
@@ -72,7 +72,7 @@ async function main() {
         eventQueueCapacity: 512,
         tickSize: new BN(d.tickSize),
         minBaseQty: new BN(d.minBaseQty),
-        feesBps: d.feesBps,
+        takerFeeBps: d.feesBps,
         pre: 1_200_000,
       });
       console.log(`${d.slug} (${label}): initMarket ${sigMarket}`);
@@ -75,7 +75,7 @@ async function main() {
       eventQueueCapacity: 512,
       tickSize: new BN(m.tickSize),
       minBaseQty: new BN(m.minBaseQty),
-      feesBps: m.feesBps,
+      takerFeeBps: m.feesBps,
       pre: 1_200_000,
     });
     console.log(`${m.slug}: initMarket ${sig1}`);